@@ -0,0 +1,32 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use place_ty_compute::{Field, Local, Type, place_expr};
+
+fn maybe_uninit(inner: &Type) -> Type {
+    Type::wrapper("MaybeUninit", inner)
+}
+
+fn bench_clone(c: &mut Criterion) {
+    // The place of the `multi_wrapper2` test.
+    let z = Type::new_generic("Z");
+    let mbz = maybe_uninit(&z);
+    let mb2z = maybe_uninit(&mbz);
+    let mb3z = maybe_uninit(&mb2z);
+    let y = Type::new_struct("Y", [Field::new("z", mbz.clone())]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    let p = Local::new(Type::new_ref(e), "p");
+    let mut place = place_expr!(@%mb3z @%mb2z @%mbz *(*(*(*p).x).y).z);
+    place.compute_ty().unwrap();
+
+    c.bench_function("clone multi_wrapper2 place", |b| {
+        b.iter(|| black_box(&place).clone())
+    });
+    c.bench_function("clone multi_wrapper2 type", |b| {
+        b.iter(|| black_box(&mb3z).clone())
+    });
+}
+
+criterion_group!(benches, bench_clone);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use place_ty_compute::{Field, Local, PlaceExpr, Type};
+
+const DEPTH: usize = 20;
+
+/// Returns `&&...&Struct` with `DEPTH` references.
+fn deref_chain() -> Type {
+    let field = Type::new_generic("Field");
+    let mut ty = Type::new_struct("Struct", [Field::new("field", field)]);
+    for _ in 0..DEPTH {
+        ty = Type::new_ref(ty);
+    }
+    ty
+}
+
+fn bench_deref_chain(c: &mut Criterion) {
+    let ty = deref_chain();
+    // Every iteration uses a fresh local, so the place is not in the type cache yet.
+    c.bench_function("auto deref 20 references", |b| {
+        b.iter_batched(
+            || {
+                PlaceExpr::FieldAccess(
+                    Box::new(PlaceExpr::LocalVar(Local::new(ty.clone(), "p"))),
+                    "field".to_string(),
+                )
+            },
+            |mut place| black_box(place.compute_ty().unwrap()),
+            BatchSize::SmallInput,
+        )
+    });
+    c.bench_function("explicit deref 20 references", |b| {
+        b.iter_batched(
+            || {
+                let mut place = PlaceExpr::LocalVar(Local::new(ty.clone(), "p"));
+                for _ in 0..DEPTH {
+                    place = PlaceExpr::Deref(Box::new(place));
+                }
+                place
+            },
+            |mut place| black_box(place.compute_ty().unwrap()),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_deref_chain);
+criterion_main!(benches);
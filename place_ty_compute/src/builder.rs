@@ -0,0 +1,258 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::{self, Display};
+
+use crate::{Field, Map, Type, TypeKind, WrapFn};
+
+/// A builder for [`Type`], created by [`Type::builder`].
+///
+/// This is a less error-prone alternative to [`Type::new`], since every part of the type is named
+/// and nonsensical combinations are rejected by [`TypeBuilder::build`].
+#[derive(Default)]
+pub struct TypeBuilder {
+    target: Option<Type>,
+    element: Option<Type>,
+    wrapper: Option<(String, WrapFn)>,
+    fields: Vec<Field>,
+    display: Option<String>,
+    idempotent: bool,
+    transparent: bool,
+    deref_when: Option<fn(&Type) -> bool>,
+    explanation: Option<String>,
+}
+
+/// An error returned by [`TypeBuilder::build`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum TypeBuildError {
+    /// Both a `HasPlace` target and an element type were set, so the type is neither a pointer
+    /// nor a slice.
+    TargetAndElement,
+    /// A place wrapper was set without a `HasPlace` target.
+    WrapperWithoutTarget,
+    /// The type was marked as deref-transparent without setting a wrapper.
+    TransparentWithoutWrapper,
+    /// A condition for dereferencing was set without a `HasPlace` target.
+    DerefConditionWithoutTarget,
+    /// The function wrapping a type in the place wrapper `name` returned a type that is not a
+    /// place wrapper named `name`, but one named `found` or no wrapper at all.
+    WrapperNameMismatch { name: String, found: Option<String> },
+    /// The same field was declared twice.
+    DuplicateField(String),
+    /// No display string was set.
+    MissingDisplay,
+}
+
+impl Display for TypeBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TargetAndElement => {
+                write!(
+                    f,
+                    "a type cannot have both a `HasPlace` target and an element type"
+                )
+            }
+            Self::WrapperWithoutTarget => {
+                write!(f, "a place wrapper needs to have a `HasPlace` target")
+            }
+            Self::TransparentWithoutWrapper => {
+                write!(f, "only a wrapper can be deref-transparent")
+            }
+            Self::DerefConditionWithoutTarget => {
+                write!(
+                    f,
+                    "only a type with a `HasPlace` target can be dereferenced"
+                )
+            }
+            Self::WrapperNameMismatch { name, found } => match found {
+                Some(found) => write!(f, "the wrapper `{name}` wraps types in `{found}` instead"),
+                None => write!(f, "the wrapper `{name}` wraps types in a non-wrapper type"),
+            },
+            Self::DuplicateField(field) => write!(f, "the field `{field}` is declared twice"),
+            Self::MissingDisplay => write!(f, "the type has no display string"),
+        }
+    }
+}
+
+impl core::error::Error for TypeBuildError {}
+
+impl Type {
+    pub fn builder() -> TypeBuilder {
+        TypeBuilder::default()
+    }
+}
+
+impl TypeBuilder {
+    /// Sets the `HasPlace` target, i.e. the type of `*p` for `p: Self`.
+    pub fn target(mut self, target: Type) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Sets the element type, i.e. the type of `p[i]` for `p: Self`.
+    pub fn element(mut self, element: Type) -> Self {
+        self.element = Some(element);
+        self
+    }
+
+    /// Makes the type a place wrapper named `name`, where `wrap` wraps a type in it.
+    ///
+    /// [`Self::build`] checks that `wrap` returns a wrapper named `name` for the target.
+    pub fn wrapper(
+        mut self,
+        name: &str,
+        wrap: impl Fn(Type) -> Type + Send + Sync + 'static,
+    ) -> Self {
+        self.wrapper = Some((name.to_string(), Box::new(wrap)));
+        self
+    }
+
+    /// Marks the place wrapper as idempotent, see [`Type::idempotent_wrapper`].
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /// Makes the wrapper deref-transparent like `ManuallyDrop<T>` instead of lifting
+    /// projections, see [`Type::transparent_wrapper`].
+    pub fn transparent(mut self) -> Self {
+        self.transparent = true;
+        self
+    }
+
+    /// Makes the type implement `PlaceDeref` only if `condition` holds for its target, just like
+    /// `Pin<P>` only implements `Deref` if `P: Deref`.
+    ///
+    /// [`PlaceExpr::compute_ty_checked`] rejects dereferences of the type for which the condition
+    /// does not hold, [`PlaceExpr::compute_ty`] ignores the condition.
+    ///
+    /// [`PlaceExpr::compute_ty_checked`]: crate::PlaceExpr::compute_ty_checked
+    /// [`PlaceExpr::compute_ty`]: crate::PlaceExpr::compute_ty
+    pub fn deref_when(mut self, condition: fn(&Type) -> bool) -> Self {
+        self.deref_when = Some(condition);
+        self
+    }
+
+    /// Attaches a note shown in the context wherever the type is dereferenced or the wrapper is
+    /// lifted, see [`Type::with_explanation`].
+    pub fn explanation(mut self, explanation: impl Into<String>) -> Self {
+        self.explanation = Some(explanation.into());
+        self
+    }
+
+    /// Adds a field named `name`.
+    pub fn field(mut self, name: &str, ty: Type) -> Self {
+        self.fields.push(Field::new(name, ty));
+        self
+    }
+
+    /// Sets the string used when displaying the type.
+    pub fn display(mut self, display: impl Into<String>) -> Self {
+        self.display = Some(display.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Type, TypeBuildError> {
+        if self.target.is_some() && self.element.is_some() {
+            return Err(TypeBuildError::TargetAndElement);
+        }
+        if self.wrapper.is_some() && self.target.is_none() {
+            return Err(TypeBuildError::WrapperWithoutTarget);
+        }
+        if self.transparent && self.wrapper.is_none() {
+            return Err(TypeBuildError::TransparentWithoutWrapper);
+        }
+        if let (Some((name, wrap)), Some(target)) = (&self.wrapper, &self.target)
+            && let Some(wrapped) = checking_wrapper(|| wrap(target.clone()))
+        {
+            let found = wrapped.wrapper_name().map(ToString::to_string);
+            if found.as_ref() != Some(name) {
+                return Err(TypeBuildError::WrapperNameMismatch {
+                    name: name.clone(),
+                    found,
+                });
+            }
+        }
+        if self.deref_when.is_some() && self.target.is_none() {
+            return Err(TypeBuildError::DerefConditionWithoutTarget);
+        }
+        let mut fields = Map::new();
+        let mut field_order = vec![];
+        for field in self.fields {
+            let name = field.0.name.clone();
+            if fields.insert(name.clone(), field).is_some() {
+                return Err(TypeBuildError::DuplicateField(name.0));
+            }
+            field_order.push(name);
+        }
+        let display = self.display.ok_or(TypeBuildError::MissingDisplay)?;
+        let idempotent = self.idempotent;
+        let transparent = self.transparent;
+        let deref_when = self.deref_when;
+        let explanation = self.explanation;
+        let is_struct = self.target.is_none()
+            && self.element.is_none()
+            && self.wrapper.is_none()
+            && !fields.is_empty();
+        let (wrapper_name, wrapper_wrap) = self.wrapper.unzip();
+        let ty = Type::new(
+            self.target,
+            self.element,
+            wrapper_wrap,
+            wrapper_name,
+            fields,
+            display,
+        );
+        Ok(ty.with_field_order(field_order).with_inner(|inner| {
+            inner.idempotent = idempotent;
+            inner.deref_when = deref_when;
+            inner.explanation = explanation;
+            if is_struct {
+                inner.kind = TypeKind::Struct;
+            }
+            if transparent {
+                inner.kind = TypeKind::DerefTransparent;
+            }
+        }))
+    }
+}
+
+/// Returns the result of `f`, which builds a type to check a wrapper with, or `None` if a wrapper
+/// is already being checked.
+///
+/// A wrap function usually builds the wrapper it belongs to via the builder again, which would
+/// check the wrapper again and so on, so only the outermost build checks it. With the `std`
+/// feature this is tracked per thread, without it for the whole program, so builds on other
+/// threads may skip the check in the meantime.
+fn checking_wrapper(f: impl FnOnce() -> Type) -> Option<Type> {
+    #[cfg(feature = "std")]
+    std::thread_local! {
+        static CHECKING: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+    }
+    #[cfg(feature = "std")]
+    let replace = |checking| CHECKING.replace(checking);
+    #[cfg(not(feature = "std"))]
+    let replace = |checking| {
+        static CHECKING: core::sync::atomic::AtomicBool =
+            core::sync::atomic::AtomicBool::new(false);
+        CHECKING.swap(checking, core::sync::atomic::Ordering::Relaxed)
+    };
+
+    /// Clears the flag again, also if `f` panics.
+    struct Reset<F: Fn(bool) -> bool>(F);
+
+    impl<F: Fn(bool) -> bool> Drop for Reset<F> {
+        fn drop(&mut self) {
+            (self.0)(false);
+        }
+    }
+
+    if replace(true) {
+        return None;
+    }
+    let _reset = Reset(replace);
+    Some(f())
+}
@@ -0,0 +1,269 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+#[cfg(feature = "std")]
+use core::hash::BuildHasher;
+use core::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+    ptr,
+};
+#[cfg(feature = "std")]
+use std::{
+    hash::RandomState,
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::{PlaceExpr, Type, TypeKind};
+
+/// The number of independently locked parts of a [`Cache`].
+#[cfg(feature = "std")]
+const SHARDS: usize = 16;
+
+/// A global cache of computed values.
+///
+/// The keys are spread over several maps, each behind its own lock, so threads looking up
+/// different keys rarely wait for each other. Without the `std` feature there is no lock to guard
+/// the cache with, so nothing is cached and every value is computed again, which only costs time.
+pub(crate) struct Cache<K, V> {
+    #[cfg(feature = "std")]
+    shards: [RwLock<BTreeMap<K, V>>; SHARDS],
+    #[cfg(not(feature = "std"))]
+    shards: core::marker::PhantomData<BTreeMap<K, V>>,
+}
+
+#[cfg(feature = "std")]
+impl<K: Ord + Hash, V: Clone> Cache<K, V> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            shards: [const { RwLock::new(BTreeMap::new()) }; SHARDS],
+        }
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<V> {
+        read(self.shard(key)).get(key).cloned()
+    }
+
+    pub(crate) fn insert(&self, key: K, value: V) {
+        write(self.shard(&key)).insert(key, value);
+    }
+
+    /// Returns the cached value of `key`, computing and caching it with `f` if there is none.
+    ///
+    /// `f` runs without holding a lock. If another thread caches a value for `key` in the
+    /// meantime, that value is returned instead.
+    pub(crate) fn get_or_insert_with(&self, key: K, f: impl FnOnce() -> V) -> V {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = f();
+        write(self.shard(&key)).entry(key).or_insert(value).clone()
+    }
+
+    /// Returns the number of cached values.
+    pub(crate) fn len(&self) -> usize {
+        self.shards.iter().map(|shard| read(shard).len()).sum()
+    }
+
+    fn shard(&self, key: &K) -> &RwLock<BTreeMap<K, V>> {
+        static STATE: std::sync::OnceLock<RandomState> = std::sync::OnceLock::new();
+        let hash = STATE.get_or_init(RandomState::new).hash_one(key);
+        &self.shards[hash as usize % SHARDS]
+    }
+}
+
+/// Locks a shard for reading, ignoring that a panic happened while it was locked, since every
+/// update leaves the map in a consistent state.
+#[cfg(feature = "std")]
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Locks a shard for writing, see [`read`].
+#[cfg(feature = "std")]
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(not(feature = "std"))]
+impl<K, V> Cache<K, V> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            shards: core::marker::PhantomData,
+        }
+    }
+
+    pub(crate) fn get(&self, _: &K) -> Option<V> {
+        None
+    }
+
+    pub(crate) fn insert(&self, _: K, _: V) {}
+
+    pub(crate) fn get_or_insert_with(&self, _: K, f: impl FnOnce() -> V) -> V {
+        f()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        0
+    }
+}
+
+/// A type used as the key of a [`TypeCache`].
+///
+/// Types are compared structurally, which ignores the display strings, the explanations and the
+/// kinds of wrappers created via [`Type::custom_wrapper`]. A cached type is built from its arguments, so two
+/// arguments that are equal but differ in those would get each other's cached type. Keys also
+/// compare them, for the argument itself and every type it consists of, with kinds compared by
+/// identity.
+#[derive(Clone)]
+pub(crate) struct TypeKey(pub(crate) Type);
+
+impl Eq for TypeKey {}
+
+impl PartialEq for TypeKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Ord for TypeKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .cmp(&other.0)
+            .then_with(|| cmp_identity(&self.0, &other.0))
+    }
+}
+
+impl PartialOrd for TypeKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Equal keys are equal types, so hashing the type is enough.
+impl Hash for TypeKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+/// Compares what `==` ignores about two structurally equal types and the types they consist of.
+fn cmp_identity(a: &Type, b: &Type) -> Ordering {
+    if Arc::ptr_eq(&a.0, &b.0) {
+        return Ordering::Equal;
+    }
+    let kind = |ty: &Type| {
+        let kind = ty.0.wrapper_kind.as_ref()?;
+        Some(Arc::as_ptr(kind).cast::<()>().addr())
+    };
+    a.0.name
+        .cmp(&b.0.name)
+        .then_with(|| a.0.explanation.cmp(&b.0.explanation))
+        .then_with(|| kind(a).cmp(&kind(b)))
+        .then_with(|| {
+            parts(a)
+                .iter()
+                .zip(&parts(b))
+                .map(|(a, b)| cmp_identity(a, b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+/// Returns the types `ty` consists of, in the same order for structurally equal types.
+fn parts(ty: &Type) -> Vec<Type> {
+    let this = &*ty.0;
+    let fields = ty.sorted_fields().into_values().map(|(ty, ..)| ty);
+    let variants = ty.sorted_variants().into_values().cloned();
+    let others = this.has_place_target.iter().chain(&this.array_slice_elem);
+    others
+        .cloned()
+        .chain(fields)
+        .chain(variants)
+        .chain(this.args.iter().cloned())
+        .collect()
+}
+
+/// A place used as the key of the caches of computed places, compared as it is written.
+///
+/// Places themselves are ordered by where they are after desugaring them, which is what the
+/// caches compute in the first place. Keys instead compare the projections as written, so `p.x`
+/// and its desugaring `(*p).x` are different keys. Unlike places, keys also compare the targets
+/// recorded by [`PlaceExpr::BoundDeref`], since they decide the type without a bound.
+#[repr(transparent)]
+pub(crate) struct PlaceKey(pub(crate) PlaceExpr);
+
+impl PlaceKey {
+    /// Returns `place` as a key without cloning it.
+    pub(crate) fn from_ref(place: &PlaceExpr) -> &PlaceKey {
+        // SAFETY: `PlaceKey` is a transparent wrapper around `PlaceExpr`.
+        unsafe { &*ptr::from_ref(place).cast::<PlaceKey>() }
+    }
+}
+
+impl Eq for PlaceKey {}
+
+impl PartialEq for PlaceKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Ord for PlaceKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let bound_targets = |place: &PlaceExpr| {
+            let targets = place.chain().into_iter().map(|place| match place {
+                PlaceExpr::BoundDeref(_, target) => Some(TypeKey(target.clone())),
+                _ => None,
+            });
+            targets.collect::<Vec<_>>()
+        };
+        self.0
+            .cmp_written(&other.0)
+            .then_with(|| bound_targets(&self.0).cmp(&bound_targets(&other.0)))
+    }
+}
+
+impl PartialOrd for PlaceKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for PlaceKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+/// The name, type arguments, lifted argument, idempotence and kind of a cached wrapper type.
+pub(crate) type WrapperKey = (String, Vec<TypeKey>, usize, bool, TypeKind);
+
+/// The cache shared by the constructors of slices and wrappers like [`Type::wrapper`], so calling
+/// them twice with the same arguments returns the same type.
+///
+/// The cache is safe to use from many threads at once, constructing types from different threads
+/// rarely waits for a lock. Without the `std` feature nothing is cached.
+pub struct TypeCache {
+    pub(crate) slices: Cache<TypeKey, Type>,
+    pub(crate) wrappers: Cache<WrapperKey, Type>,
+}
+
+impl TypeCache {
+    /// Returns the cache used by all type constructors.
+    pub fn global() -> &'static TypeCache {
+        static GLOBAL: TypeCache = TypeCache {
+            slices: Cache::new(),
+            wrappers: Cache::new(),
+        };
+        &GLOBAL
+    }
+
+    /// Returns the number of cached types.
+    pub fn len(&self) -> usize {
+        self.slices.len() + self.wrappers.len()
+    }
+
+    /// Returns whether no type is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,85 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+
+use crate::{
+    ComputeOptions, Desugaring, Local, Map, ParseError, PlaceExpr, PlaceTyError, Type,
+    WrapperRegistry, cache::PlaceKey,
+};
+
+/// The desugared place and the type of every place computed by a [`PlaceTyCtxt`], keyed by the
+/// place as it was passed in.
+pub(crate) type Memo = BTreeMap<PlaceKey, (PlaceExpr, Type)>;
+
+/// An environment for computing the types of many place expressions over the same local
+/// variables.
+///
+/// The context remembers every place it computed the type of, including the places projected
+/// from, so the shared prefix `(*p).x` of `(*p).x.y` and `(*p).x.z` is only desugared once. Places
+/// are identified by their local variables, so the places have to be built from the locals
+/// returned by [`Self::local`] or parsed via [`Self::parse`] to share results.
+pub struct PlaceTyCtxt {
+    types: Map<String, Type>,
+    registry: Arc<WrapperRegistry>,
+    locals: Map<String, Local>,
+    memo: Memo,
+    cache_hits: usize,
+}
+
+impl PlaceTyCtxt {
+    /// Creates a context with the local variables and place wrappers in `types`, which are looked
+    /// up by name just like in [`PlaceExpr::parse`].
+    pub fn new(types: Map<String, Type>) -> Self {
+        Self::with_registry(types, WrapperRegistry::shared_standard())
+    }
+
+    /// Creates a context like [`Self::new`], looking up the place wrappers that are not in
+    /// `types` in `registry` instead of the standard registry. The places are also computed with
+    /// `registry` as their [`ComputeOptions::registry`].
+    pub fn with_registry(
+        types: Map<String, Type>,
+        registry: impl Into<Arc<WrapperRegistry>>,
+    ) -> Self {
+        Self {
+            types,
+            registry: registry.into(),
+            locals: Map::new(),
+            memo: Memo::new(),
+            cache_hits: 0,
+        }
+    }
+
+    /// Returns the local variable `name`, which is the same for every call.
+    pub fn local(&mut self, name: &str) -> Option<Local> {
+        if let Some(local) = self.locals.get(name) {
+            return Some(local.clone());
+        }
+        let local = Local::new(self.types.get(name)?.clone(), name);
+        self.locals.insert(name.into(), local.clone());
+        Some(local)
+    }
+
+    /// Parses a place expression like [`PlaceExpr::parse`], using the local variables and the
+    /// registry of this context.
+    pub fn parse(&mut self, src: &str) -> Result<PlaceExpr, ParseError> {
+        PlaceExpr::parse_with_locals(src, &self.types, &self.registry, &mut self.locals)
+    }
+
+    /// Computes the type of `place` and desugars it like [`PlaceExpr::compute_ty`], reusing the
+    /// results of the places computed before.
+    pub fn compute(&mut self, place: &mut PlaceExpr) -> Result<Type, PlaceTyError> {
+        let mut state = Desugaring::new(ComputeOptions {
+            registry: self.registry.clone(),
+            ..ComputeOptions::default()
+        });
+        state.memo = Some(core::mem::take(&mut self.memo));
+        let res = place.compute_ty_uncached(&mut state);
+        self.memo = state.memo.unwrap();
+        self.cache_hits += state.memo_hits;
+        res
+    }
+
+    /// Returns how many places had their type taken from the results of previous computations so
+    /// far, instead of being computed again.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits
+    }
+}
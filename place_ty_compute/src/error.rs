@@ -0,0 +1,445 @@
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::{self, Display};
+
+use crate::{Location, PlaceExpr, PlaceProjection, Type};
+
+/// An error that occurred while computing the type of a place expression.
+///
+/// Every variant carries the offending place expression and its type, which is printed as part of
+/// the error message. The alternate display `{:#}` also points at where the place was written,
+/// see [`PlaceTyError::location`].
+#[derive(Debug)]
+pub enum PlaceTyError {
+    /// A field projection `p.field` where neither `typeof(p)` nor any of its implicit deref
+    /// targets have the field.
+    UnknownField {
+        place: PlaceExpr,
+        on_type: Type,
+        field: String,
+    },
+    /// A field projection `p.field` where neither `typeof(p)` nor any of its implicit deref
+    /// targets have the field, and the type it gave up on has no fields at all, e.g. a slice or a
+    /// generic parameter.
+    FieldOnNonStruct {
+        place: PlaceExpr,
+        ty: Type,
+        field: String,
+    },
+    /// A projection like `p.field` or `p[i]` on an unsized trait object `dyn Trait`, which has
+    /// no known fields, see [`Type::new_dyn`]. `projection` is the projection as written after
+    /// the place.
+    FieldOnUnsized {
+        place: PlaceExpr,
+        ty: Type,
+        projection: String,
+    },
+    /// A field projection `p.field` on an enum where several variants have the field, so it has
+    /// to be downcast to one of the `variants` first.
+    AmbiguousField {
+        place: PlaceExpr,
+        ty: Type,
+        field: String,
+        variants: Vec<String>,
+    },
+    /// An index projection `p[i]` where neither `typeof(p)` nor any of its implicit deref targets
+    /// can be indexed. `kind` tells what kind of type `ty` is.
+    IndexOnNonIndexable {
+        place: PlaceExpr,
+        ty: Type,
+        kind: NonIndexableKind,
+    },
+    /// An index projection with a literal that is out of bounds for an array, or a range
+    /// projection on an array whose literal start is after its end.
+    IndexOutOfBounds {
+        place: PlaceExpr,
+        ty: Type,
+        len: usize,
+        index: String,
+    },
+    /// An index projection `p[q]` where the type of the index local `q` is not `usize`. The
+    /// place is the index local.
+    IndexNotUsize { place: PlaceExpr, ty: Type },
+    /// A downcast `(p as Variant)` where neither `typeof(p)` nor any of its implicit deref targets
+    /// have the variant.
+    UnknownVariant {
+        place: PlaceExpr,
+        on_type: Type,
+        variant: String,
+    },
+    /// A dereference `*p` where `typeof(p)` does not implement `HasPlace`.
+    DerefOnNonPointer { place: PlaceExpr, ty: Type },
+    /// A projection that would need to implicitly dereference a raw pointer.
+    ImplicitRawPtrDeref { place: PlaceExpr, ty: Type },
+    /// A projection on a cell like `Cell<T>` that does not go through its explicit projection.
+    CellProjectionRequired {
+        place: PlaceExpr,
+        ty: Type,
+        projection: String,
+    },
+    /// A projection on a value read out of a cell, which is not a place, see
+    /// [`Type::new_value_cell`].
+    ProjectionOnValue { place: PlaceExpr, ty: Type },
+    /// A place wrapper expression `@%Wrapper p` where `Wrapper` does not implement
+    /// `PlaceWrapper`.
+    NotAPlaceWrapper {
+        place: PlaceExpr,
+        ty: Type,
+        wrapper: Type,
+    },
+    /// A projection that would need more implicit dereferences than allowed by
+    /// [`ComputeOptions::max_auto_deref`], `attempted` is the number of the dereference that
+    /// exceeded the limit.
+    ///
+    /// [`ComputeOptions::max_auto_deref`]: crate::ComputeOptions::max_auto_deref
+    AutoDerefLimit {
+        place: PlaceExpr,
+        ty: Type,
+        attempted: usize,
+        limit: usize,
+    },
+    /// A projection on a dereferenceable type that would need an implicit dereference, while
+    /// they are disabled by [`ComputeOptions::auto_deref`]. `suggestion` is the projection with
+    /// the dereference written out.
+    ///
+    /// [`ComputeOptions::auto_deref`]: crate::ComputeOptions::auto_deref
+    ExplicitDerefRequired {
+        place: PlaceExpr,
+        ty: Type,
+        suggestion: PlaceExpr,
+    },
+    /// A place written to via [`Access::Write`] that dereferences `place`, a shared reference or
+    /// a `*const` pointer, on the way.
+    ///
+    /// [`Access::Write`]: crate::Access::Write
+    WriteThroughShared {
+        place: PlaceExpr,
+        ty: Type,
+        written: PlaceExpr,
+    },
+    /// Computing the type needed more nested steps than the recursion limit allows, see
+    /// [`PlaceExpr::compute_ty_with_recursion_limit`]. The place is the local variable at the root
+    /// and `trace` contains the places being computed when the limit was reached, from the
+    /// innermost to the outermost one. It is empty if the place as written already has more
+    /// projections than the limit allows.
+    RecursionLimit {
+        place: PlaceExpr,
+        ty: Type,
+        limit: usize,
+        trace: Vec<PlaceExpr>,
+    },
+    /// A place wrapper expression `@%found p` whose wrapper does not match the computed type of
+    /// `p`, since `p` dereferences a different place wrapper or wrapping it results in a different
+    /// type, namely `expected`.
+    WrapperAnnotationMismatch {
+        place: PlaceExpr,
+        ty: Type,
+        expected: Type,
+        found: Type,
+    },
+    /// A dereference `deref` of a type whose condition for implementing `PlaceDeref` does not
+    /// hold for its target, see [`TypeBuilder::deref_when`].
+    ///
+    /// [`TypeBuilder::deref_when`]: crate::TypeBuilder::deref_when
+    DerefConditionFailed {
+        place: PlaceExpr,
+        ty: Type,
+        deref: PlaceExpr,
+    },
+    /// A dereference of a place wrapper not implementing `PlaceDeref` that is not undone by a
+    /// place wrapper expression, see [`PlaceExpr::compute_ty_checked`].
+    WrapperDerefNotAllowed {
+        place: PlaceExpr,
+        wrapper: Type,
+        deref: PlaceExpr,
+    },
+    /// A projection through the wrapper `place` that its [`WrapperKind`] resolved to
+    /// `projection`, which is a dereference or a place wrapper expression instead of a field,
+    /// index, range or downcast.
+    ///
+    /// [`WrapperKind`]: crate::WrapperKind
+    InvalidWrapperProjection {
+        place: PlaceExpr,
+        ty: Type,
+        projection: PlaceProjection,
+    },
+    /// A place wrapper expression `@%Wrapper p`, or a wrapper lifted over a projection, whose
+    /// wrapper is registered as [`WrapperBehavior::Transparent`] in [`ComputeOptions::registry`],
+    /// so it is dereferenced implicitly instead of wrapping places. The place is `p`.
+    ///
+    /// [`WrapperBehavior::Transparent`]: crate::WrapperBehavior::Transparent
+    /// [`ComputeOptions::registry`]: crate::ComputeOptions::registry
+    WrapperBehaviorMismatch {
+        place: PlaceExpr,
+        ty: Type,
+        wrapper: Type,
+    },
+    /// A dereference of the place wrapper expression `place`, whose wrapper dereferences to
+    /// `target` instead of the type `unwrapped` of the place it wraps. The wrap function of the
+    /// wrapper does not construct a wrapper around its argument.
+    WrapperTargetMismatch {
+        place: PlaceExpr,
+        ty: Type,
+        target: Type,
+        unwrapped: Type,
+    },
+    /// Computing the type of `place` panicked with `message`, e.g. in the wrap function of a
+    /// wrapper. `ty` is the type of its local variable.
+    #[cfg_attr(feature = "std", doc = "")]
+    #[cfg_attr(
+        feature = "std",
+        doc = "Only [`PlaceExpr::try_compute_ty`] catches panics and returns this error."
+    )]
+    Internal {
+        place: PlaceExpr,
+        ty: Type,
+        message: String,
+    },
+}
+
+/// The kind of type an index projection was applied to, see
+/// [`PlaceTyError::IndexOnNonIndexable`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonIndexableKind {
+    /// A struct, whose fields might be indexable.
+    Struct,
+    /// A union.
+    Union,
+    /// An enum, whose variants might have indexable fields.
+    Enum,
+    /// A tuple.
+    Tuple,
+    /// Any other type without fields, like a generic type parameter.
+    Scalar,
+}
+
+impl PlaceTyError {
+    /// Returns the place expression that caused the error.
+    pub fn place(&self) -> &PlaceExpr {
+        match self {
+            Self::UnknownField { place, .. }
+            | Self::FieldOnNonStruct { place, .. }
+            | Self::FieldOnUnsized { place, .. }
+            | Self::AmbiguousField { place, .. }
+            | Self::IndexOnNonIndexable { place, .. }
+            | Self::IndexOutOfBounds { place, .. }
+            | Self::IndexNotUsize { place, .. }
+            | Self::UnknownVariant { place, .. }
+            | Self::DerefOnNonPointer { place, .. }
+            | Self::ImplicitRawPtrDeref { place, .. }
+            | Self::CellProjectionRequired { place, .. }
+            | Self::ProjectionOnValue { place, .. }
+            | Self::NotAPlaceWrapper { place, .. }
+            | Self::AutoDerefLimit { place, .. }
+            | Self::ExplicitDerefRequired { place, .. }
+            | Self::WriteThroughShared { place, .. }
+            | Self::RecursionLimit { place, .. }
+            | Self::WrapperAnnotationMismatch { place, .. }
+            | Self::DerefConditionFailed { place, .. }
+            | Self::InvalidWrapperProjection { place, .. }
+            | Self::WrapperBehaviorMismatch { place, .. }
+            | Self::WrapperTargetMismatch { place, .. }
+            | Self::Internal { place, .. }
+            | Self::WrapperDerefNotAllowed { place, .. } => place,
+        }
+    }
+
+    /// Returns the type of the place expression that caused the error.
+    pub fn ty(&self) -> &Type {
+        match self {
+            Self::UnknownField { on_type: ty, .. }
+            | Self::FieldOnNonStruct { ty, .. }
+            | Self::FieldOnUnsized { ty, .. }
+            | Self::AmbiguousField { ty, .. }
+            | Self::IndexOnNonIndexable { ty, .. }
+            | Self::IndexOutOfBounds { ty, .. }
+            | Self::IndexNotUsize { ty, .. }
+            | Self::UnknownVariant { on_type: ty, .. }
+            | Self::DerefOnNonPointer { ty, .. }
+            | Self::ImplicitRawPtrDeref { ty, .. }
+            | Self::CellProjectionRequired { ty, .. }
+            | Self::ProjectionOnValue { ty, .. }
+            | Self::NotAPlaceWrapper { ty, .. }
+            | Self::AutoDerefLimit { ty, .. }
+            | Self::ExplicitDerefRequired { ty, .. }
+            | Self::WriteThroughShared { ty, .. }
+            | Self::RecursionLimit { ty, .. }
+            | Self::WrapperAnnotationMismatch { ty, .. }
+            | Self::DerefConditionFailed { ty, .. }
+            | Self::InvalidWrapperProjection { ty, .. }
+            | Self::WrapperBehaviorMismatch { ty, .. }
+            | Self::WrapperTargetMismatch { ty, .. }
+            | Self::Internal { ty, .. }
+            | Self::WrapperDerefNotAllowed { wrapper: ty, .. } => ty,
+        }
+    }
+
+    /// Returns where the place expression that caused the error was written, if it was written
+    /// via `place_expr!`, see [`PlaceExpr::location`].
+    ///
+    /// This is where the `place_expr!` invocation starts, even if the error is about one of its
+    /// inner projections.
+    pub fn location(&self) -> Option<Location> {
+        self.place().location()
+    }
+}
+
+impl Display for PlaceTyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the type of `{}: {}` ", self.place(), self.ty())?;
+        self.fmt_reason(f)?;
+        if f.alternate()
+            && let Some(location) = self.location()
+        {
+            write!(f, "\n  --> {location}")?;
+        }
+        Ok(())
+    }
+}
+
+impl PlaceTyError {
+    fn fmt_reason(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownField { on_type, field, .. } => {
+                write!(f, "has no field `{field}`")?;
+                if let Some(len) = on_type.tuple_len() {
+                    write!(f, ", it only has {len} elements")?;
+                }
+                Ok(())
+            }
+            Self::FieldOnNonStruct { field, .. } => {
+                write!(f, "is not a struct, so it has no field `{field}`")
+            }
+            Self::FieldOnUnsized { projection, .. } => write!(
+                f,
+                "is an unsized trait object, so its contents are unknown and `{projection}` \
+                 cannot be projected from it"
+            ),
+            Self::AmbiguousField {
+                field, variants, ..
+            } => {
+                let variants = variants
+                    .iter()
+                    .map(|v| format!("`{v}`"))
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "is an enum whose variants {} all have a field `{field}`, so it must be \
+                     downcast to one of them first",
+                    variants.join(", ")
+                )
+            }
+            Self::IndexOnNonIndexable { kind, .. } => {
+                match kind {
+                    NonIndexableKind::Struct => write!(f, "is a struct, so it ")?,
+                    NonIndexableKind::Union => write!(f, "is a union, so it ")?,
+                    NonIndexableKind::Enum => write!(f, "is an enum, so it ")?,
+                    NonIndexableKind::Tuple => write!(f, "is a tuple, so it ")?,
+                    NonIndexableKind::Scalar => {}
+                }
+                write!(
+                    f,
+                    "cannot be indexed and no indexable type is reachable through dereferences"
+                )
+            }
+            Self::IndexOutOfBounds { len, index, .. } => {
+                write!(
+                    f,
+                    "has length {len}, so the index `{index}` is out of bounds"
+                )
+            }
+            Self::IndexNotUsize { .. } => {
+                write!(f, "cannot be used as an index, it should be `usize`")
+            }
+            Self::UnknownVariant { variant, .. } => write!(f, "has no variant named `{variant}`"),
+            Self::DerefOnNonPointer { .. } => write!(f, "should implement `HasPlace`"),
+            Self::ImplicitRawPtrDeref { .. } => {
+                write!(f, "is a raw pointer, which must be dereferenced explicitly")
+            }
+            Self::CellProjectionRequired { projection, .. } => write!(
+                f,
+                "is a cell, its contents must be accessed explicitly via `.{projection}`"
+            ),
+            Self::ProjectionOnValue { .. } => write!(
+                f,
+                "is a value read out of a cell, not a place, so it can only be dereferenced"
+            ),
+            Self::NotAPlaceWrapper { wrapper, .. } => {
+                write!(
+                    f,
+                    "cannot be wrapped, since `{wrapper}` should implement `PlaceWrapper`"
+                )
+            }
+            Self::AutoDerefLimit {
+                attempted, limit, ..
+            } => write!(
+                f,
+                "would need implicit dereference number {attempted}, which exceeds the limit of \
+                 {limit}"
+            ),
+            Self::ExplicitDerefRequired { suggestion, .. } => write!(
+                f,
+                "must be dereferenced explicitly, since implicit dereferences are disabled: \
+                 write `{suggestion}`"
+            ),
+            Self::WriteThroughShared { written, .. } => write!(
+                f,
+                "only allows reading through it, so `{written}` cannot be written to"
+            ),
+            Self::RecursionLimit { limit, trace, .. } => match trace.last() {
+                Some(place) => write!(
+                    f,
+                    "is projected too often in `{place}`, which exceeds the recursion limit of \
+                     {limit}"
+                ),
+                None => write!(
+                    f,
+                    "is projected too often, which exceeds the recursion limit of {limit}"
+                ),
+            },
+            Self::WrapperAnnotationMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "is wrapped in `@%{found}`, but wrapping it results in `{expected}`"
+            ),
+            Self::DerefConditionFailed { deref, .. } => write!(
+                f,
+                "only implements `PlaceDeref` for some targets, so it cannot be dereferenced in \
+                 `{deref}`"
+            ),
+            Self::WrapperDerefNotAllowed { wrapper, deref, .. } => write!(
+                f,
+                "does not implement `PlaceDeref`, so it cannot be dereferenced in `{deref}` \
+                 without wrapping the result in `@%{}`",
+                wrapper.wrapper_name().unwrap_or("?")
+            ),
+            Self::InvalidWrapperProjection { projection, .. } => write!(
+                f,
+                "is a wrapper whose kind resolves a projection through it to `{projection}`, which \
+                 is not a field, index, range or downcast"
+            ),
+            Self::WrapperBehaviorMismatch { wrapper, .. } => {
+                let name = wrapper.wrapper_name().unwrap_or("?");
+                write!(
+                    f,
+                    "is wrapped in `@%{name}`, but `{name}` is registered as a transparent \
+                     wrapper, which is dereferenced instead"
+                )
+            }
+            Self::WrapperTargetMismatch {
+                target, unwrapped, ..
+            } => write!(
+                f,
+                "dereferences to `{target}`, but the place it wraps has the type `{unwrapped}`"
+            ),
+            Self::Internal { message, .. } => {
+                write!(
+                    f,
+                    "could not be computed, since computing it panicked: {message}"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for PlaceTyError {}
@@ -0,0 +1,67 @@
+use alloc::string::{String, ToString};
+
+use crate::{Map, Type, TypeKind};
+
+/// What is known about the generic type parameters of a place, see [`ComputeOptions::generics`].
+///
+/// A generic parameter like `T` is opaque, so `*p` with `p: T` is an error. With a bound
+/// `T: Deref<Target = U>` in the environment, `*p` has the type `U` instead and `p.field`
+/// desugars to `(*p).field` if `U` has the field.
+///
+/// The bounds are only known while computing the type, so a dereference resolved via a bound is
+/// desugared to a [`PlaceExpr::BoundDeref`] that records the target. That way [`PlaceExpr::ty`]
+/// and [`PlaceExpr::context`] see the target afterwards, and computing the desugared place again
+/// does not need the bound.
+///
+/// ```
+/// # use place_ty_compute::{ComputeOptions, GenericEnv, Local, PlaceExpr};
+/// # use place_ty_compute::Type;
+/// let u = Type::new_generic("U");
+/// let p = Local::new(Type::new_generic("T"), "p");
+/// let mut generics = GenericEnv::new();
+/// generics.add_deref_bound("T", u.clone());
+/// let mut place = PlaceExpr::Deref(Box::new(PlaceExpr::LocalVar(p)));
+/// let options = ComputeOptions { generics, ..ComputeOptions::default() };
+/// assert_eq!(place.compute_ty_with_options(options).unwrap(), u);
+/// assert_eq!(place.ty(), Some(u));
+/// ```
+///
+/// [`ComputeOptions::generics`]: crate::ComputeOptions::generics
+/// [`PlaceExpr::BoundDeref`]: crate::PlaceExpr::BoundDeref
+/// [`PlaceExpr::ty`]: crate::PlaceExpr::ty
+/// [`PlaceExpr::context`]: crate::PlaceExpr::context
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenericEnv {
+    /// The `Deref` targets of the generic parameters, by the name of the parameter.
+    deref_bounds: Map<String, Type>,
+}
+
+impl GenericEnv {
+    /// Creates an environment without any bounds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the bound `param: Deref<Target = target>`, replacing an earlier `Deref` bound of
+    /// `param`.
+    pub fn add_deref_bound(&mut self, param: &str, target: Type) -> &mut Self {
+        self.deref_bounds.insert(param.to_string(), target);
+        self
+    }
+
+    /// Returns the `Deref` target of the generic parameter `param`, if it has a bound.
+    pub fn deref_bound(&self, param: &str) -> Option<&Type> {
+        self.deref_bounds.get(param)
+    }
+
+    /// Returns the type of `*p` for `p: ty`, taking the bounds into account if `ty` is a generic
+    /// parameter.
+    pub(crate) fn deref_target(&self, ty: &Type) -> Option<Type> {
+        match ty.0.kind {
+            TypeKind::Generic => ty
+                .get_has_place_target()
+                .or_else(|| self.deref_bound(&ty.0.name).cloned()),
+            _ => ty.get_has_place_target(),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use alloc::vec::Vec;
+
+use crate::{
+    ComputeOptions, Desugaring, PlaceExpr, PlaceProjection, PlaceTyError, Type, cache::PlaceKey,
+    ctxt::Memo,
+};
+
+/// A place expression built by appending one projection at a time, e.g. while a user types it.
+///
+/// Appending a projection only computes the type of the new outermost place: the place built so
+/// far is already desugared and its type is known, so it is not computed again. The results are
+/// the same as computing the type of the whole place with [`PlaceExpr::compute_ty`].
+///
+/// ```
+/// # use place_ty_compute::{Field, IncrementalPlace, Local, PlaceExpr, PlaceProjection, Type};
+/// let s = Type::new_struct("S", [Field::new("x", Type::new_generic("X"))]);
+/// let p = Local::new(Type::new_ref(s), "p");
+/// let mut place = IncrementalPlace::new(PlaceExpr::LocalVar(p)).unwrap();
+/// place.push_projection(PlaceProjection::Field("x".into())).unwrap();
+/// assert_eq!(place.place().to_string(), "(*p).x");
+/// place.pop_projection();
+/// assert_eq!(place.place().to_string(), "p");
+/// ```
+pub struct IncrementalPlace {
+    place: PlaceExpr,
+    ty: Type,
+    /// The places before every appended projection, together with their types and the appended
+    /// projection.
+    history: Vec<(PlaceExpr, Type, PlaceProjection)>,
+}
+
+impl IncrementalPlace {
+    /// Starts building a place at `place`, computing its type like [`PlaceExpr::compute_ty`].
+    pub fn new(mut place: PlaceExpr) -> Result<Self, PlaceTyError> {
+        let ty = place.compute_ty()?;
+        Ok(Self {
+            place,
+            ty,
+            history: Vec::new(),
+        })
+    }
+
+    /// Returns the desugared place built so far.
+    pub fn place(&self) -> &PlaceExpr {
+        &self.place
+    }
+
+    /// Returns the type of the place built so far.
+    pub fn ty(&self) -> &Type {
+        &self.ty
+    }
+
+    /// Appends `proj` to the place and returns the type of the result.
+    ///
+    /// If the type cannot be computed, the error is returned and the place is left unchanged.
+    pub fn push_projection(&mut self, proj: PlaceProjection) -> Result<Type, PlaceTyError> {
+        let mut memo = Memo::new();
+        memo.insert(
+            PlaceKey(self.place.clone()),
+            (self.place.clone(), self.ty.clone()),
+        );
+        let mut state = Desugaring::new(ComputeOptions::default());
+        state.memo = Some(memo);
+        let mut place = proj.clone().apply(self.place.clone());
+        let ty = place.compute_ty_uncached(&mut state)?;
+        let place = core::mem::replace(&mut self.place, place);
+        let prev = core::mem::replace(&mut self.ty, ty.clone());
+        self.history.push((place, prev, proj));
+        Ok(ty)
+    }
+
+    /// Removes the projection appended last, restoring the place and type from before it was
+    /// appended, and returns the projection.
+    ///
+    /// Returns `None` if no projection was appended.
+    pub fn pop_projection(&mut self) -> Option<PlaceProjection> {
+        let (place, ty, proj) = self.history.pop()?;
+        self.place = place;
+        self.ty = ty;
+        Some(proj)
+    }
+}
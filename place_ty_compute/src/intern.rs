@@ -0,0 +1,125 @@
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+    hash::Hash,
+    ops::Deref,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::Type;
+
+/// Deduplicates structurally equal types, handing out [`Interned`] handles.
+///
+/// Interning a type returns a handle to the first structurally equal type that was interned, so
+/// constructing `MaybeUninit<T>` twice and interning both yields the same handle. Handles of the
+/// same interner are compared by pointer.
+///
+/// # Memory model
+///
+/// An interner keeps every type interned into it alive until it is dropped. The global interner
+/// returned by [`TypeInterner::global`] is never dropped, so its types live until the end of the
+/// program. Handles own their type, so they stay valid after their interner is dropped, but
+/// comparing handles of different interners only compares their pointers and thus is meaningless.
+pub struct TypeInterner {
+    types: Mutex<BTreeSet<Type>>,
+}
+
+impl TypeInterner {
+    pub const fn new() -> Self {
+        Self {
+            types: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Returns the interner shared by the whole program.
+    pub fn global() -> &'static TypeInterner {
+        static GLOBAL: TypeInterner = TypeInterner::new();
+        &GLOBAL
+    }
+
+    /// Returns the handle of `ty`, interning it if no structurally equal type was interned before.
+    pub fn intern(&self, ty: Type) -> Interned {
+        let mut types = self.types();
+        if let Some(ty) = types.get(&ty) {
+            return Interned(ty.clone());
+        }
+        types.insert(ty.clone());
+        Interned(ty)
+    }
+
+    /// Returns the number of distinct types interned so far.
+    pub fn len(&self) -> usize {
+        self.types().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Locks the interned types, ignoring that a panic happened while they were locked, since
+    /// interning never leaves the set in an inconsistent state.
+    fn types(&self) -> MutexGuard<'_, BTreeSet<Type>> {
+        self.types.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for TypeInterner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handle to a type interned by a [`TypeInterner`].
+///
+/// Cloning a handle is cheap and handles are compared and hashed by pointer. A handle dereferences
+/// to its type and can be turned into a [`Type`] to be used in places, e.g. via [`Local::new`].
+///
+/// [`Local::new`]: crate::Local::new
+#[derive(Clone)]
+pub struct Interned(Type);
+
+impl Interned {
+    pub fn into_type(self) -> Type {
+        self.0
+    }
+}
+
+impl Deref for Interned {
+    type Target = Type;
+
+    fn deref(&self) -> &Type {
+        &self.0
+    }
+}
+
+impl From<Interned> for Type {
+    fn from(interned: Interned) -> Self {
+        interned.0
+    }
+}
+
+impl Eq for Interned {}
+
+impl PartialEq for Interned {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0.0, &other.0.0)
+    }
+}
+
+impl Hash for Interned {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0.0).addr().hash(state)
+    }
+}
+
+impl Display for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Interned").field(&self.0.0.name).finish()
+    }
+}
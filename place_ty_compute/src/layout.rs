@@ -0,0 +1,156 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{Type, TypeInner, TypeKind};
+
+/// The size and alignment of a type in bytes, see [`Type::layout`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layout {
+    pub size: usize,
+    pub align: usize,
+}
+
+impl Layout {
+    /// The layout of a thin pointer on the host, which is also used for references and raw
+    /// pointers.
+    pub const POINTER: Layout = Layout {
+        size: size_of::<usize>(),
+        align: align_of::<usize>(),
+    };
+
+    /// Rounds `offset` up to the next multiple of the alignment.
+    fn align_up(self, offset: usize) -> usize {
+        offset.next_multiple_of(self.align)
+    }
+}
+
+impl Type {
+    /// Returns this type with the given layout, with its structure unchanged.
+    ///
+    /// This is meant for leaves like generic parameters and opaque types, whose layout cannot be
+    /// computed otherwise. The layout takes precedence over the one computed by [`Self::layout`].
+    pub fn with_layout(&self, layout: Layout) -> Type {
+        assert!(
+            layout.align.is_power_of_two(),
+            "alignment should be a power of two"
+        );
+        let mut inner = TypeInner::clone(&self.0);
+        inner.layout = Some(layout);
+        Type(Arc::new(inner))
+    }
+
+    /// Returns the size and alignment of this type, or `None` if it is unknown.
+    ///
+    /// Unless given via [`Self::with_layout`], the layout is computed from the structure:
+    /// - references and raw pointers are pointer-sized, pointers to slices and
+    ///   trait objects are twice that,
+    /// - arrays `[T; N]` consist of `N` elements of type `T`,
+    /// - structs and tuples lay out their fields in declaration order, each at the next offset
+    ///   that fits its alignment unless the field has an explicit offset, and are padded to their
+    ///   alignment,
+    /// - unions are as large as their largest field,
+    /// - cells and `Pin<P>` have the layout of their contents,
+    /// - the never type `!` has size zero.
+    ///
+    /// The layout of everything else is unknown, as is the layout of a type built from a type with
+    /// an unknown layout. Slices and arrays with a symbolic length have no fixed size.
+    pub fn layout(&self) -> Option<Layout> {
+        let this = &*self.0;
+        if let Some(layout) = this.layout {
+            return Some(layout);
+        }
+        match this.kind {
+            TypeKind::Ref(_) | TypeKind::RawPtr(_) => {
+                let target = this.has_place_target.as_ref()?;
+                let words = if target.is_slice() || target.is_dyn() {
+                    2
+                } else {
+                    1
+                };
+                Some(Layout {
+                    size: Layout::POINTER.size * words,
+                    ..Layout::POINTER
+                })
+            }
+            TypeKind::Array(len) => {
+                let elem = this.array_slice_elem.as_ref()?.layout()?;
+                Some(Layout {
+                    size: elem.size.checked_mul(len)?,
+                    align: elem.align,
+                })
+            }
+            TypeKind::Struct | TypeKind::Tuple(_) => Some(self.field_offsets()?.0),
+            TypeKind::Union => {
+                let mut layout = Layout { size: 0, align: 1 };
+                for field in this.fields.values() {
+                    let field = field.ty().layout()?;
+                    layout.size = layout.size.max(field.size);
+                    layout.align = layout.align.max(field.align);
+                }
+                layout.size = layout.align_up(layout.size);
+                Some(layout)
+            }
+            TypeKind::Cell { .. } => this.fields.values().next()?.ty().layout(),
+            TypeKind::Pin => this.args[0].layout(),
+            TypeKind::Never => Some(Layout { size: 0, align: 1 }),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte offset of the field reached by following the field names in `path`, like
+    /// `offset_of!(Type, x.y.z)`, or `None` if a field is missing or a layout is unknown.
+    ///
+    /// The offsets are within a single value, so unlike [`PlaceExpr::compute_ty`] this does not
+    /// dereference anything: a field behind a reference or `Box` is not found. Fields of unions
+    /// and cells are at offset zero, fields of structs and tuples where [`Self::layout`] puts
+    /// them.
+    ///
+    /// [`PlaceExpr::compute_ty`]: crate::PlaceExpr::compute_ty
+    pub fn offset_of(&self, path: &[&str]) -> Option<usize> {
+        let mut ty = self;
+        let mut offset = 0usize;
+        for name in path {
+            offset = offset.checked_add(ty.field_offset(name)?)?;
+            ty = ty.field(name)?;
+        }
+        Some(offset)
+    }
+
+    /// Returns the byte offset of the field `name` into this type.
+    fn field_offset(&self, name: &str) -> Option<usize> {
+        let this = &*self.0;
+        match this.kind {
+            TypeKind::Struct | TypeKind::Tuple(_) => {
+                let (_, offsets) = self.field_offsets()?;
+                let index = this.field_order.iter().position(|field| field.0 == name)?;
+                Some(offsets[index])
+            }
+            TypeKind::Union | TypeKind::Cell { .. } => {
+                self.field(name)?;
+                self.layout()?;
+                Some(0)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the layout of this struct or tuple together with the offsets of its fields in
+    /// declaration order.
+    fn field_offsets(&self) -> Option<(Layout, Vec<usize>)> {
+        let this = &*self.0;
+        let mut layout = Layout { size: 0, align: 1 };
+        let mut offsets = Vec::with_capacity(this.field_order.len());
+        for name in &this.field_order {
+            let field = &this.fields[name];
+            let field_layout = field.ty().layout()?;
+            let offset = field
+                .offset()
+                .unwrap_or_else(|| field_layout.align_up(layout.size));
+            layout.size = layout.size.max(offset.checked_add(field_layout.size)?);
+            layout.align = layout.align.max(field_layout.align);
+            offsets.push(offset);
+        }
+        layout.size = layout.align_up(layout.size);
+        Some((layout, offsets))
+    }
+}
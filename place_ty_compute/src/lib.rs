@@ -1,16 +1,96 @@
-use std::{
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{
     borrow::Borrow,
-    collections::{BTreeMap, HashMap, HashSet},
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    cmp::Ordering,
     fmt::{self, Display},
     hash::Hash,
     hint::unreachable_unchecked,
     ptr,
-    sync::{Arc, Mutex},
 };
 
-use tracing::{debug, info, info_span};
+#[cfg(feature = "tracing")]
+use tracing::{Level, debug, enabled, info, info_span};
+
+mod builder;
+mod cache;
+mod ctxt;
+mod error;
+mod generics;
+mod incremental;
+#[cfg(feature = "std")]
+mod intern;
+mod layout;
+mod lint;
+mod location;
+#[cfg(not(feature = "tracing"))]
+mod no_tracing;
+mod parse;
+mod registry;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod trace;
+mod tree;
+mod value;
+mod visit;
+mod well_formed;
+mod wrapper;
+
+pub use builder::{TypeBuildError, TypeBuilder};
+pub use ctxt::PlaceTyCtxt;
+pub use error::{NonIndexableKind, PlaceTyError};
+pub use generics::GenericEnv;
+pub use incremental::IncrementalPlace;
+#[cfg(feature = "std")]
+pub use intern::{Interned, TypeInterner};
+pub use layout::Layout;
+pub use lint::Lint;
+pub use location::Location;
+pub use parse::{ParseError, wrapper_by_name};
+pub use registry::{WrapperBehavior, WrapperRegistry};
+pub use value::ExprOfPlace;
+pub use visit::{PlaceProjection, PlaceVisitor, ProjectionDiff, Rewrite};
+pub use well_formed::MalformedPlace;
+pub use wrapper::{ByProjection, Lifting, ProjectionResult, Transparent, WrapperKind};
+
+pub use cache::TypeCache;
+use cache::{Cache, PlaceKey, TypeKey};
+#[cfg(not(feature = "tracing"))]
+use no_tracing::{debug, enabled, info, info_span};
+use trace::TraceStep;
+
+/// The map type used by the API, e.g. for the fields of [`Type::new`] and the locals of
+/// [`PlaceExpr::parse`].
+///
+/// This is a `HashMap` with the `std` feature and a `BTreeMap` without it.
+#[cfg(feature = "std")]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+/// The map type used by the API, e.g. for the fields of [`Type::new`] and the locals of
+/// [`PlaceExpr::parse`].
+///
+/// This is a `HashMap` with the `std` feature and a `BTreeMap` without it.
+#[cfg(not(feature = "std"))]
+pub type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+#[cfg(feature = "std")]
+type Set<T> = std::collections::HashSet<T>;
+#[cfg(not(feature = "std"))]
+type Set<T> = alloc::collections::BTreeSet<T>;
 
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct Ident(String);
 
 impl Borrow<str> for Ident {
@@ -20,58 +100,183 @@ impl Borrow<str> for Ident {
 }
 
 impl Display for Ident {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.fmt(f)
     }
 }
 
-#[derive(Clone, PartialOrd, Ord, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialOrd, Ord, Eq, PartialEq, Hash)]
 pub struct Expr(pub String);
 
+impl Expr {
+    /// Returns `n` if this is an index `^n` counting from the end, where `^1` is the last element.
+    pub fn from_end(&self) -> Option<usize> {
+        self.0.strip_prefix('^')?.trim().parse().ok()
+    }
+}
+
 impl Display for Expr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.fmt(f)
     }
 }
 
+/// A type.
+///
+/// Cloning a type is cheap, all clones share the same type including its wrapper function.
 #[derive(Clone)]
 pub struct Type(Arc<TypeInner>);
 
+/// Types are compared structurally.
+///
+/// Two types are equal when they have the same kind, deref target, element type, wrapper name,
+/// fields and variants. Nominal types (generics, structs and enums) additionally need to have the
+/// same name. The display string of other types, the explanation and the wrapper function are
+/// ignored.
+///
+/// Types with type arguments that are not reachable otherwise, like `Pin<P>` or the `E` in the
+/// place wrapper `Result<T, E>`, also compare them, just like types with a layout given via
+/// [`Type::with_layout`] compare it.
 impl Eq for Type {}
 
 impl PartialEq for Type {
     fn eq(&self, other: &Self) -> bool {
-        Arc::ptr_eq(&self.0, &other.0)
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Ord for Type {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        ptr::from_ref(self.0.as_ref())
-            .addr()
-            .cmp(&ptr::from_ref(other.0.as_ref()).addr())
+    fn cmp(&self, other: &Self) -> Ordering {
+        if Arc::ptr_eq(&self.0, &other.0) {
+            return Ordering::Equal;
+        }
+        let (this, that) = (&*self.0, &*other.0);
+        this.kind
+            .cmp(&that.kind)
+            .then_with(|| self.nominal_name().cmp(&other.nominal_name()))
+            .then_with(|| this.wrapper_name.cmp(&that.wrapper_name))
+            .then_with(|| this.has_place_target.cmp(&that.has_place_target))
+            .then_with(|| this.array_slice_elem.cmp(&that.array_slice_elem))
+            .then_with(|| self.sorted_fields().cmp(&other.sorted_fields()))
+            .then_with(|| self.sorted_variants().cmp(&other.sorted_variants()))
+            .then_with(|| this.args.cmp(&that.args))
+            .then_with(|| this.lifted_arg.cmp(&that.lifted_arg))
+            .then_with(|| this.layout.cmp(&that.layout))
     }
 }
 
 impl PartialOrd for Type {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
 impl Hash for Type {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        (&raw const *self.0).addr().hash(state)
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        let this = &*self.0;
+        this.kind.hash(state);
+        self.nominal_name().hash(state);
+        this.wrapper_name.hash(state);
+        this.has_place_target.hash(state);
+        this.array_slice_elem.hash(state);
+        self.sorted_fields().hash(state);
+        self.sorted_variants().hash(state);
+        this.args.hash(state);
+        this.lifted_arg.hash(state);
+        this.layout.hash(state);
     }
 }
 
+/// Wraps a type in a place wrapper, e.g. turns `T` into `MaybeUninit<T>`.
+type WrapFn = Box<dyn Fn(Type) -> Type + Send + Sync>;
+
+#[derive(Clone)]
 struct TypeInner {
     has_place_target: Option<Type>,
     array_slice_elem: Option<Type>,
-    wrapper_wrap: Option<Box<dyn Fn(Type) -> Type + Send + Sync>>,
+    wrapper_wrap: Option<Arc<dyn Fn(Type) -> Type + Send + Sync>>,
     wrapper_name: Option<String>,
-    fields: HashMap<Ident, Field>,
+    fields: Map<Ident, Field>,
+    /// The names of the fields in declaration order.
+    field_order: Vec<Ident>,
+    variants: Map<Ident, Type>,
+    /// Type arguments that are not reachable through the target, element or fields, e.g. the `P`
+    /// in `Pin<P>`.
+    args: Vec<Type>,
+    /// The index of the target in `args` for place wrappers with multiple type arguments, i.e. the
+    /// argument that projections are lifted over.
+    lifted_arg: usize,
+    /// Whether nested layers of this place wrapper can be collapsed into one.
+    idempotent: bool,
+    /// The kind of a wrapper created via [`Type::custom_wrapper`].
+    wrapper_kind: Option<Arc<dyn WrapperKind>>,
+    /// Decides whether this type implements `PlaceDeref` based on its target, see
+    /// [`TypeBuilder::deref_when`].
+    deref_when: Option<fn(&Type) -> bool>,
+    /// The layout given via [`Type::with_layout`].
+    layout: Option<Layout>,
+    /// The note shown in the context where this type is dereferenced or lifted, see
+    /// [`Type::with_explanation`].
+    explanation: Option<String>,
     name: String,
+    kind: TypeKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum TypeKind {
+    /// A type constructed via [`Type::new`], we don't know anything about its shape.
+    Opaque,
+    /// A generic type parameter.
+    Generic,
+    /// A struct type, its fields can be accessed by name.
+    Struct,
+    /// A union type, its fields can be accessed by name, but only in `unsafe` code.
+    Union,
+    /// A tuple type with the given number of elements.
+    Tuple(usize),
+    /// A reference type `&T` or `&mut T`.
+    Ref(Mutability),
+    /// A raw pointer type `*const T` or `*mut T`.
+    RawPtr(Mutability),
+    /// An array type `[T; N]` with the given length.
+    Array(usize),
+    /// An array type `[T; N]` whose length is the const generic parameter `N`, which is stored as
+    /// the only argument.
+    GenericArray,
+    /// An enum type, its variants can be accessed through a downcast.
+    Enum,
+    /// A wrapper type like `Box<T>` that is dereferenced implicitly instead of being lifted.
+    DerefTransparent,
+    /// The `Pin<P>` type, which can only be dereferenced if `P` implements `Deref`.
+    Pin,
+    /// An interior mutability type like `Cell<T>`, its contents are only reachable through an
+    /// explicit projection. `value` tells whether the projection reads the contents by copy, see
+    /// [`Type::new_value_cell`].
+    Cell { value: bool },
+    /// The never type `!`, every projection on it results in `!` again.
+    Never,
+    /// A trait object type `dyn Trait`, which is unsized and cannot be projected from.
+    Dyn,
+}
+
+/// The length of an array type, see [`Type::new_array_generic`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArrayLen {
+    /// A literal length like `3` in `[T; 3]`.
+    Const(usize),
+    /// A const generic parameter like `N` in `[T; N]`.
+    Generic(String),
+}
+
+/// Whether a reference allows mutation of its target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mutability {
+    /// A shared reference `&T`.
+    Not,
+    /// A mutable reference `&mut T`.
+    Mut,
 }
 
 impl Display for Type {
@@ -80,325 +285,2253 @@ impl Display for Type {
     }
 }
 
+impl fmt::Debug for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Type").field(&self.0.name).finish()
+    }
+}
+
 impl Type {
     pub fn new(
         has_place_target: Option<Type>,
         array_slice_elem: Option<Type>,
         wrapper_wrap: Option<Box<dyn Fn(Type) -> Type + Send + Sync>>,
         wrapper_name: Option<String>,
-        fields: HashMap<Ident, Field>,
+        fields: Map<Ident, Field>,
         name: String,
     ) -> Self {
         assert!(wrapper_wrap.is_some() == wrapper_name.is_some());
+        let mut field_order = fields.keys().cloned().collect::<Vec<_>>();
+        field_order.sort_by(|a, b| a.0.cmp(&b.0));
         Self(Arc::new(TypeInner {
             has_place_target,
             array_slice_elem,
-            wrapper_wrap,
+            wrapper_wrap: wrapper_wrap.map(Arc::from),
             wrapper_name,
             fields,
+            field_order,
+            variants: Map::new(),
+            args: vec![],
+            lifted_arg: 0,
+            idempotent: false,
+            wrapper_kind: None,
+            deref_when: None,
+            layout: None,
+            explanation: None,
             name,
+            kind: TypeKind::Opaque,
         }))
     }
 
     pub fn new_generic(name: &str) -> Self {
-        Self::new(None, None, None, None, HashMap::new(), name.to_string())
+        Self::new(None, None, None, None, Map::new(), name.to_string()).with_kind(TypeKind::Generic)
     }
 
-    pub fn new_with_target(name: &str, target: Type) -> Self {
+    /// Creates the never type `!` of diverging expressions.
+    ///
+    /// A place of type `!` is never reached, so every projection on it is accepted and results in
+    /// a place of type `!` again: `p.field`, `p[i]`, `p as Variant` and even `*p`. No dereference
+    /// is inserted and no wrapper is lifted, so `p.field` desugars to itself.
+    pub fn new_never() -> Self {
+        Self::new(None, None, None, None, Map::new(), "!".to_string()).with_kind(TypeKind::Never)
+    }
+
+    /// Creates the trait object type `dyn Trait` for the trait `trait_name`.
+    ///
+    /// Nothing is known about the type behind a trait object, so it has no fields and cannot be
+    /// indexed or downcast, any such projection results in a [`PlaceTyError::FieldOnUnsized`].
+    /// It is still a place, so `*p` with `p: &dyn Trait` is fine.
+    pub fn new_dyn(trait_name: &str) -> Self {
         Self::new(
-            Some(target),
             None,
             None,
             None,
-            HashMap::new(),
-            name.to_string(),
+            None,
+            Map::new(),
+            format!("dyn {trait_name}"),
         )
+        .with_kind(TypeKind::Dyn)
     }
 
+    pub fn new_with_target(name: &str, target: Type) -> Self {
+        Self::new(Some(target), None, None, None, Map::new(), name.to_string())
+    }
+
+    /// Creates a struct type with the given fields.
+    ///
+    /// Fields are looked up by name only, so a field can share its name with the struct or with a
+    /// place wrapper. A struct without fields is still a struct, so accessing a field of it
+    /// results in a [`PlaceTyError::UnknownField`] rather than a
+    /// [`PlaceTyError::FieldOnNonStruct`].
     pub fn new_struct(name: &str, fields: impl IntoIterator<Item = Field>) -> Self {
+        let fields = fields.into_iter().collect::<Vec<_>>();
+        let order = fields.iter().map(|f| f.0.name.clone()).collect();
         let fields = fields.into_iter().map(|f| (f.0.name.clone(), f)).collect();
         Self::new(None, None, None, None, fields, name.to_string())
+            .with_kind(TypeKind::Struct)
+            .with_field_order(order)
     }
 
-    fn get_has_place_target(&self) -> Option<Type> {
-        self.0.has_place_target.clone()
+    /// Creates a struct type with the given fields that implements `Deref<Target = target>`, like
+    /// a user-defined smart pointer.
+    ///
+    /// Dereferencing a place of this type results in a place of type `target`, and fields that
+    /// the struct does not have are looked up in `target` through an implicit dereference, just
+    /// like for `Box<T>`. The dereferences show up as a [`Context::Deref`] through a user `Deref`
+    /// impl.
+    pub fn new_smart_pointer(
+        name: &str,
+        fields: impl IntoIterator<Item = Field>,
+        target: Type,
+    ) -> Self {
+        Self::new_struct(name, fields).with_inner(|inner| inner.has_place_target = Some(target))
     }
 
-    fn get_array_or_slice_element(&self) -> Option<Type> {
-        self.0.array_slice_elem.clone()
+    /// Creates a newtype `struct Name(Inner)`, a tuple struct whose only field `0` has the type
+    /// `inner`.
+    pub fn new_newtype(name: &str, inner: Type) -> Self {
+        Self::new_struct(name, [Field::new("0", inner)])
     }
 
-    fn wrap_type(&self, compute_ty: Type) -> Option<Type> {
-        self.0.wrapper_wrap.as_ref().map(|wrap| wrap(compute_ty))
+    /// Creates a newtype like [`Self::new_newtype`] that also implements `Deref<Target = Inner>`,
+    /// so fields of `inner` are reachable through an implicit dereference, see
+    /// [`Self::new_smart_pointer`].
+    pub fn new_deref_newtype(name: &str, inner: Type) -> Self {
+        Self::new_smart_pointer(name, [Field::new("0", inner.clone())], inner)
     }
 
-    fn get_field(&self, field: &str) -> Option<Field> {
-        self.0.fields.get(field).cloned()
+    /// Creates a struct type from `(name, type)` pairs, e.g. `[("y", ty)]`, see
+    /// [`Self::new_struct`].
+    pub fn struct_from<S: AsRef<str>>(
+        name: &str,
+        fields: impl IntoIterator<Item = (S, Type)>,
+    ) -> Self {
+        Self::new_struct(name, fields.into_iter().collect::<Fields>())
     }
 
-    fn wrapper_name(&self) -> Option<&str> {
-        self.0.wrapper_name.as_deref()
+    /// Creates a union type.
+    ///
+    /// Just like for a struct, accessing a field results in a place of the declared type of that
+    /// field. All fields overlap, so every field access is independent of the others and reading
+    /// the resulting place requires `unsafe`, which is recorded in
+    /// [`DesugarReport::requires_unsafe`] and as a [`Context::UnionField`].
+    pub fn new_union(name: &str, fields: impl IntoIterator<Item = Field>) -> Self {
+        Self::new_struct(name, fields).with_kind(TypeKind::Union)
     }
-}
 
-#[derive(Clone)]
-pub struct Field(Arc<FieldInner>);
+    /// Creates a tuple type, its elements are accessible as the fields `0`, `1`, etc.
+    pub fn new_tuple(elems: impl IntoIterator<Item = Type>) -> Self {
+        let elems = elems.into_iter().collect::<Vec<_>>();
+        let name = match &elems[..] {
+            [elem] => format!("({elem},)"),
+            elems => {
+                let elems = elems.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                format!("({})", elems.join(", "))
+            }
+        };
+        let elems_len = elems.len();
+        let kind = TypeKind::Tuple(elems_len);
+        let fields = elems
+            .into_iter()
+            .enumerate()
+            .map(|(i, ty)| Field::new(&i.to_string(), ty))
+            .map(|f| (f.0.name.clone(), f))
+            .collect();
+        let order = (0..elems_len).map(|i| Ident(i.to_string())).collect();
+        Self::new(None, None, None, None, fields, name)
+            .with_kind(kind)
+            .with_field_order(order)
+    }
 
-impl Eq for Field {}
+    /// Creates a shared reference type `&T`.
+    pub fn new_ref(target: Type) -> Self {
+        let name = format!("&{target}");
+        Self::new(Some(target), None, None, None, Map::new(), name)
+            .with_kind(TypeKind::Ref(Mutability::Not))
+    }
 
-impl PartialEq for Field {
-    fn eq(&self, other: &Self) -> bool {
-        Arc::ptr_eq(&self.0, &other.0)
+    /// Creates a mutable reference type `&mut T`.
+    pub fn new_mut_ref(target: Type) -> Self {
+        let name = format!("&mut {target}");
+        Self::new(Some(target), None, None, None, Map::new(), name)
+            .with_kind(TypeKind::Ref(Mutability::Mut))
     }
-}
-impl Hash for Field {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        (&raw const *self.0).addr().hash(state)
+
+    /// Creates a raw pointer type `*const T` or `*mut T`.
+    ///
+    /// Raw pointers can be dereferenced explicitly, but are never dereferenced implicitly.
+    pub fn new_raw_ptr(target: Type, mutable: bool) -> Self {
+        let (name, mutability) = if mutable {
+            (format!("*mut {target}"), Mutability::Mut)
+        } else {
+            (format!("*const {target}"), Mutability::Not)
+        };
+        Self::new(Some(target), None, None, None, Map::new(), name)
+            .with_kind(TypeKind::RawPtr(mutability))
     }
-}
 
-struct FieldInner {
-    ty: Type,
-    name: Ident,
-}
+    /// Creates an array type `[T; N]`.
+    ///
+    /// In contrast to slices, indexing an array with a literal is checked against its length.
+    pub fn new_array(element: Type, len: usize) -> Self {
+        let name = format!("[{element}; {len}]");
+        Self::new(None, Some(element), None, None, Map::new(), name).with_kind(TypeKind::Array(len))
+    }
 
-impl Field {
-    pub fn new(name: &str, ty: Type) -> Self {
-        Self(Arc::new(FieldInner {
-            ty,
-            name: Ident(name.to_string()),
-        }))
+    /// Creates an array type `[T; N]` whose length may be a const generic parameter.
+    ///
+    /// Indexing an array with a symbolic length cannot be checked against its length, every index
+    /// is accepted and recorded as a [`Context::SymbolicArrayLen`].
+    pub fn new_array_generic(element: Type, len: ArrayLen) -> Self {
+        match len {
+            ArrayLen::Const(len) => Self::new_array(element, len),
+            ArrayLen::Generic(len) => {
+                let name = format!("[{element}; {len}]");
+                Self::new(None, Some(element), None, None, Map::new(), name)
+                    .with_kind(TypeKind::GenericArray)
+                    .with_inner(|inner| inner.args = vec![Type::new_generic(&len)])
+            }
+        }
     }
 
-    fn ty(&self) -> Type {
-        self.0.ty.clone()
+    /// Creates a slice type `[T]`.
+    ///
+    /// With the `std` feature, slices are cached per element type, so calling this twice returns
+    /// the same type.
+    pub fn new_slice(element: Type) -> Self {
+        TypeCache::global()
+            .slices
+            .get_or_insert_with(TypeKey(element.clone()), || {
+                let name = format!("[{element}]");
+                Self::new(None, Some(element), None, None, Map::new(), name)
+            })
     }
-}
 
-#[derive(Clone)]
-pub struct Local(Arc<LocalInner>);
+    /// Creates an enum type with the given variants.
+    ///
+    /// Each variant is represented as a struct type named `Enum::Variant` containing the fields of
+    /// the variant. It is only reachable by downcasting a place of the enum type.
+    pub fn new_enum(name: &str, variants: Vec<(String, Vec<Field>)>) -> Self {
+        let variants = variants
+            .into_iter()
+            .map(|(variant, fields)| {
+                let ty = Type::new_struct(&format!("{name}::{variant}"), fields);
+                (Ident(variant), ty)
+            })
+            .collect();
+        Self::new(None, None, None, None, Map::new(), name.to_string()).with_inner(|inner| {
+            inner.kind = TypeKind::Enum;
+            inner.variants = variants;
+        })
+    }
 
-impl Eq for Local {}
+    /// Creates the type `Pin<P>`.
+    ///
+    /// Just like `Pin<P>` only implements `Deref` when `P: Deref`, it can only be dereferenced if
+    /// `pointer` is a pointer type other than a raw pointer or a place wrapper. In that case, it is
+    /// deref-transparent and dereferences to the target of `pointer`.
+    pub fn new_pin(pointer: Type) -> Self {
+        let name = format!("Pin<{pointer}>");
+        let target = pointer
+            .implements_deref()
+            .then(|| pointer.get_has_place_target())
+            .flatten();
+        Self::new(target, None, None, None, Map::new(), name).with_inner(|inner| {
+            inner.kind = TypeKind::Pin;
+            inner.args = vec![pointer];
+        })
+    }
 
-impl PartialEq for Local {
-    fn eq(&self, other: &Self) -> bool {
-        Arc::ptr_eq(&self.0, &other.0)
+    /// Creates an interior mutability type `Name<Inner>` like `Cell<T>` or `UnsafeCell<T>`.
+    ///
+    /// Such a type is neither dereferenced implicitly nor lifted. Instead its contents are reached
+    /// through the explicit projection `p.projection`, so field accesses cannot silently skip the
+    /// cell. For a cell named `UnsafeCell`, the projection requires `unsafe`, see
+    /// [`PlaceExpr::safety`].
+    pub fn new_cell(name: &str, projection: &str, inner: Type) -> Self {
+        Self::cell(name, projection, inner, false)
     }
-}
 
-impl Hash for Local {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        (&raw const *self.0).addr().hash(state)
+    /// Creates an interior mutability type `Name<Inner>` like [`Self::new_cell`], whose projection
+    /// reads the contents by copy like `Cell::get`.
+    ///
+    /// The projection `p.projection` results in a value instead of a place, so it cannot be
+    /// projected any further, see [`PlaceExpr::compute_projected`]. Only dereferencing the value
+    /// leads to a place again.
+    pub fn new_value_cell(name: &str, projection: &str, inner: Type) -> Self {
+        Self::cell(name, projection, inner, true)
     }
-}
 
-impl PartialOrd for Local {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    fn cell(name: &str, projection: &str, inner: Type, value: bool) -> Self {
+        let display = format!("{name}<{inner}>");
+        let field = Field::new(projection, inner);
+        let fields = Map::from([(field.0.name.clone(), field)]);
+        Self::new(None, None, None, None, fields, display).with_kind(TypeKind::Cell { value })
     }
-}
 
-impl Ord for Local {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        ptr::from_ref(self.0.as_ref())
-            .addr()
-            .cmp(&ptr::from_ref(other.0.as_ref()).addr())
+    /// Creates the place wrapper type `Name<Inner>`, e.g. `MaybeUninit<T>`.
+    ///
+    /// Field and index projections through the wrapper are lifted, which is implemented by
+    /// calling this function again with the projected type. With the `std` feature, wrapper types
+    /// are cached per name and inner type, so calling this twice returns the same type.
+    pub fn wrapper(name: &str, inner: &Type) -> Self {
+        Self::cached_wrapper(name, vec![inner.clone()], 0, false, TypeKind::Opaque)
     }
-}
 
-struct LocalInner {
-    ty: Type,
-    name: Ident,
-}
+    /// Creates the deref-transparent wrapper type `Name<Inner>`, e.g. `ManuallyDrop<T>`, `Rc<T>` or
+    /// `Cow<T>`.
+    ///
+    /// Whether a wrapper is lifting or transparent is up to its author: projections through a
+    /// wrapper created by [`Self::wrapper`] are lifted, i.e. wrapped in `@%Name`, while projections
+    /// through this one insert an implicit dereference just like [`Self::new_deref_transparent`].
+    /// Wrapper types are cached just like with [`Self::wrapper`].
+    pub fn transparent_wrapper(name: &str, inner: &Type) -> Self {
+        Self::cached_wrapper(
+            name,
+            vec![inner.clone()],
+            0,
+            false,
+            TypeKind::DerefTransparent,
+        )
+    }
 
-impl Local {
-    pub fn new(ty: Type, name: &str) -> Self {
-        Self(Arc::new(LocalInner {
-            ty,
-            name: Ident(name.to_string()),
-        }))
+    /// Creates the place wrapper type `Name<A, B, ..>` with multiple type arguments, e.g.
+    /// `Result<T, E>`.
+    ///
+    /// Projections are lifted over the argument at index `lifted`, so for `Result<T, E>` with
+    /// `lifted == 0` a field `f` of `T` has the type `Result<F, E>`, while the other arguments are
+    /// kept. Wrapper types are cached just like with [`Self::wrapper`].
+    pub fn wrapper_with_args(name: &str, args: &[Type], lifted: usize) -> Self {
+        assert!(
+            lifted < args.len(),
+            "the lifted argument {lifted} does not exist"
+        );
+        Self::cached_wrapper(name, args.to_vec(), lifted, false, TypeKind::Opaque)
     }
 
-    pub fn ty(&self) -> Type {
-        self.0.ty.clone()
+    /// Creates the idempotent place wrapper type `Name<Inner>`.
+    ///
+    /// This is the same as [`Self::wrapper`], but [`Self::simplify_wrappers`] is allowed to
+    /// collapse nested layers of the wrapper, i.e. `Name<Name<T>>` is considered to be equivalent
+    /// to `Name<T>`.
+    pub fn idempotent_wrapper(name: &str, inner: &Type) -> Self {
+        Self::cached_wrapper(name, vec![inner.clone()], 0, true, TypeKind::Opaque)
     }
-}
 
-impl Display for Local {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.name.fmt(f)
+    /// Creates the place wrapper type `Name<Inner>` whose projections are resolved by `kind`.
+    ///
+    /// Unlike the other wrappers, this is not cached and `kind` decides for every projection
+    /// whether it is lifted, see [`WrapperKind`]. The projected types are wrapped in a wrapper of
+    /// the same kind again. Like the wrapper function, the kind is not compared, so this type is
+    /// equal to the one created by [`Self::wrapper`]. The cached wrappers around it still keep
+    /// its kind, they tell it apart from every other kind.
+    pub fn custom_wrapper(name: &str, inner: &Type, kind: impl WrapperKind + 'static) -> Self {
+        Self::wrapper_of_kind(name, inner.clone(), Arc::new(kind))
     }
-}
 
-// ----------
+    fn wrapper_of_kind(name: &str, inner: Type, kind: Arc<dyn WrapperKind>) -> Self {
+        let wrapper_name = name.to_string();
+        let wrap_kind = kind.clone();
+        Self::new(
+            Some(inner.clone()),
+            None,
+            Some(Box::new(move |ty| {
+                Type::wrapper_of_kind(&wrapper_name, ty, wrap_kind.clone())
+            })),
+            Some(name.to_string()),
+            Map::new(),
+            format!("{name}<{inner}>"),
+        )
+        .with_inner(|inner| inner.wrapper_kind = Some(kind))
+    }
 
-pub struct Error {
-    place: PlaceExpr,
-    msg: String,
-}
+    /// Returns the kind of this type if it is a wrapper.
+    fn wrapper_kind(&self) -> Option<&dyn WrapperKind> {
+        match &self.0.wrapper_kind {
+            Some(kind) => Some(&**kind),
+            None if self.0.wrapper_wrap.is_none() => None,
+            None if self.0.kind == TypeKind::DerefTransparent => Some(&Transparent),
+            None => Some(&Lifting),
+        }
+    }
 
-impl Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "the type of `{}", self.place)?;
-        if let Some(ty) = self.place.ty() {
-            write!(f, ": {ty}")?;
+    /// Creates a cached wrapper type, `kind` is either [`TypeKind::Opaque`] for place wrappers or
+    /// [`TypeKind::DerefTransparent`].
+    fn cached_wrapper(
+        name: &str,
+        args: Vec<Type>,
+        lifted: usize,
+        idempotent: bool,
+        kind: TypeKind,
+    ) -> Self {
+        TypeCache::global().wrappers.get_or_insert_with(
+            (
+                name.to_string(),
+                args.iter().cloned().map(TypeKey).collect(),
+                lifted,
+                idempotent,
+                kind,
+            ),
+            || {
+                let wrapper_name = name.to_string();
+                let wrap_args = args.clone();
+                let display = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                Self::new(
+                    Some(args[lifted].clone()),
+                    None,
+                    Some(Box::new(move |ty| {
+                        let mut args = wrap_args.clone();
+                        args[lifted] = ty;
+                        Type::cached_wrapper(&wrapper_name, args, lifted, idempotent, kind)
+                    })),
+                    Some(name.to_string()),
+                    Map::new(),
+                    format!("{name}<{}>", display.join(", ")),
+                )
+                .with_inner(|inner| {
+                    inner.idempotent = idempotent;
+                    inner.kind = kind;
+                    // Single argument wrappers are fully described by their target.
+                    if args.len() > 1 {
+                        inner.args = args;
+                        inner.lifted_arg = lifted;
+                    }
+                })
+            },
+        )
+    }
+
+    /// Collapses adjacent layers of the same idempotent place wrapper.
+    ///
+    /// This walks through the place wrappers at the top of this type, so for example
+    /// `MaybeUninit<MaybeUninit<T>>` turns into `MaybeUninit<T>` if `MaybeUninit` was created via
+    /// [`Self::idempotent_wrapper`]. Types that are not place wrappers are returned unchanged and
+    /// wrappers not tagged as idempotent are never collapsed.
+    pub fn simplify_wrappers(&self) -> Type {
+        let mut layers = vec![];
+        let mut ty = self.clone();
+        while ty.is_place_wrapper()
+            && let Some(target) = ty.get_has_place_target()
+        {
+            layers.push(ty);
+            ty = target;
+        }
+        let mut inner_name = None;
+        for layer in layers.into_iter().rev() {
+            let name = layer.wrapper_name();
+            if layer.0.idempotent && name.is_some() && name == inner_name.as_deref() {
+                continue;
+            }
+            inner_name = name.map(str::to_string);
+            ty = layer.wrap_type(ty).unwrap();
         }
-        write!(f, "` {}", self.msg)
+        ty
     }
-}
 
-impl Error {
-    pub fn new(p: &PlaceExpr, msg: &str) -> Self {
-        Self {
-            place: p.clone(),
-            msg: msg.to_string(),
+    /// Returns the names of all generic type parameters occurring in this type.
+    ///
+    /// Just like [`Self::subst`], this recurses through the target, element, fields, variants and
+    /// type arguments, so the inner type of a wrapper is included. The length `N` of an array
+    /// created via [`Self::new_array_generic`] counts as a parameter as well. A type without
+    /// generic parameters is concrete.
+    pub fn generics(&self) -> BTreeSet<String> {
+        fn _do(ty: &Type, generics: &mut BTreeSet<String>) {
+            let this = &*ty.0;
+            if this.kind == TypeKind::Generic {
+                generics.insert(this.name.clone());
+            }
+            let fields = this.fields.values().map(Field::ty);
+            let variants = this.variants.values().cloned();
+            let others = this.has_place_target.iter().chain(&this.array_slice_elem);
+            for inner in others
+                .cloned()
+                .chain(fields)
+                .chain(variants)
+                .chain(this.args.clone())
+            {
+                _do(&inner, generics);
+            }
         }
+        let mut generics = BTreeSet::new();
+        _do(self, &mut generics);
+        generics
     }
-}
 
-/// A place expression.
-///
-/// A place expression is a recursive data structure. The singular leaf node are local variables
-/// (any local variable is a place expression). For the recursive cases, given a place expression
-/// `p`, we can
-/// - dereference it, written as `*p`,
-/// - access a field, written as `p.field`, where `field` is an identifier,
-/// - index into it, written as `p[expr]`, where `expr` is any expression,
-/// - wrap it with a place wrapper, written as `@%Wrapper p`, where `Wrapper` is a `PlaceWrapper`.
-#[derive(Clone, Ord, PartialOrd, PartialEq, Eq)]
-pub enum PlaceExpr {
-    /// Local variable `v`.
-    LocalVar(Local),
-    /// Derefing a place `*p`.
-    Deref(Box<PlaceExpr>),
-    /// Accessing a field `p.field`, `field` can be any identifier.
-    FieldAccess(Box<PlaceExpr>, String),
-    /// Indexing a place `p[42]`, the index can be an arbitrary expression.
-    Index(Box<PlaceExpr>, Expr),
-    /// Wrapping a place, `@%Wrapper p`.
-    Wrap(Box<PlaceExpr>, Type),
-}
+    /// Returns how deeply this type is nested through its target, element and type arguments,
+    /// e.g. 0 for `T`, 1 for `&T` and 2 for `MaybeUninit<[T]>`. Fields are not taken into
+    /// account.
+    pub fn depth(&self) -> usize {
+        let this = &*self.0;
+        let inner = this
+            .has_place_target
+            .iter()
+            .chain(&this.array_slice_elem)
+            .chain(&this.args);
+        inner.map(|ty| ty.depth() + 1).max().unwrap_or(0)
+    }
 
-impl Display for PlaceExpr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            PlaceExpr::Deref(p) => match &**p {
-                PlaceExpr::FieldAccess(..)
-                | PlaceExpr::Deref(..)
-                | PlaceExpr::LocalVar(..)
-                | PlaceExpr::Index(..) => {
-                    write!(f, "*{p}")
-                }
-                PlaceExpr::Wrap(..) => write!(f, "*({p})"),
+    /// Replaces the generic type parameters named in `substs` with the given types.
+    ///
+    /// This recurses through the target, element, fields, variants and type arguments and
+    /// regenerates the display strings. Place wrappers and deref-transparent wrappers are rebuilt
+    /// by calling their wrap function with the substituted inner type. The names of nominal types
+    /// like `Foo<T>` have every occurrence of a parameter replaced. Returns this type unchanged if
+    /// none of the parameters occur in it, see [`Self::generics`]. A layout given via
+    /// [`Self::with_layout`] is kept.
+    pub fn subst(&self, substs: &[(&str, Type)]) -> Type {
+        let generics = self.generics();
+        if !substs.iter().any(|(param, _)| generics.contains(*param)) {
+            return self.clone();
+        }
+        let this = &*self.0;
+        let target = || this.has_place_target.as_ref().unwrap().subst(substs);
+        let element = || this.array_slice_elem.as_ref().unwrap().subst(substs);
+        let fields = |ty: &Type| {
+            ty.fields_in_order()
+                .into_iter()
+                .map(|(name, _)| {
+                    let field = ty.get_field(name).unwrap();
+                    field.with_ty(field.ty().subst(substs))
+                })
+                .collect::<Vec<_>>()
+        };
+        let ty = match this.kind {
+            TypeKind::Never | TypeKind::Dyn => self.clone(),
+            TypeKind::Generic => match substs.iter().find(|(param, _)| *param == this.name) {
+                Some((_, ty)) => ty.clone(),
+                None => self.clone(),
             },
-            PlaceExpr::FieldAccess(p, field) => match &**p {
-                PlaceExpr::FieldAccess(..) | PlaceExpr::LocalVar(..) | PlaceExpr::Index(..) => {
-                    write!(f, "{p}.{field}")
+            TypeKind::Struct => Type::new_struct(&subst_name(&this.name, substs), fields(self)),
+            TypeKind::Union => Type::new_union(&subst_name(&this.name, substs), fields(self)),
+            TypeKind::Tuple(len) => Type::new_tuple(
+                (0..len).map(|i| self.get_field(&i.to_string()).unwrap().ty().subst(substs)),
+            ),
+            TypeKind::Ref(Mutability::Not) => Type::new_ref(target()),
+            TypeKind::Ref(Mutability::Mut) => Type::new_mut_ref(target()),
+            TypeKind::RawPtr(m) => Type::new_raw_ptr(target(), m == Mutability::Mut),
+            TypeKind::Array(len) => Type::new_array(element(), len),
+            TypeKind::GenericArray => Type::new_array_generic(
+                element(),
+                ArrayLen::Generic(self.array_len_param().unwrap()),
+            ),
+            TypeKind::Enum => Type::new_enum(
+                &subst_name(&this.name, substs),
+                self.sorted_variants()
+                    .into_iter()
+                    .map(|(name, variant)| (name.to_string(), fields(variant)))
+                    .collect(),
+            ),
+            TypeKind::DerefTransparent => (this.wrapper_wrap.as_ref().unwrap())(target()),
+            TypeKind::Pin => Type::new_pin(this.args[0].subst(substs)),
+            TypeKind::Cell { value } => {
+                let (name, _) = this.name.split_once('<').unwrap();
+                let projection = self.cell_projection().unwrap();
+                let inner = self.get_field(projection).unwrap().ty();
+                Type::cell(name, projection, inner.subst(substs), value)
+            }
+            TypeKind::Opaque if self.is_place_wrapper() && !this.args.is_empty() => {
+                let args = this.args.iter().map(|a| a.subst(substs)).collect();
+                let name = self.wrapper_name().unwrap();
+                Type::cached_wrapper(
+                    name,
+                    args,
+                    this.lifted_arg,
+                    this.idempotent,
+                    TypeKind::Opaque,
+                )
+            }
+            TypeKind::Opaque if self.is_place_wrapper() => self.wrap_type(target()).unwrap(),
+            TypeKind::Opaque => Type::new(
+                this.has_place_target.as_ref().map(|_| target()),
+                this.array_slice_elem.as_ref().map(|_| element()),
+                None,
+                None,
+                fields(self)
+                    .into_iter()
+                    .map(|f| (f.0.name.clone(), f))
+                    .collect(),
+                subst_name(&this.name, substs),
+            )
+            .with_field_order(this.field_order.clone()),
+        };
+        let ty = match this.kind {
+            TypeKind::Never | TypeKind::Dyn | TypeKind::Generic => ty,
+            // The constructors above only know the shape of their kind, so what was added to it,
+            // e.g. the target of a smart pointer, is carried over.
+            _ => {
+                let mut inner = TypeInner::clone(&ty.0);
+                if inner.has_place_target.is_none() && this.has_place_target.is_some() {
+                    inner.has_place_target = Some(target());
                 }
-                PlaceExpr::Wrap(..) | PlaceExpr::Deref(..) => write!(f, "({p}).{field}"),
-            },
-            PlaceExpr::Index(p, i) => match &**p {
-                PlaceExpr::Deref(..) | PlaceExpr::Wrap(..) => write!(f, "({p})[{i}]"),
-                PlaceExpr::FieldAccess(..) | PlaceExpr::LocalVar(..) | PlaceExpr::Index(..) => {
-                    write!(f, "{p}[{i}]")
+                if inner.wrapper_wrap.is_none() {
+                    inner.wrapper_wrap = this.wrapper_wrap.clone();
+                    inner.wrapper_name = this.wrapper_name.clone();
                 }
-            },
-            PlaceExpr::LocalVar(var) => write!(f, "{var}"),
-            PlaceExpr::Wrap(p, ty) => write!(f, "@%{} {p}", ty.wrapper_name().unwrap()),
+                inner.deref_when = inner.deref_when.or(this.deref_when);
+                inner.layout = inner.layout.or(this.layout);
+                Type(Arc::new(inner))
+            }
+        };
+        if ty == *self {
+            return self.clone();
+        }
+        match &this.explanation {
+            Some(explanation) if this.kind != TypeKind::Generic && ty.0.explanation.is_none() => {
+                ty.with_explanation(explanation.clone())
+            }
+            _ => ty,
         }
     }
-}
-
-#[derive(Eq, Hash, PartialEq)]
-pub enum Context {
-    Local(Local),
-    Field(Type, Field),
-}
 
-impl Display for Context {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Context::Local(local) => write!(f, "{}: {}", local, local.ty()),
-            Context::Field(ty, field) => write!(f, "{}.{}: {}", ty, field.0.name, field.ty()),
-        }
+    /// Creates a deref-transparent wrapper type like `Box<T>`.
+    ///
+    /// In contrast to place wrappers like `MaybeUninit<T>`, field and index projections through
+    /// such a wrapper insert an implicit dereference, just like they do for `&T`, and the result is
+    /// not wrapped again. Consequently, it cannot be used in a place wrapper expression
+    /// `@%Wrapper p`.
+    pub fn new_deref_transparent(
+        wrapper_name: &str,
+        target: Type,
+        wrapper_wrap: Box<dyn Fn(Type) -> Type + Send + Sync>,
+    ) -> Self {
+        let name = format!("{wrapper_name}<{target}>");
+        Self::new(
+            Some(target),
+            None,
+            Some(wrapper_wrap),
+            Some(wrapper_name.to_string()),
+            Map::new(),
+            name,
+        )
+        .with_kind(TypeKind::DerefTransparent)
     }
-}
 
-impl PlaceExpr {
-    pub fn context(&self) -> HashSet<Context> {
-        fn _do(this: &PlaceExpr, ctx: &mut HashSet<Context>) {
-            match this {
-                PlaceExpr::Deref(p) => _do(p, ctx),
-                PlaceExpr::FieldAccess(p, field) => {
-                    if let Some(ty) = p.ty()
-                        && let Some(field) = ty.get_field(field)
-                    {
-                        ctx.insert(Context::Field(ty, field));
-                    }
-                    _do(p, ctx);
-                }
-                PlaceExpr::Index(p, _) => _do(p, ctx),
-                PlaceExpr::LocalVar(var) => {
-                    ctx.insert(Context::Local(var.clone()));
-                }
-                PlaceExpr::Wrap(p, _) => _do(p, ctx),
+    /// Returns the name identifying this type for comparisons, if it is identified by name.
+    ///
+    /// Opaque types without any structure can only be told apart by name, so they are treated as
+    /// nominal as well.
+    fn nominal_name(&self) -> Option<&str> {
+        let this = &*self.0;
+        match this.kind {
+            TypeKind::Generic
+            | TypeKind::Struct
+            | TypeKind::Union
+            | TypeKind::Enum
+            | TypeKind::Cell { .. } => Some(&this.name),
+            TypeKind::Opaque
+                if this.has_place_target.is_none()
+                    && this.array_slice_elem.is_none()
+                    && this.wrapper_name.is_none()
+                    && this.fields.is_empty() =>
+            {
+                Some(&this.name)
             }
+            _ => None,
         }
-        let mut locals = HashSet::new();
-        _do(self, &mut locals);
-        locals
     }
 
-    fn wrap_in_place(&mut self, wrapper: Type) {
-        let this: *mut Self = self;
-        let b = Box::new_uninit();
-        // SAFETY: `this` comes from a mutable reference and we write a value back later without
-        // panicking.
-        let val = unsafe { this.read() };
-        let b = Box::write(b, val);
-        let val = Self::Wrap(b, wrapper);
-        // SAFETY: `this` comes from a mutable reference and we moved the value out before.
-        unsafe { this.write(val) };
+    fn sorted_fields(&self) -> BTreeMap<&str, (Type, Visibility, Option<usize>)> {
+        self.0
+            .fields
+            .iter()
+            .map(|(name, field)| (&*name.0, (field.ty(), field.0.vis, field.0.offset)))
+            .collect()
     }
 
-    fn deref_in_place(&mut self) {
-        let this: *mut Self = self;
-        let b = Box::new_uninit();
-        // SAFETY: `this` comes from a mutable reference and we write a value back later without
-        // panicking.
-        let val = unsafe { this.read() };
-        let b = Box::write(b, val);
-        let val = Self::Deref(b);
-        // SAFETY: `this` comes from a mutable reference and we moved the value out before.
-        unsafe { this.write(val) };
+    fn sorted_variants(&self) -> BTreeMap<&str, &Type> {
+        self.0
+            .variants
+            .iter()
+            .map(|(name, ty)| (&*name.0, ty))
+            .collect()
     }
 
-    fn strip_wrap_then_deref(&mut self) {
-        assert!(matches!(self, Self::Deref(p) if matches!(**p, Self::Wrap(..))));
-        let this: *mut Self = self;
-        // SAFETY: `this` comes from a mutable reference and we write a value back later without
-        // panicking.
-        let val = unsafe { this.read() };
-        let Self::Deref(p) = val else {
-            unsafe { unreachable_unchecked() }
-        };
-        let Self::Wrap(p, wrapper) = *p else {
-            unsafe { unreachable_unchecked() }
-        };
-        unsafe { this.write(*p) };
+    /// Sets the kind of a freshly constructed type.
+    fn with_kind(self, kind: TypeKind) -> Self {
+        self.with_inner(|inner| inner.kind = kind)
+    }
+
+    /// Sets the declaration order of the fields of a freshly constructed type.
+    fn with_field_order(self, order: Vec<Ident>) -> Self {
+        debug_assert_eq!(order.len(), self.0.fields.len());
+        self.with_inner(|inner| inner.field_order = order)
+    }
+
+    /// Returns the fields in declaration order.
+    ///
+    /// Types constructed via [`Type::new`] have no declaration order, their fields are sorted by
+    /// name instead.
+    fn fields_in_order(&self) -> Vec<(&str, Type)> {
+        self.0
+            .field_order
+            .iter()
+            .map(|name| (&*name.0, self.0.fields[name].ty()))
+            .collect()
+    }
+
+    /// Modifies a freshly constructed type.
+    fn with_inner(mut self, f: impl FnOnce(&mut TypeInner)) -> Self {
+        f(Arc::get_mut(&mut self.0).expect("type should not be shared yet"));
+        self
+    }
+
+    /// Returns the mutability of this type if it is a reference or a raw pointer.
+    fn ptr_mutability(&self) -> Option<Mutability> {
+        match self.0.kind {
+            TypeKind::Ref(m) | TypeKind::RawPtr(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    fn tuple_len(&self) -> Option<usize> {
+        match self.0.kind {
+            TypeKind::Tuple(len) => Some(len),
+            _ => None,
+        }
+    }
+
+    fn array_len(&self) -> Option<usize> {
+        match self.0.kind {
+            TypeKind::Array(len) => Some(len),
+            _ => None,
+        }
+    }
+
+    /// Returns the const generic length parameter of an array created via
+    /// [`Type::new_array_generic`].
+    fn array_len_param(&self) -> Option<String> {
+        match self.0.kind {
+            TypeKind::GenericArray => Some(self.0.args[0].to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns whether this type is a place wrapper, i.e. whether field and index projections
+    /// through it are lifted.
+    fn is_place_wrapper(&self) -> bool {
+        self.0.wrapper_wrap.is_some() && self.0.kind != TypeKind::DerefTransparent
+    }
+
+    /// Returns whether this type implements `Deref`, i.e. it can be dereferenced and is neither a
+    /// raw pointer nor a place wrapper.
+    fn implements_deref(&self) -> bool {
+        self.get_has_place_target().is_some() && !self.is_raw_ptr() && !self.is_place_wrapper()
+    }
+
+    /// Returns the pointer `P` of `Pin<P>` if this is a pin that cannot be dereferenced.
+    fn pin_without_deref(&self) -> Option<&Type> {
+        match (self.0.kind, &self.0.args[..]) {
+            (TypeKind::Pin, [pointer]) if self.get_has_place_target().is_none() => Some(pointer),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the projection to the contents of this type if it is a cell.
+    fn cell_projection(&self) -> Option<&str> {
+        match self.0.kind {
+            TypeKind::Cell { .. } => self.0.fields.keys().next().map(|name| &*name.0),
+            _ => None,
+        }
+    }
+
+    /// Returns the first type reachable through implicit dereferences of this type that also has
+    /// the field `field`, along with that field.
+    ///
+    /// Auto-deref never goes through raw pointers or cells, so neither does this.
+    fn shadowed_field(&self, field: &str) -> Option<(Type, Field)> {
+        let mut ty = self.clone();
+        while !ty.is_raw_ptr()
+            && ty.cell_projection().is_none()
+            && let Some(target) = ty.get_has_place_target()
+        {
+            if let Some(field) = target.get_field(field) {
+                return Some((target, field));
+            }
+            ty = target;
+        }
+        None
+    }
+
+    /// Returns whether this type can have fields, i.e. whether it is a struct, a union, a tuple or
+    /// a cell.
+    fn has_fields(&self) -> bool {
+        matches!(
+            self.0.kind,
+            TypeKind::Struct | TypeKind::Union | TypeKind::Tuple(_) | TypeKind::Cell { .. }
+        ) || !self.0.fields.is_empty()
+    }
+
+    /// Returns whether the condition set via [`TypeBuilder::deref_when`] holds for the target,
+    /// which is the case for all types without a condition.
+    fn deref_condition_holds(&self) -> bool {
+        match (self.0.deref_when, &self.0.has_place_target) {
+            (Some(condition), Some(target)) => condition(target),
+            _ => true,
+        }
+    }
+
+    /// Returns the kind of this type for a [`PlaceTyError::IndexOnNonIndexable`].
+    fn non_indexable_kind(&self) -> NonIndexableKind {
+        match self.0.kind {
+            TypeKind::Union => NonIndexableKind::Union,
+            TypeKind::Enum => NonIndexableKind::Enum,
+            TypeKind::Tuple(_) => NonIndexableKind::Tuple,
+            _ if self.has_fields() => NonIndexableKind::Struct,
+            _ => NonIndexableKind::Scalar,
+        }
+    }
+
+    fn is_never(&self) -> bool {
+        self.0.kind == TypeKind::Never
+    }
+
+    fn is_dyn(&self) -> bool {
+        self.0.kind == TypeKind::Dyn
+    }
+
+    fn is_union(&self) -> bool {
+        self.0.kind == TypeKind::Union
+    }
+
+    /// Returns whether this is a cell named `UnsafeCell`, whose contents are only reachable in
+    /// `unsafe` code.
+    fn is_unsafe_cell(&self) -> bool {
+        matches!(self.0.kind, TypeKind::Cell { .. }) && self.0.name.starts_with("UnsafeCell<")
+    }
+
+    /// Returns whether this is a cell whose projection results in a value, see
+    /// [`Type::new_value_cell`].
+    fn is_value_cell(&self) -> bool {
+        self.0.kind == TypeKind::Cell { value: true }
+    }
+
+    /// Returns whether this is the type `usize`, the only type that can be used as an index.
+    fn is_usize(&self) -> bool {
+        self.nominal_name() == Some("usize")
+    }
+
+    fn is_raw_ptr(&self) -> bool {
+        matches!(self.0.kind, TypeKind::RawPtr(_))
+    }
+
+    /// Returns whether this is a slice type `[T]`, which is indexable but has no length.
+    fn is_slice(&self) -> bool {
+        let this = &*self.0;
+        this.kind == TypeKind::Opaque
+            && this.has_place_target.is_none()
+            && this.array_slice_elem.is_some()
+    }
+
+    fn get_has_place_target(&self) -> Option<Type> {
+        self.0.has_place_target.clone()
+    }
+
+    fn get_array_or_slice_element(&self) -> Option<Type> {
+        self.0.array_slice_elem.clone()
+    }
+
+    /// Returns the type of indexing this type with a range.
+    ///
+    /// Arrays turn into slices, everything else that can be indexed stays the same.
+    fn get_subslice(&self) -> Option<Type> {
+        let elem = self.get_array_or_slice_element()?;
+        match self.0.kind {
+            TypeKind::Array(_) | TypeKind::GenericArray => Some(Type::new_slice(elem)),
+            _ => Some(self.clone()),
+        }
+    }
+
+    fn wrap_type(&self, compute_ty: Type) -> Option<Type> {
+        if self.0.kind == TypeKind::DerefTransparent {
+            return None;
+        }
+        let ty = self.0.wrapper_wrap.as_ref().map(|wrap| wrap(compute_ty))?;
+        match &self.0.explanation {
+            Some(explanation)
+                if ty.0.explanation.is_none() && ty.wrapper_name() == self.wrapper_name() =>
+            {
+                Some(ty.with_explanation(explanation.clone()))
+            }
+            _ => Some(ty),
+        }
+    }
+
+    fn get_field(&self, field: &str) -> Option<Field> {
+        self.0.fields.get(field).cloned()
+    }
+
+    fn get_variant(&self, variant: &str) -> Option<Type> {
+        self.0.variants.get(variant).cloned()
+    }
+
+    /// Returns the names of the variants that have the field `field`, sorted by name.
+    fn variants_with_field(&self, field: &str) -> Vec<String> {
+        self.sorted_variants()
+            .into_iter()
+            .filter(|(_, ty)| ty.get_field(field).is_some())
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    /// Returns the name of the wrapper if this is a place wrapper or a deref-transparent wrapper,
+    /// e.g. `MaybeUninit` for `MaybeUninit<T>`.
+    pub fn wrapper_name(&self) -> Option<&str> {
+        self.0.wrapper_name.as_deref()
+    }
+
+    /// Returns the target of dereferencing this type if it is a pointer, i.e. a reference, a raw
+    /// pointer or a deref-transparent wrapper like `Box<T>`.
+    ///
+    /// Place wrappers like `MaybeUninit<T>` return `None`, see [`Self::is_lifting_wrapper`].
+    pub fn deref_target(&self) -> Option<&Type> {
+        if self.is_place_wrapper() {
+            return None;
+        }
+        self.0.has_place_target.as_ref()
+    }
+
+    /// Returns the names and types of the fields of this struct, union or tuple in declaration
+    /// order.
+    ///
+    /// Types constructed via [`Type::new`] have no declaration order, their fields are sorted by
+    /// name instead. Enums have no fields themselves, only their variants do.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Type)> {
+        self.0
+            .field_order
+            .iter()
+            .map(|name| (&*name.0, &self.0.fields[name].0.ty))
+    }
+
+    /// Returns the type of the field `name`, or `None` if this type has no such field.
+    pub fn field(&self, name: &str) -> Option<&Type> {
+        self.0.fields.get(name).map(|field| &field.0.ty)
+    }
+
+    /// Returns whether this type can be dereferenced, see [`Self::deref_target`].
+    pub fn is_dereferenceable(&self) -> bool {
+        self.deref_target().is_some()
+    }
+
+    /// Returns whether this type is a place wrapper like `MaybeUninit<T>`, whose contents are
+    /// reached by lifting the wrapper over the projection instead of dereferencing it.
+    pub fn is_lifting_wrapper(&self) -> bool {
+        self.is_place_wrapper()
+    }
+
+    /// Returns how a field access `p.field` is desugared for a place `p` of this type, following
+    /// the rules of [`PlaceExpr::compute_ty`]:
+    ///
+    /// - If this type has the field, it is projected directly.
+    /// - Otherwise, a dereference is inserted and the field is looked up in the target, which is
+    ///   repeated until it is found. Raw pointers, cells and types that cannot be dereferenced
+    ///   stop this, so the field access is an error.
+    /// - Afterwards, the dereferenced place wrappers are lifted over the projection, starting
+    ///   with the innermost dereference, until the first dereference of something other than a
+    ///   place wrapper. So the field of `&MaybeUninit<Struct>` is lifted over `MaybeUninit`,
+    ///   while the reference in `MaybeUninit<&Struct>` stops the lifting. Wrappers created via
+    ///   [`Self::custom_wrapper`] decide this via their [`WrapperKind`].
+    pub fn projection_behavior(&self, field: &str) -> ProjectionBehavior {
+        let mut proj = Projection::Field(field.to_string());
+        let mut derefs = vec![];
+        let mut ty = self.clone();
+        while proj.resolve(&ty).is_none() {
+            if ty.is_raw_ptr() || ty.cell_projection().is_some() {
+                return ProjectionBehavior::Error;
+            }
+            let Some(target) = ty.get_has_place_target() else {
+                return ProjectionBehavior::Error;
+            };
+            let Ok(lift) = proj.through_wrapper(&ty, &target) else {
+                return ProjectionBehavior::Error;
+            };
+            derefs.push((ty, lift));
+            ty = target;
+        }
+        let lifted = derefs
+            .iter()
+            .rev()
+            .map_while(|(ty, lift)| lift.then(|| ty.wrapper_name().unwrap().to_string()))
+            .collect::<Vec<_>>();
+        match (derefs.len(), lifted.is_empty()) {
+            (0, _) => ProjectionBehavior::Project,
+            (derefs, true) => ProjectionBehavior::DerefThenProject { derefs },
+            (derefs, false) => ProjectionBehavior::Lift { derefs, lifted },
+        }
+    }
+
+    /// Returns whether the two types are structurally equal, just like `==`.
+    ///
+    /// This ignores the display string of non-nominal types and the wrapper function, so two code
+    /// paths formatting the same type differently still produce equal types.
+    pub fn structurally_eq(&self, other: &Type) -> bool {
+        self == other
+    }
+
+    /// Returns whether the two types have the same display string, regardless of their structure.
+    pub fn display_eq(&self, other: &Type) -> bool {
+        self.0.name == other.0.name
+    }
+
+    /// Returns this type displayed as `display`, with its structure unchanged.
+    ///
+    /// The display string of structs, enums, unions, cells and generic parameters is also their
+    /// name, so for them this returns a different type.
+    pub fn with_display(&self, display: impl Into<String>) -> Type {
+        let mut inner = TypeInner::clone(&self.0);
+        inner.name = display.into();
+        Type(Arc::new(inner))
+    }
+
+    /// Returns this type with a human-readable note on why it behaves the way it does, e.g.
+    /// "lifted because the outer value may be uninitialized" for `MaybeUninit<T>`.
+    ///
+    /// The note is meant for custom wrappers: [`PlaceExpr::context`] shows it as a
+    /// [`Context::Explanation`] wherever a place of this type is dereferenced or the wrapper is
+    /// lifted. Lifting a place wrapper passes the note on to the wrapper it results in, and
+    /// [`Self::subst`] keeps it. The note is ignored when comparing types, but not by the
+    /// [`TypeCache`], so a cached wrapper around this type keeps it.
+    pub fn with_explanation(&self, explanation: impl Into<String>) -> Type {
+        let mut inner = TypeInner::clone(&self.0);
+        inner.explanation = Some(explanation.into());
+        Type(Arc::new(inner))
+    }
+
+    /// Returns the note given via [`Self::with_explanation`] or [`TypeBuilder::explanation`].
+    pub fn explanation(&self) -> Option<&str> {
+        self.0.explanation.as_deref()
+    }
+
+    /// Returns this type with its display string rebuilt from its structure, e.g. `&T`, `[T; 4]`
+    /// or `MaybeUninit<T>`.
+    ///
+    /// The types this type is built from are displayed as they are. Named types like structs and
+    /// generic parameters, as well as opaque types constructed via [`Type::new`] that are neither
+    /// wrappers nor slices, keep their display string.
+    pub fn regenerate_display(&self) -> Type {
+        let this = &*self.0;
+        let target = || this.has_place_target.as_ref().unwrap();
+        let element = || this.array_slice_elem.as_ref().unwrap();
+        let display = match this.kind {
+            TypeKind::Ref(Mutability::Not) => format!("&{}", target()),
+            TypeKind::Ref(Mutability::Mut) => format!("&mut {}", target()),
+            TypeKind::RawPtr(Mutability::Not) => format!("*const {}", target()),
+            TypeKind::RawPtr(Mutability::Mut) => format!("*mut {}", target()),
+            TypeKind::Array(len) => format!("[{}; {len}]", element()),
+            TypeKind::GenericArray => format!("[{}; {}]", element(), this.args[0]),
+            TypeKind::Tuple(1) => format!("({},)", self.get_field("0").unwrap().ty()),
+            TypeKind::Tuple(len) => {
+                let elems = (0..len)
+                    .map(|i| self.get_field(&i.to_string()).unwrap().ty().to_string())
+                    .collect::<Vec<_>>();
+                format!("({})", elems.join(", "))
+            }
+            TypeKind::Pin => format!("Pin<{}>", this.args[0]),
+            TypeKind::Opaque | TypeKind::DerefTransparent if this.wrapper_name.is_some() => {
+                let args = match &this.args[..] {
+                    [] => vec![target().to_string()],
+                    args => args.iter().map(|a| a.to_string()).collect(),
+                };
+                format!(
+                    "{}<{}>",
+                    this.wrapper_name.as_ref().unwrap(),
+                    args.join(", ")
+                )
+            }
+            TypeKind::Opaque
+                if this.has_place_target.is_none() && this.array_slice_elem.is_some() =>
+            {
+                format!("[{}]", element())
+            }
+            _ => return self.clone(),
+        };
+        self.with_display(display)
+    }
+}
+
+#[derive(Clone)]
+pub struct Field(Arc<FieldInner>);
+
+impl Eq for Field {}
+
+/// Fields are compared structurally, just like types, since the constructors of types may return
+/// a cached, structurally equal type with different `Field`s.
+impl PartialEq for Field {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+            || (self.0.name == other.0.name
+                && self.0.vis == other.0.vis
+                && self.0.offset == other.0.offset
+                && self.0.ty == other.0.ty)
+    }
+}
+impl Hash for Field {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.name.hash(state)
+    }
+}
+
+struct FieldInner {
+    ty: Type,
+    name: Ident,
+    vis: Visibility,
+    offset: Option<usize>,
+}
+
+impl fmt::Debug for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Field")
+            .field("name", &self.0.name.0)
+            .field("ty", &self.0.ty)
+            .field("vis", &self.0.vis)
+            .field("offset", &self.0.offset)
+            .finish()
+    }
+}
+
+impl Field {
+    /// Creates a public field without a known offset.
+    pub fn new(name: &str, ty: Type) -> Self {
+        Self::new_full(name, ty, Visibility::Public, None)
+    }
+
+    /// Creates a field with the given visibility and byte offset into its type.
+    pub fn new_full(name: &str, ty: Type, vis: Visibility, offset: Option<usize>) -> Self {
+        Self(Arc::new(FieldInner {
+            ty,
+            name: Ident(name.to_string()),
+            vis,
+            offset,
+        }))
+    }
+
+    /// Returns the name of this field.
+    pub fn name(&self) -> &str {
+        &self.0.name.0
+    }
+
+    /// Returns the declared type of this field.
+    pub fn ty(&self) -> Type {
+        self.0.ty.clone()
+    }
+
+    /// Returns the visibility of this field.
+    pub fn visibility(&self) -> Visibility {
+        self.0.vis
+    }
+
+    /// Returns the byte offset of this field into its type, if known.
+    pub fn offset(&self) -> Option<usize> {
+        self.0.offset
+    }
+
+    /// Returns this field with its type replaced by `ty`, keeping the other metadata.
+    fn with_ty(&self, ty: Type) -> Self {
+        Self::new_full(self.name(), ty, self.0.vis, self.0.offset)
+    }
+}
+
+/// The visibility of a [`Field`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Visibility {
+    /// The field can be accessed from everywhere.
+    #[default]
+    Public,
+    /// The field can only be accessed from the module of its type, accessing it is recorded as a
+    /// [`Context::PrivateField`].
+    Private,
+}
+
+/// A list of fields in declaration order, collected from `(name, type)` pairs or [`Field`]s.
+///
+/// It can be passed to [`Type::new_struct`] and the other constructors taking fields.
+#[derive(Clone, Default)]
+pub struct Fields(Vec<Field>);
+
+impl<S: AsRef<str>> FromIterator<(S, Type)> for Fields {
+    fn from_iter<I: IntoIterator<Item = (S, Type)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(name, ty)| Field::new(name.as_ref(), ty))
+                .collect(),
+        )
+    }
+}
+
+impl FromIterator<Field> for Fields {
+    fn from_iter<I: IntoIterator<Item = Field>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Fields {
+    type Item = Field;
+    type IntoIter = alloc::vec::IntoIter<Field>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// A local variable.
+///
+/// Local variables are compared by identity, two locals with the same name and type are still
+/// different variables.
+#[derive(Clone)]
+pub struct Local(Arc<LocalInner>, Option<Location>);
+
+impl Eq for Local {}
+
+impl PartialEq for Local {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Hash for Local {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        (&raw const *self.0).addr().hash(state)
+    }
+}
+
+impl PartialOrd for Local {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Locals are ordered by name, so sorting them is reproducible, and only locals with the same name
+// fall back to the order of their addresses.
+impl Ord for Local {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.name.0.cmp(&other.0.name.0).then_with(|| {
+            ptr::from_ref(self.0.as_ref())
+                .addr()
+                .cmp(&ptr::from_ref(other.0.as_ref()).addr())
+        })
+    }
+}
+
+struct LocalInner {
+    ty: Type,
+    name: Ident,
+    mutable: bool,
+    /// Whether this is an anonymous temporary, see [`Local::new_temp`].
+    temp: bool,
+}
+
+impl Local {
+    pub fn new(ty: impl Into<Type>, name: &str) -> Self {
+        Self(
+            Arc::new(LocalInner {
+                ty: ty.into(),
+                name: Ident(name.to_string()),
+                mutable: false,
+                temp: false,
+            }),
+            None,
+        )
+    }
+
+    /// Creates a local variable declared as `let mut name: ty`.
+    pub fn new_mut(ty: impl Into<Type>, name: &str) -> Self {
+        Self(
+            Arc::new(LocalInner {
+                ty: ty.into(),
+                name: Ident(name.to_string()),
+                mutable: true,
+                temp: false,
+            }),
+            None,
+        )
+    }
+
+    /// Creates an anonymous temporary of type `ty`, e.g. the value of `&x` in `(&x).field`.
+    ///
+    /// A place starting at a temporary is computed just like one starting at a local variable.
+    /// It is displayed as `(_: ty)`, which is also how `place_expr!` writes it, and it is mutable
+    /// like every temporary.
+    pub fn new_temp(ty: impl Into<Type>) -> Self {
+        Self(
+            Arc::new(LocalInner {
+                ty: ty.into(),
+                name: Ident("_".to_string()),
+                mutable: true,
+                temp: true,
+            }),
+            None,
+        )
+    }
+
+    /// Returns whether this is a temporary created via [`Self::new_temp`].
+    pub fn is_temp(&self) -> bool {
+        self.0.temp
+    }
+
+    pub fn ty(&self) -> Type {
+        self.0.ty.clone()
+    }
+
+    /// Returns whether this local variable is declared `mut`.
+    pub fn is_mut(&self) -> bool {
+        self.0.mutable
+    }
+}
+
+impl Display for Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.temp {
+            return write!(f, "(_: {})", self.0.ty);
+        }
+        self.0.name.fmt(f)
+    }
+}
+
+impl fmt::Debug for Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Local")
+            .field("name", &self.0.name.0)
+            .field("ty", &self.0.ty)
+            .field("mutable", &self.0.mutable)
+            .field("temp", &self.0.temp)
+            .finish()
+    }
+}
+
+/// The value a place expression starts at, see [`PlaceExpr::place_base`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlaceBase {
+    /// A named local variable.
+    Local(Local),
+    /// An anonymous temporary of the given type, see [`Local::new_temp`].
+    Temp(Type),
+}
+
+// ----------
+
+/// A place expression.
+///
+/// A place expression is a recursive data structure. The singular leaf node are local variables
+/// (any local variable is a place expression). For the recursive cases, given a place expression
+/// `p`, we can
+/// - dereference it, written as `*p`,
+/// - access a field, written as `p.field`, where `field` is an identifier,
+/// - index into it, written as `p[expr]`, where `expr` is any expression or a local variable,
+/// - index into it with a range, written as `p[a..b]`, where `a` and `b` are optional,
+/// - downcast it to an enum variant, written as `(p as Variant)`,
+/// - wrap it with a place wrapper, written as `@%Wrapper p`, where `Wrapper` is a `PlaceWrapper`.
+///
+/// A stack of place wrapper expressions is checked from the inside out: every `@%Wrapper p`
+/// undoes the closest dereference of a place wrapper in `p` that is not undone yet, and has to
+/// name that wrapper. So the stack lists the wrappers in the order they appear in the type, e.g.
+/// `@%A @%B **p` with `p: A<B<T>>` has the type `A<B<T>>`, while the transposed stack
+/// `@%B @%A **p` results in a [`PlaceTyError::WrapperAnnotationMismatch`] for `@%A`.
+///
+/// Cloning a place expression copies its projections, but shares its types and local variables
+/// with the original.
+///
+/// The alternate display `{:#}` marks the dereferences inserted by [`PlaceExpr::compute_ty`] as
+/// `*⟨auto⟩` and the ones resolved via a bound as `*⟨bound⟩`, the plain display prints them like
+/// any other dereference.
+#[derive(Clone, Debug)]
+pub enum PlaceExpr {
+    /// Local variable `v`.
+    LocalVar(Local),
+    /// Derefing a place `*p`.
+    Deref(Box<PlaceExpr>),
+    /// A dereference `*p` inserted implicitly while desugaring.
+    ///
+    /// It behaves exactly like [`PlaceExpr::Deref`] and compares equal to it, only the alternate
+    /// display tells them apart.
+    AutoDeref(Box<PlaceExpr>),
+    /// A dereference `*p` of a generic parameter that was resolved via a `Deref` bound of
+    /// [`ComputeOptions::generics`], with the target of the bound.
+    ///
+    /// The type of `p` does not know the bound, so [`PlaceExpr::ty`] takes the type of the
+    /// dereference from the recorded target instead. Otherwise it behaves exactly like
+    /// [`PlaceExpr::Deref`] and compares equal to it.
+    BoundDeref(Box<PlaceExpr>, Type),
+    /// Accessing a field `p.field`, `field` can be any identifier.
+    FieldAccess(Box<PlaceExpr>, String),
+    /// Indexing a place `p[42]`, the index can be an arbitrary expression.
+    Index(Box<PlaceExpr>, Expr),
+    /// Indexing a place by the value of a local variable `p[q]`, the local has to be a `usize`.
+    LocalIndex(Box<PlaceExpr>, Local),
+    /// Indexing a place with a range `p[a..b]`, both bounds are optional.
+    Range(Box<PlaceExpr>, Option<Expr>, Option<Expr>),
+    /// Downcasting a place of an enum type to one of its variants `(p as Variant)`.
+    Downcast(Box<PlaceExpr>, String),
+    /// Wrapping a place, `@%Wrapper p`.
+    Wrap(Box<PlaceExpr>, Type),
+}
+
+impl Display for PlaceExpr {
+    // The operands are formatted with `f` itself to pass on the alternate flag.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaceExpr::Deref(p) => {
+                f.write_str("*")?;
+                Operand(p, Precedence::Deref).fmt(f)
+            }
+            PlaceExpr::AutoDeref(p) => {
+                f.write_str(if f.alternate() { "*⟨auto⟩" } else { "*" })?;
+                Operand(p, Precedence::Deref).fmt(f)
+            }
+            PlaceExpr::BoundDeref(p, _) => {
+                f.write_str(if f.alternate() { "*⟨bound⟩" } else { "*" })?;
+                Operand(p, Precedence::Deref).fmt(f)
+            }
+            PlaceExpr::FieldAccess(p, field) => {
+                Operand(p, Precedence::Postfix).fmt(f)?;
+                write!(f, ".{field}")
+            }
+            PlaceExpr::Index(p, i) => {
+                Operand(p, Precedence::Postfix).fmt(f)?;
+                write!(f, "[{i}]")
+            }
+            PlaceExpr::LocalIndex(p, i) => {
+                Operand(p, Precedence::Postfix).fmt(f)?;
+                write!(f, "[{i}]")
+            }
+            PlaceExpr::Range(p, start, end) => {
+                Operand(p, Precedence::Postfix).fmt(f)?;
+                write!(f, "[{}]", RangeDisplay(start, end))
+            }
+            PlaceExpr::Downcast(p, variant) => {
+                // `place_expr!` only accepts a single token tree or a deref of one in front of
+                // `as`, so everything else needs parentheses.
+                let min = match &**p {
+                    PlaceExpr::Deref(q) | PlaceExpr::AutoDeref(q) | PlaceExpr::BoundDeref(q, _)
+                        if q.precedence() == Precedence::Atom =>
+                    {
+                        Precedence::Deref
+                    }
+                    _ => Precedence::Postfix,
+                };
+                f.write_str("(")?;
+                Operand(p, min).fmt(f)?;
+                write!(f, " as {variant})")
+            }
+            PlaceExpr::LocalVar(var) => write!(f, "{var}"),
+            PlaceExpr::Wrap(p, ty) => {
+                match ty.wrapper_name() {
+                    Some(name) => write!(f, "@%{name} ")?,
+                    // Computing the type rejects such a place, but its error still displays it.
+                    None => write!(f, "@%{ty} ")?,
+                }
+                p.fmt(f)
+            }
+        }
+    }
+}
+
+// Places are equal if they are written the same, `AutoDeref` only affects the alternate display
+// and `BoundDeref` only records the type, so they are compared like `Deref`.
+impl PartialEq for PlaceExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp_written(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PlaceExpr {}
+
+// Equal places project from the same local variable in the same number of steps, hashing only
+// those keeps the hash cheap.
+impl Hash for PlaceExpr {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.root_local().hash(state);
+        self.depth().hash(state);
+    }
+}
+
+impl PartialOrd for PlaceExpr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Places are ordered by the components of their [`PlaceExpr::canonical_path`]: the name of their
+/// local variable, then the projections of the desugared place from the local outwards. So places
+/// that only differ in how they are written, like `p.x` and `(*p).x` for a reference `p`, sort
+/// next to each other, and a place sorts right before the places projecting from it.
+///
+/// Comparing desugars both places like [`PlaceExpr::compute_ty`] does, as far as their types can
+/// be computed. Places with the same canonical path are ordered as they are written, so the order
+/// agrees with `==`, which tells them apart.
+impl Ord for PlaceExpr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if ptr::eq(self, other) {
+            return Ordering::Equal;
+        }
+        self.canonical_steps()
+            .cmp(&other.canonical_steps())
+            .then_with(|| self.cmp_written(other))
+    }
+}
+
+/// The outermost step of a place expression without the place it projects from, see
+/// [`PlaceExpr::outermost_step`].
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Step<'a> {
+    /// The name of the local variable, the local itself only breaks ties.
+    Local(&'a str),
+    Deref,
+    Field(&'a str),
+    Index(&'a Expr),
+    LocalIndex(&'a Local),
+    Range(&'a Option<Expr>, &'a Option<Expr>),
+    Downcast(&'a str),
+    Wrap(&'a Type),
+}
+
+/// How tightly a place expression binds when displayed, from loosest to tightest.
+///
+/// The prefix operators `@%Wrapper` and `*` bind looser than the postfix projections, so `*p.f`
+/// is `*(p.f)` and `@%Wrapper *p` is `@%Wrapper (*p)`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Wrap,
+    Deref,
+    Postfix,
+    /// Local variables and downcasts, which are always parenthesized.
+    Atom,
+}
+
+impl PlaceExpr {
+    fn precedence(&self) -> Precedence {
+        match self {
+            Self::Wrap(..) => Precedence::Wrap,
+            Self::Deref(_) | Self::AutoDeref(_) | Self::BoundDeref(..) => Precedence::Deref,
+            Self::FieldAccess(..) | Self::Index(..) | Self::LocalIndex(..) | Self::Range(..) => {
+                Precedence::Postfix
+            }
+            Self::LocalVar(_) | Self::Downcast(..) => Precedence::Atom,
+        }
+    }
+
+    /// Compares the places as they are written: by the names of their local variables and their
+    /// projections from the local outwards, then by their place wrapper expressions and finally
+    /// by the identity of their local variables.
+    fn cmp_written(&self, other: &Self) -> Ordering {
+        fn steps<'a>(chain: &'a [&'a PlaceExpr], wraps: bool) -> impl Iterator<Item = Step<'a>> {
+            chain
+                .iter()
+                .filter(move |place| wraps || !matches!(place, PlaceExpr::Wrap(..)))
+                .map(|place| place.outermost_step())
+        }
+        let (this, that) = (self.chain(), other.chain());
+        steps(&this, false)
+            .cmp(steps(&that, false))
+            .then_with(|| steps(&this, true).cmp(steps(&that, true)))
+            .then_with(|| self.root_local().cmp(other.root_local()))
+    }
+
+    /// Returns the outermost step of this place, treating both kinds of dereferences the same.
+    fn outermost_step(&self) -> Step<'_> {
+        match self {
+            Self::LocalVar(local) => Step::Local(&local.0.name.0),
+            Self::Deref(_) | Self::AutoDeref(_) | Self::BoundDeref(..) => Step::Deref,
+            Self::FieldAccess(_, field) => Step::Field(field),
+            Self::Index(_, index) => Step::Index(index),
+            Self::LocalIndex(_, index) => Step::LocalIndex(index),
+            Self::Range(_, start, end) => Step::Range(start, end),
+            Self::Downcast(_, variant) => Step::Downcast(variant),
+            Self::Wrap(_, wrapper) => Step::Wrap(wrapper),
+        }
+    }
+}
+
+/// Displays the operand of a place expression, adding parentheses if it binds looser than the
+/// given precedence.
+struct Operand<'a>(&'a PlaceExpr, Precedence);
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.precedence() < self.1 {
+            f.write_str("(")?;
+            self.0.fmt(f)?;
+            f.write_str(")")
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+#[derive(Eq, Hash, PartialEq)]
+pub enum Context {
+    Local(Local),
+    Field(Type, Field),
+    /// Dereferencing a place of the given type.
+    ///
+    /// For wrapper types like `Rc<T>`, [`Type::wrapper_name`] tells which wrapper was
+    /// dereferenced.
+    Deref(Type),
+    /// A projection on a place of the given raw pointer type, which is missing an explicit deref.
+    MissingRawDeref(Type),
+    /// Dereferencing a place of type `Pin<P>`, where `P` does not implement `Deref`.
+    PinWithoutDeref(Type),
+    /// A field of the first type that shadows the field with the same name of the second type,
+    /// which is reachable from the first one through implicit dereferences.
+    ShadowedField(Type, Type, Field),
+    /// Lifting the given place wrapper over a projection, i.e. a place wrapper expression.
+    LiftWrapper(Type),
+    /// Indexing into a place of the given type, by a single index or a range.
+    Index(Type),
+    /// A field of the given union type, accessing it requires `unsafe`.
+    UnionField(Type, Field),
+    /// Indexing into a place of the given type, which cannot be indexed and has no dereference
+    /// target that could, see [`PlaceTyError::IndexOnNonIndexable`].
+    NotIndexable(Type),
+    /// Indexing into a place of the given type with an index `^n` counting from its end, whose
+    /// length is not known.
+    IndexFromEnd(Type, Expr),
+    /// A private field of the given type, see [`Visibility::Private`].
+    PrivateField(Type, Field),
+    /// Indexing into an array of the given type whose length is a const generic parameter, so
+    /// the index is not checked against it.
+    SymbolicArrayLen(Type),
+    /// The note explaining the given wrapper, which was dereferenced or lifted in the previous
+    /// step, see [`Type::with_explanation`].
+    Explanation(Type, String),
+}
+
+impl Context {
+    /// Returns the note explaining `ty`, if it has one.
+    fn explanation(ty: Type) -> Option<Context> {
+        let explanation = ty.explanation()?.to_string();
+        Some(Context::Explanation(ty, explanation))
+    }
+}
+
+impl Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Context::Local(local) => write!(f, "{}: {}", local, local.ty()),
+            Context::Field(ty, field) => write!(f, "{}.{}: {}", ty, field.0.name, field.ty()),
+            Context::Deref(ty) => {
+                write!(f, "deref of `{ty}`")?;
+                match (ty.ptr_mutability(), ty.is_raw_ptr()) {
+                    (Some(Mutability::Not), false) => write!(f, " (shared)"),
+                    (Some(Mutability::Mut), false) => write!(f, " (mutable)"),
+                    (Some(_), true) => write!(f, " (raw)"),
+                    (None, _) => match ty.wrapper_name() {
+                        Some(name) => write!(f, " (via `{name}`)"),
+                        None if ty.0.kind == TypeKind::Struct => {
+                            write!(f, " (via user `Deref` impl)")
+                        }
+                        None => Ok(()),
+                    },
+                }
+            }
+            Context::MissingRawDeref(ty) => {
+                write!(f, "explicit deref of raw pointer `{ty}` required")
+            }
+            Context::ShadowedField(ty, deeper, field) => write!(
+                f,
+                "warning: `{ty}.{name}` shadows `{deeper}.{name}: {}`",
+                field.ty(),
+                name = field.0.name
+            ),
+            Context::PinWithoutDeref(ty) => {
+                let pointer = ty.pin_without_deref().unwrap();
+                write!(
+                    f,
+                    "`{ty}` cannot be dereferenced, since `{pointer}` does not implement `Deref`"
+                )
+            }
+            Context::LiftWrapper(wrapper) => match wrapper.wrapper_name() {
+                Some(name) => write!(f, "lift of place wrapper `{name}`"),
+                None => write!(f, "lift of place wrapper `{wrapper}`"),
+            },
+            Context::Index(ty) => write!(f, "index into `{ty}`"),
+            Context::NotIndexable(ty) => write!(
+                f,
+                "`{ty}` cannot be indexed and no indexable type is reachable through dereferences"
+            ),
+            Context::IndexFromEnd(ty, index) => {
+                write!(f, "index `{index}` counts from the end of `{ty}`")
+            }
+            Context::PrivateField(ty, field) => {
+                write!(f, "warning: `{ty}.{}` is private", field.name())
+            }
+            Context::Explanation(_, explanation) => write!(f, "note: {explanation}"),
+            Context::SymbolicArrayLen(ty) => {
+                let len = ty.array_len_param().unwrap();
+                write!(
+                    f,
+                    "`{ty}` has the symbolic length `{len}`, the index is not checked"
+                )
+            }
+            Context::UnionField(ty, field) => write!(
+                f,
+                "{}.{}: {} (union field, requires `unsafe`)",
+                ty,
+                field.0.name,
+                field.ty()
+            ),
+        }
+    }
+}
+
+/// How a field access is desugared, see [`Type::projection_behavior`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProjectionBehavior {
+    /// The field is projected directly.
+    Project,
+    /// The field is projected after inserting `derefs` dereferences.
+    DerefThenProject { derefs: usize },
+    /// The field is projected after inserting `derefs` dereferences and the place wrappers named
+    /// in `lifted` are lifted over the projection, starting with the innermost one.
+    Lift { derefs: usize, lifted: Vec<String> },
+    /// The field cannot be reached.
+    Error,
+}
+
+/// Whether using a place requires `unsafe`, see [`PlaceExpr::safety`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Safety {
+    Safe,
+    /// The place can only be used in `unsafe` code for the given reasons, from the local variable
+    /// outwards.
+    Unsafe(Vec<UnsafeReason>),
+}
+
+/// A projection that requires `unsafe`, carrying the place resulting from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnsafeReason {
+    /// A dereference `*p` of a raw pointer.
+    RawPtrDeref(PlaceExpr),
+    /// A field access `p.field` of a union.
+    UnionField(PlaceExpr),
+    /// The projection `p.projection` to the contents of an `UnsafeCell<T>`.
+    UnsafeCellProjection(PlaceExpr),
+}
+
+/// The implicit operations inserted while desugaring a place expression, see
+/// [`PlaceExpr::desugar_report`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DesugarReport {
+    /// The number of dereferences inserted implicitly.
+    pub auto_deref_count: usize,
+    /// The names of the place wrappers that were lifted over a projection, in the order the
+    /// place wrapper expressions were inserted.
+    pub lifted_wrappers: Vec<String>,
+    /// The number of projections in the place as written, including dereferences and place
+    /// wrapper expressions.
+    pub explicit_projections: usize,
+    /// Whether the place accesses a field of a union, which requires `unsafe`.
+    pub requires_unsafe: bool,
+    /// The fields that were projected to, from the local variable outwards.
+    pub fields: Vec<Field>,
+}
+
+/// The set of types implementing the `PlaceDeref` trait.
+///
+/// `PlaceDeref` governs whether a place of a type can be dereferenced when the resulting place
+/// expression is actually used. References, raw pointers and deref-transparent wrappers always
+/// implement it. Place wrappers like `MaybeUninit<T>` only implement it when registered by name,
+/// since in general dereferencing them is only valid if the result is wrapped again.
+#[derive(Default, Clone)]
+pub struct PlaceDerefImpls {
+    wrappers: Set<String>,
+}
+
+impl PlaceDerefImpls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares that the place wrapper named `wrapper_name` implements `PlaceDeref`.
+    pub fn register_wrapper(&mut self, wrapper_name: &str) -> &mut Self {
+        self.wrappers.insert(wrapper_name.to_string());
+        self
+    }
+
+    /// Returns whether `ty` implements `PlaceDeref`.
+    ///
+    /// Types with a condition set via [`TypeBuilder::deref_when`] implement it if the condition
+    /// holds for their target.
+    pub fn is_implemented_for(&self, ty: &Type) -> bool {
+        let Some(target) = ty.get_has_place_target() else {
+            return false;
+        };
+        if let Some(condition) = ty.0.deref_when {
+            return condition(&target);
+        }
+        if ty.is_place_wrapper() {
+            return ty
+                .wrapper_name()
+                .is_some_and(|name| self.wrappers.contains(name));
+        }
+        true
+    }
+}
+
+impl PlaceExpr {
+    /// Returns the steps taken to compute the type of this place, starting at the local variable.
+    ///
+    /// Every projection contributes its steps after the steps of the place it projects from, so
+    /// for a desugared place the entries follow the desugaring step by step.
+    pub fn context(&self) -> Vec<Context> {
+        fn _do(this: &PlaceExpr, ctx: &mut Vec<Context>) {
+            if let Some(p) = this.base() {
+                _do(p, ctx);
+            }
+            match this {
+                PlaceExpr::Deref(p) | PlaceExpr::AutoDeref(p) | PlaceExpr::BoundDeref(p, _) => {
+                    if let Some(ty) = p.ty() {
+                        if ty.pin_without_deref().is_some() {
+                            ctx.push(Context::PinWithoutDeref(ty));
+                        } else {
+                            ctx.push(Context::Deref(ty.clone()));
+                            ctx.extend(Context::explanation(ty));
+                        }
+                    }
+                }
+                PlaceExpr::FieldAccess(p, field) => {
+                    if let Some(ty) = p.ty() {
+                        if let Some(found) = ty.get_field(field)
+                            && ty.is_union()
+                        {
+                            ctx.push(Context::UnionField(ty, found));
+                        } else if let Some(found) = ty.get_field(field) {
+                            let shadowed = ty.shadowed_field(field);
+                            let private = found.visibility() == Visibility::Private;
+                            ctx.push(Context::Field(ty.clone(), found.clone()));
+                            if private {
+                                ctx.push(Context::PrivateField(ty.clone(), found));
+                            }
+                            if let Some((deeper, shadowed)) = shadowed {
+                                ctx.push(Context::ShadowedField(ty, deeper, shadowed));
+                            }
+                        } else if ty.is_raw_ptr() {
+                            ctx.push(Context::MissingRawDeref(ty));
+                        }
+                    }
+                }
+                PlaceExpr::Index(p, _)
+                | PlaceExpr::LocalIndex(p, _)
+                | PlaceExpr::Range(p, ..)
+                | PlaceExpr::Downcast(p, _) => {
+                    if let Some(ty) = p.ty() {
+                        if ty.is_raw_ptr() {
+                            ctx.push(Context::MissingRawDeref(ty));
+                        } else if !matches!(this, PlaceExpr::Downcast(..)) {
+                            let symbolic = ty.array_len_param().is_some();
+                            ctx.push(Context::Index(ty.clone()));
+                            if ty.get_array_or_slice_element().is_none()
+                                && ty.0.has_place_target.is_none()
+                            {
+                                ctx.push(Context::NotIndexable(ty.clone()));
+                            }
+                            if let PlaceExpr::Index(_, index) = this
+                                && index.from_end().is_some()
+                            {
+                                ctx.push(Context::IndexFromEnd(ty.clone(), index.clone()));
+                            }
+                            if symbolic {
+                                ctx.push(Context::SymbolicArrayLen(ty));
+                            }
+                        }
+                    }
+                    if let PlaceExpr::LocalIndex(_, index) = this {
+                        ctx.push(Context::Local(index.clone()));
+                    }
+                }
+                PlaceExpr::LocalVar(var) => {
+                    ctx.push(Context::Local(var.clone()));
+                }
+                PlaceExpr::Wrap(_, wrapper) => {
+                    ctx.push(Context::LiftWrapper(wrapper.clone()));
+                    ctx.extend(Context::explanation(wrapper.clone()));
+                }
+            }
+        }
+        let mut ctx = vec![];
+        _do(self, &mut ctx);
+        ctx
+    }
+
+    fn wrap_in_place(&mut self, wrapper: Type) {
+        let this: *mut Self = self;
+        let b = Box::new_uninit();
+        // SAFETY: `this` comes from a mutable reference and we write a value back later without
+        // panicking.
+        let val = unsafe { this.read() };
+        let b = Box::write(b, val);
+        let val = Self::Wrap(b, wrapper);
+        // SAFETY: `this` comes from a mutable reference and we moved the value out before.
+        unsafe { this.write(val) };
+    }
+
+    fn deref_in_place(&mut self) {
+        let this: *mut Self = self;
+        let b = Box::new_uninit();
+        // SAFETY: `this` comes from a mutable reference and we write a value back later without
+        // panicking.
+        let val = unsafe { this.read() };
+        let b = Box::write(b, val);
+        let val = Self::AutoDeref(b);
+        // SAFETY: `this` comes from a mutable reference and we moved the value out before.
+        unsafe { this.write(val) };
+    }
+
+    /// Turns this dereference into a [`PlaceExpr::BoundDeref`] with the target of the bound it
+    /// was resolved via.
+    fn record_bound_target(&mut self, target: Type) {
+        assert!(matches!(
+            self,
+            Self::Deref(_) | Self::AutoDeref(_) | Self::BoundDeref(..)
+        ));
+        let this: *mut Self = self;
+        // SAFETY: `this` comes from a mutable reference and we write a value back later without
+        // panicking.
+        let val = unsafe { this.read() };
+        let (Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _)) = val else {
+            unsafe { unreachable_unchecked() }
+        };
+        unsafe { this.write(Self::BoundDeref(p, target)) };
+    }
+
+    /// Replaces the outermost projection of this field or index access with `proj`.
+    fn replace_projection(&mut self, proj: Projection) {
+        assert!(!matches!(
+            self,
+            Self::LocalVar(_)
+                | Self::Deref(_)
+                | Self::AutoDeref(_)
+                | Self::BoundDeref(..)
+                | Self::Wrap(..)
+        ));
+        let this: *mut Self = self;
+        // SAFETY: `this` comes from a mutable reference and we write a value back later without
+        // panicking.
+        let val = unsafe { this.read() };
+        let (Self::FieldAccess(p, _)
+        | Self::Index(p, _)
+        | Self::LocalIndex(p, _)
+        | Self::Range(p, ..)
+        | Self::Downcast(p, _)) = val
+        else {
+            unsafe { unreachable_unchecked() }
+        };
+        unsafe { this.write(proj.apply(p)) };
+    }
+
+    fn strip_wrap_then_deref(&mut self) {
+        assert!(
+            matches!(self, Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) if matches!(**p, Self::Wrap(..)))
+        );
+        let this: *mut Self = self;
+        // SAFETY: `this` comes from a mutable reference and we write a value back later without
+        // panicking.
+        let val = unsafe { this.read() };
+        let (Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _)) = val else {
+            unsafe { unreachable_unchecked() }
+        };
+        let Self::Wrap(p, wrapper) = *p else {
+            unsafe { unreachable_unchecked() }
+        };
+        unsafe { this.write(*p) };
         drop(wrapper);
     }
 
+    /// Returns the mutability of the reference or raw pointer through which this place was last
+    /// dereferenced.
+    ///
+    /// This only looks at the dereference closest to the resulting place, so for `**p` with
+    /// `p: &mut &T` it returns [`Mutability::Not`]. Returns `None` if the place does not go through
+    /// a dereference or if the last dereference is not through a reference or raw pointer. Run
+    /// [`Self::compute_ty`] first, otherwise the implicit dereferences are missing.
+    pub fn deref_mutability(&self) -> Option<Mutability> {
+        match self {
+            Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) => {
+                p.ty()?.ptr_mutability()
+            }
+            _ => self.base()?.deref_mutability(),
+        }
+    }
+
+    /// Returns whether this place is a mutable place, i.e. whether it can be assigned to or
+    /// borrowed mutably.
+    ///
+    /// A place is mutable if its local variable is declared `mut` and every dereference on the
+    /// way goes through a `&mut`, a `*mut` or an owning pointer like `Box<T>` rather than a `&`
+    /// or a `*const`. Run [`Self::compute_ty`] first to take the implicit dereferences into
+    /// account, returns `None` if the type of a dereferenced place is unknown.
+    pub fn is_mutable_place(&self) -> Option<bool> {
+        match self {
+            Self::LocalVar(local) => Some(local.is_mut()),
+            Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) => {
+                let through_shared = p.ty()?.ptr_mutability() == Some(Mutability::Not);
+                Some(p.is_mutable_place()? && !through_shared)
+            }
+            _ => self.base()?.is_mutable_place(),
+        }
+    }
+
+    /// Returns whether this place can only be used in `unsafe` code, along with the projections
+    /// that require it.
+    ///
+    /// Dereferencing a raw pointer, accessing a field of a union and projecting to the contents of
+    /// an `UnsafeCell<T>` require `unsafe`. Run [`Self::compute_ty`] first to take the implicit
+    /// dereferences into account, projections from a place whose type is unknown are considered
+    /// safe.
+    pub fn safety(&self) -> Safety {
+        let mut chain = vec![self];
+        while let Some(base) = chain.last().unwrap().base() {
+            chain.push(base);
+        }
+        let mut reasons = vec![];
+        for place in chain.into_iter().rev() {
+            let Some(ty) = place.base().and_then(PlaceExpr::ty) else {
+                continue;
+            };
+            let reason = match place {
+                Self::Deref(_) | Self::AutoDeref(_) | Self::BoundDeref(..) if ty.is_raw_ptr() => {
+                    UnsafeReason::RawPtrDeref
+                }
+                Self::FieldAccess(..) if ty.is_union() => UnsafeReason::UnionField,
+                Self::FieldAccess(..) if ty.is_unsafe_cell() => UnsafeReason::UnsafeCellProjection,
+                _ => continue,
+            };
+            reasons.push(reason(place.clone()));
+        }
+        if reasons.is_empty() {
+            Safety::Safe
+        } else {
+            Safety::Unsafe(reasons)
+        }
+    }
+
+    /// Returns the place this place expression projects from, or `None` for a local variable.
+    fn base(&self) -> Option<&PlaceExpr> {
+        match self {
+            Self::LocalVar(_) => None,
+            Self::Deref(p)
+            | Self::AutoDeref(p)
+            | Self::BoundDeref(p, _)
+            | Self::FieldAccess(p, _)
+            | Self::Index(p, _)
+            | Self::LocalIndex(p, _)
+            | Self::Range(p, ..)
+            | Self::Downcast(p, _)
+            | Self::Wrap(p, _) => Some(p),
+        }
+    }
+
+    /// Finds a dereference of a place wrapper not implementing `PlaceDeref` that is not undone by a
+    /// place wrapper expression.
+    ///
+    /// Every place wrapper expression `@%Wrapper p` undoes the closest dereference of a place
+    /// wrapper inside of `p` that is not already undone by another one. Returns the offending
+    /// dereference.
+    fn find_place_deref_violation(&self, impls: &PlaceDerefImpls) -> Option<&PlaceExpr> {
+        self.wrapper_derefs().into_iter().find(|deref| {
+            let ty = deref.base().unwrap().ty().unwrap();
+            !impls.is_implemented_for(&ty)
+        })
+    }
+
+    /// Finds the innermost dereference of a type that is not a place wrapper and whose condition
+    /// set via [`TypeBuilder::deref_when`] does not hold.
+    fn find_deref_condition_violation(&self) -> Option<&PlaceExpr> {
+        self.chain().into_iter().find(|place| match place {
+            Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) => p
+                .ty()
+                .is_some_and(|ty| !ty.is_place_wrapper() && !ty.deref_condition_holds()),
+            _ => false,
+        })
+    }
+
+    /// Returns the dereferences of place wrappers in this place that are not undone by a place
+    /// wrapper expression, from the innermost to the outermost one.
+    fn wrapper_derefs(&self) -> Vec<&PlaceExpr> {
+        let mut chain = vec![self];
+        while let Some(base) = chain.last().unwrap().base() {
+            chain.push(base);
+        }
+        let mut derefs = vec![];
+        for place in chain.into_iter().rev() {
+            match place {
+                Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _)
+                    if p.ty().is_some_and(|ty| ty.is_place_wrapper()) =>
+                {
+                    derefs.push(place);
+                }
+                Self::Wrap(..) => {
+                    derefs.pop();
+                }
+                _ => {}
+            }
+        }
+        derefs
+    }
+
+    /// Checks that the place wrapper expression `@%wrapper p` is consistent with the computed type
+    /// `p_ty` of `p`.
+    ///
+    /// The wrapper has to be the one whose dereference it undoes, if there is one. Unless its
+    /// argument is a generic parameter, i.e. the annotation only names the wrapper, it also has to
+    /// be either the type of the dereferenced place wrapper or the type `ty` resulting from
+    /// wrapping `p_ty`.
+    fn check_wrapper_annotation(
+        p: &PlaceExpr,
+        p_ty: &Type,
+        wrapper: &Type,
+        ty: &Type,
+    ) -> Result<(), PlaceTyError> {
+        let undone = p
+            .wrapper_derefs()
+            .last()
+            .map(|deref| deref.base().unwrap().ty().unwrap());
+        let named_only = wrapper
+            .get_has_place_target()
+            .is_none_or(|target| target.0.kind == TypeKind::Generic);
+        let expected = match undone {
+            Some(undone) if undone.wrapper_name() != wrapper.wrapper_name() => {
+                undone.wrap_type(p_ty.clone())
+            }
+            Some(undone) if undone == *wrapper => None,
+            _ if !named_only && wrapper != ty => Some(ty.clone()),
+            _ => None,
+        };
+        match expected {
+            Some(expected) => Err(PlaceTyError::WrapperAnnotationMismatch {
+                place: p.clone(),
+                ty: p_ty.clone(),
+                expected,
+                found: wrapper.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Computes the type of this place expression like [`Self::compute_ty`], but additionally
+    /// checks that every dereference in the desugared place expression is allowed.
+    ///
+    /// A dereference is allowed if the type implements `PlaceDeref` according to `impls`, or if
+    /// it is a place wrapper and the dereference is undone by a place wrapper expression. Other
+    /// types implement `PlaceDeref` unless a condition set via [`TypeBuilder::deref_when`] does
+    /// not hold, which results in a [`PlaceTyError::DerefConditionFailed`].
+    pub fn compute_ty_with_place_deref(
+        &mut self,
+        impls: &PlaceDerefImpls,
+    ) -> Result<Type, PlaceTyError> {
+        let ty = self.compute_ty()?;
+        if let Some(deref) = self.find_deref_condition_violation() {
+            let p = deref.base().unwrap();
+            return Err(PlaceTyError::DerefConditionFailed {
+                place: p.clone(),
+                ty: p.ty().unwrap(),
+                deref: deref.clone(),
+            });
+        }
+        if let Some(deref) = self.find_place_deref_violation(impls) {
+            let p = deref.base().unwrap();
+            return Err(PlaceTyError::WrapperDerefNotAllowed {
+                place: p.clone(),
+                wrapper: p.ty().unwrap(),
+                deref: deref.clone(),
+            });
+        }
+        Ok(ty)
+    }
+
+    /// Computes the type of this place expression like [`Self::compute_ty`], but never panics.
+    ///
+    /// [`Self::compute_ty`] already reports its own failure modes as errors: places with more
+    /// projections than the recursion limit result in a [`PlaceTyError::RecursionLimit`] before
+    /// anything recurses through them, inconsistent wrappers in errors like
+    /// [`PlaceTyError::WrapperTargetMismatch`] and poisoned caches are recovered from. Only a
+    /// panic in user code, e.g. in the wrap function of a wrapper or in a [`WrapperKind`], is
+    /// caught as a backstop and returned as a [`PlaceTyError::Internal`], leaving this place as it
+    /// was written. The panic hook still runs for a caught panic.
+    ///
+    /// Some failures cannot be caught: nothing is caught if panics abort, and a stack overflow
+    /// always aborts. Displaying, cloning, comparing and dropping a place recurse through it, so
+    /// a place of many thousand projections can overflow the stack in those, also when it is
+    /// rejected here and the error is displayed.
+    #[cfg(feature = "std")]
+    pub fn try_compute_ty(&mut self) -> Result<Type, PlaceTyError> {
+        // Restoring the place clones it, which recurses through it.
+        self.check_depth(DEFAULT_RECURSION_LIMIT)?;
+        let original = self.clone();
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.compute_ty()));
+        res.unwrap_or_else(|payload| {
+            let message = match payload.downcast::<String>() {
+                Ok(message) => *message,
+                Err(payload) => match payload.downcast::<&'static str>() {
+                    Ok(message) => message.to_string(),
+                    Err(_) => "unknown panic".to_string(),
+                },
+            };
+            *self = original;
+            Err(PlaceTyError::Internal {
+                place: self.clone(),
+                ty: self.root_local().ty(),
+                message,
+            })
+        })
+    }
+
+    /// Computes the type of this place expression, rejecting places that cannot be used.
+    ///
+    /// [`Self::compute_ty`] only computes the structural type of a place and happily dereferences
+    /// place wrappers like `MaybeUninit<T>` along the way. This function instead returns an error
+    /// if the desugared place dereferences a place wrapper without undoing it via `@%Wrapper`,
+    /// since such a place is never valid. It is the same as [`Self::compute_ty_with_place_deref`]
+    /// with no place wrappers implementing `PlaceDeref`.
+    pub fn compute_ty_checked(&mut self) -> Result<Type, PlaceTyError> {
+        self.compute_ty_with_place_deref(&PlaceDerefImpls::new())
+    }
+
+    /// Computes the type of this place expression like [`Self::compute_ty`] and tells whether
+    /// the result is a place or a value.
+    ///
+    /// The projection of a cell created via [`Type::new_value_cell`] reads its contents and so
+    /// results in a value. Projecting a value any further results in a
+    /// [`PlaceTyError::ProjectionOnValue`], only dereferencing it leads to a place again.
+    pub fn compute_projected(&mut self) -> Result<Projected, PlaceTyError> {
+        let ty = self.compute_ty()?;
+        Ok(if self.is_value() {
+            Projected::Value(ty)
+        } else {
+            Projected::Place(ty)
+        })
+    }
+
+    /// Returns whether this is the projection of a cell that results in a value, see
+    /// [`Type::new_value_cell`].
+    fn is_value(&self) -> bool {
+        match self {
+            Self::FieldAccess(p, field) => p.ty().is_some_and(|ty| {
+                ty.is_value_cell() && ty.cell_projection() == Some(field.as_str())
+            }),
+            _ => false,
+        }
+    }
+
     /// Queries this place expressions' type without modifying it.
     ///
     /// After running [`Self::compute_ty`], this function returns `Some`.
     pub fn ty(&self) -> Option<Type> {
+        let p_ty = match self {
+            Self::LocalVar(local) => return Some(local.ty()),
+            Self::Wrap(p, wrapper) => return wrapper.wrap_type(p.ty()?),
+            _ => self.base()?.ty()?,
+        };
+        if p_ty.is_never() {
+            return Some(p_ty);
+        }
         match self {
-            Self::LocalVar(local) => Some(local.ty()),
-            Self::Deref(p) => p.ty()?.get_has_place_target(),
-            Self::Index(p, _) => p.ty()?.get_array_or_slice_element(),
-            Self::FieldAccess(p, field) => Some(p.ty()?.get_field(field)?.ty()),
-            Self::Wrap(p, wrapper) => wrapper.wrap_type(p.ty()?),
+            Self::Deref(_) | Self::AutoDeref(_) => p_ty.get_has_place_target(),
+            Self::BoundDeref(_, target) => Some(target.clone()),
+            Self::Index(..) | Self::LocalIndex(..) => p_ty.get_array_or_slice_element(),
+            Self::Range(..) => p_ty.get_subslice(),
+            Self::Downcast(_, variant) => p_ty.get_variant(variant),
+            Self::FieldAccess(_, field) => Some(p_ty.get_field(field)?.ty()),
+            Self::LocalVar(_) | Self::Wrap(..) => unreachable!(),
         }
     }
 
@@ -419,11 +2552,13 @@ impl PlaceExpr {
     ///     - compute the type of `q`,
     ///     - assert that `<typeof(p) as HasPlace>::Target` is the same as `typeof(q)`.
     ///   - return the type `<typeof(p) as HasPlace>::Target`.
-    /// - When `self == p.field` or `self == p[i]`, then
+    /// - When `self == p.field`, `self == p[i]`, `self == p[a..b]` or `self == (p as Variant)`,
+    ///   then (for an index `p[q]` by a local `q`, we first assert that `typeof(q)` is `usize`)
     ///   - compute the type of `p`,
     ///   - now there are three cases:
-    ///     1. `typeof(p)` has a field named `field` or can be indexed,
-    ///     2. `typeof(p)` implement `HasPlace`,
+    ///     1. `typeof(p)` has a field named `field`, can be indexed or has a variant named
+    ///        `Variant`,
+    ///     2. `typeof(p)` implement `HasPlace` and is not a raw pointer,
     ///     3. None of the two cases above hold.
     ///
     ///     We cover them in reverse, since that makes it easier to understand. We also need a list
@@ -431,67 +2566,395 @@ impl PlaceExpr {
     ///     beginning to the type computation of `p`.
     ///
     ///   - In the third case, we return an error that `typeof(p)` has no field named `field` or
-    ///     cannot be indexed.
+    ///     cannot be indexed. Raw pointers end up here, since they are never dereferenced
+    ///     implicitly.
     ///   - In the second case, we append `typeof(p)` to our list of `wrappers`, then set `p = *p`,
     ///     and then jump to the beginning to compute the type of `p`.
-    ///   - In the first case, we set `ty` to the type of the field or the element type and then
+    ///   - In the first case, we set `ty` to the type of the field, the element type or the slice
+    ///     type (for a range) and then
     ///     iterate backwards through the `wrapper` list:
     ///     - as long as the type implements `PlaceWrapper`, we set `self = @%Wrapper self` and
     ///       set `ty` to the result of wrapping `ty` with `Wrapper`,
     ///     - when we reach the end of the list or `Wrapper` doesn't implement `PlaceWrapper`, we
     ///       stop and return `ty`.
-    pub fn compute_ty(&mut self) -> Result<Type, Error> {
-        static CACHE: Mutex<BTreeMap<PlaceExpr, Type>> = Mutex::new(BTreeMap::new());
-        let cache = CACHE.lock().unwrap();
-        if let Some(ty) = cache.get(self) {
+    ///
+    /// Consequently, projections resolve at the shallowest possible depth: if `typeof(p)` has a
+    /// field named `field` and so does one of its implicit deref targets, the field of `typeof(p)`
+    /// is used, even if the two fields have different types. [`Self::context`] reports such a
+    /// field as a [`Context::ShadowedField`] warning.
+    ///
+    /// Lifted wrappers wrap the projected type as it is, so a field of type `MaybeUninit<T>`
+    /// accessed through a `MaybeUninit<Struct>` has the type `MaybeUninit<MaybeUninit<T>>`. The
+    /// layers are not collapsed, not even for idempotent wrappers, see
+    /// [`Type::simplify_wrappers`] for that.
+    pub fn compute_ty(&mut self) -> Result<Type, PlaceTyError> {
+        static CACHE: Cache<PlaceKey, Type> = Cache::new();
+        // Looking the place up hashes it recursively, so too deep places are rejected before.
+        self.check_depth(DEFAULT_RECURSION_LIMIT)?;
+        if let Some(ty) = CACHE.get(PlaceKey::from_ref(self)) {
+            return Ok(ty);
+        }
+        let res = self.compute_ty_uncached(&mut Desugaring::new(ComputeOptions::default()));
+        if let Ok(ty) = &res {
+            CACHE.insert(PlaceKey(self.clone()), ty.clone());
+        }
+        res
+    }
+
+    /// Desugars this place expression like [`Self::compute_ty`] and reports the implicit
+    /// operations inserted in the process.
+    ///
+    /// Call this on the place as written, on an already desugared place no implicit operations
+    /// are inserted. Afterwards [`Self::ty`] returns the computed type.
+    pub fn desugar_report(&mut self) -> Result<DesugarReport, PlaceTyError> {
+        let mut state = Desugaring::new(ComputeOptions::default());
+        state.report.explicit_projections = self.depth();
+        self.compute_ty_uncached(&mut state)?;
+        Ok(state.report)
+    }
+
+    /// Computes the type of this place expression like [`Self::compute_ty`], but gives up once
+    /// computing it needs more than `limit` nested steps.
+    ///
+    /// Every projection of the desugared place, including the implicit dereferences, is one step.
+    /// When the limit is reached, a [`PlaceTyError::RecursionLimit`] is returned, whose trace
+    /// contains the places that were being computed. This never consults the cache of
+    /// [`Self::compute_ty`].
+    pub fn compute_ty_with_recursion_limit(&mut self, limit: usize) -> Result<Type, PlaceTyError> {
+        self.compute_ty_with_options(ComputeOptions {
+            recursion_limit: limit,
+            ..ComputeOptions::default()
+        })
+    }
+
+    /// Computes the type of this place expression like [`Self::compute_ty`] with the given
+    /// options.
+    ///
+    /// This never consults the cache of [`Self::compute_ty`], since a cached type may have been
+    /// computed with different options.
+    pub fn compute_ty_with_options(
+        &mut self,
+        options: ComputeOptions,
+    ) -> Result<Type, PlaceTyError> {
+        let access = options.access;
+        let ty = self.compute_ty_uncached(&mut Desugaring::new(options))?;
+        if access == Access::Write
+            && let Some(deref) = self.find_shared_deref()
+        {
+            let p = deref.base().unwrap();
+            return Err(PlaceTyError::WriteThroughShared {
+                place: p.clone(),
+                ty: p.ty().unwrap(),
+                written: self.clone(),
+            });
+        }
+        Ok(ty)
+    }
+
+    /// Finds a dereference of a shared reference or a `*const` pointer, which cannot be used to
+    /// write to the place.
+    fn find_shared_deref(&self) -> Option<&PlaceExpr> {
+        self.chain().into_iter().find(|place| match place {
+            Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) => p
+                .ty()
+                .is_some_and(|ty| ty.ptr_mutability() == Some(Mutability::Not)),
+            _ => false,
+        })
+    }
+
+    /// Returns what this place projects from, a local variable or a temporary.
+    pub fn place_base(&self) -> PlaceBase {
+        let local = self.root_local();
+        if local.is_temp() {
+            PlaceBase::Temp(local.ty())
+        } else {
+            PlaceBase::Local(local.clone())
+        }
+    }
+
+    /// Returns the local variable this place projects from.
+    pub fn root_local(&self) -> &Local {
+        let mut place = self;
+        while let Some(base) = place.base() {
+            place = base;
+        }
+        match place {
+            Self::LocalVar(local) => local,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the type of this place and of every place it projects from, starting at the local
+    /// variable.
+    ///
+    /// Run [`Self::compute_ty`] first to get the types along the desugared place, including the
+    /// implicit dereferences. The result ends before the first place whose type is unknown.
+    pub fn types_along_chain(&self) -> Vec<Type> {
+        self.chain().into_iter().map_while(PlaceExpr::ty).collect()
+    }
+
+    /// Returns the dereferences in this place as pairs of the dereferenced type and its target,
+    /// starting at the local variable.
+    ///
+    /// Run [`Self::compute_ty`] first to include the implicit dereferences, e.g. `p.x.y` with
+    /// `p: &X` and `x: &Y` results in `[(&X, X), (&Y, Y)]`. Dereferences of places whose type is
+    /// unknown are skipped.
+    pub fn deref_chain(&self) -> Vec<(Type, Type)> {
+        self.chain()
+            .into_iter()
+            .filter_map(|place| match place {
+                Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) => {
+                    Some((p.ty()?, place.ty()?))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns this place and every place it projects from, starting at the local variable.
+    pub(crate) fn chain(&self) -> Vec<&PlaceExpr> {
+        let mut chain = vec![self];
+        while let Some(base) = chain.last().unwrap().base() {
+            chain.push(base);
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// Returns the number of projections between this place and its local variable, including
+    /// dereferences and place wrapper expressions.
+    ///
+    /// After [`Self::compute_ty`] this counts the desugared place, so `p.field` with `p: &&T` has a
+    /// depth of 3 instead of 1.
+    pub fn depth(&self) -> usize {
+        let mut count = 0;
+        let mut place = self;
+        while let Some(base) = place.base() {
+            count += 1;
+            place = base;
+        }
+        count
+    }
+
+    /// Computes the type of this place expression like [`Self::compute_ty`] without consulting
+    /// the cache, recording the implicit operations in `state`.
+    ///
+    /// Only the outermost place is cached, since caching every intermediate place of a deep deref
+    /// chain would clone the chain once per step. Returns an error instead of recursing further
+    /// once the recursion limit is reached.
+    fn compute_ty_uncached(&mut self, state: &mut Desugaring) -> Result<Type, PlaceTyError> {
+        if state.depth == 0 {
+            self.check_depth(state.options.recursion_limit)?;
+        }
+        if state.depth > state.options.recursion_limit {
+            let local = self.root_local();
+            return Err(PlaceTyError::RecursionLimit {
+                place: PlaceExpr::LocalVar(local.clone()),
+                ty: local.ty(),
+                limit: state.options.recursion_limit,
+                trace: vec![self.clone()],
+            });
+        }
+        let memoized = state
+            .memo
+            .as_ref()
+            .and_then(|memo| memo.get(PlaceKey::from_ref(self)));
+        if let Some((desugared, ty)) = memoized {
+            debug!("reusing the type of `{self}`");
+            *self = desugared.clone();
+            state.memo_hits += 1;
             return Ok(ty.clone());
         }
-        drop(cache);
+        let key = state.memo.is_some().then(|| PlaceKey(self.clone()));
+        state.depth += 1;
+        let mut res = self.desugar(state);
+        state.depth -= 1;
+        if let (Some(key), Some(memo), Ok(ty)) = (key, &mut state.memo, &res) {
+            memo.insert(key, (self.clone(), ty.clone()));
+        }
+        if let Err(PlaceTyError::RecursionLimit { trace, .. }) = &mut res {
+            trace.push(self.clone());
+        }
+        if state.depth == 0 && res.is_ok() && enabled!(Level::DEBUG) {
+            debug!("desugared place:\n{}", self.debug_tree());
+        }
+        res
+    }
+
+    /// Returns a [`PlaceTyError::RecursionLimit`] if this place has more projections than `limit`,
+    /// so computing its type would reach the limit anyway.
+    ///
+    /// This only walks down the place, before anything recurses through it, so even a place too
+    /// deep to be computed without overflowing the stack is rejected. The trace is empty, since
+    /// no place was computed yet.
+    fn check_depth(&self, limit: usize) -> Result<(), PlaceTyError> {
+        if self.depth() <= limit {
+            return Ok(());
+        }
+        let local = self.root_local();
+        Err(PlaceTyError::RecursionLimit {
+            place: PlaceExpr::LocalVar(local.clone()),
+            ty: local.ty(),
+            limit,
+            trace: vec![],
+        })
+    }
+
+    /// Desugars this place expression by one level, see [`Self::compute_ty`].
+    fn desugar(&mut self, state: &mut Desugaring) -> Result<Type, PlaceTyError> {
         let span = info_span!("computing type of", place = %self).entered();
-        let res = span.in_scope(|| match self {
+        span.in_scope(|| match self {
             Self::LocalVar(local) => {
                 debug!("found local variable");
                 info!("resolved `{local}: {}`", local.ty());
-                Ok(local.ty())
+                let ty = local.ty();
+                state.record(|| TraceStep::new("local", self, None, ty.clone()));
+                Ok(ty)
             }
-            Self::Deref(p) => {
+            Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) => {
                 debug!("found deref, descending");
-                let p_ty = p.compute_ty()?;
+                let mark = state.trace_mark();
+                let p_ty = p.compute_ty_uncached(state)?;
+                if p_ty.is_never() {
+                    debug!("`{p}` is never reached, so neither is its dereference");
+                    state
+                        .record(|| TraceStep::new("deref", self, Some(p_ty.clone()), p_ty.clone()));
+                    return Ok(p_ty);
+                }
                 debug!("expecting `{p_ty}: HasPlace`");
-                if let Some(target) = p_ty.get_has_place_target() {
-                    if let Self::Wrap(..) = &**p {
+                // A place desugared before keeps the target of its bound even without the bound.
+                let recorded = match &*self {
+                    Self::BoundDeref(_, target) => Some(target.clone()),
+                    _ => None,
+                };
+                let base = self.base().unwrap();
+                if let Some(target) = state.options.generics.deref_target(&p_ty).or(recorded) {
+                    if let Self::Wrap(_, wrapper) = base {
+                        let wrapper = wrapper.clone();
                         self.strip_wrap_then_deref();
-                        let ty = self.compute_ty()?;
-                        assert!(ty == target, "{ty} != {target}");
+                        state.truncate_trace(mark);
+                        let ty = self.compute_ty_uncached(state)?;
+                        if ty != target {
+                            return Err(PlaceTyError::WrapperTargetMismatch {
+                                place: PlaceExpr::Wrap(Box::new(self.clone()), wrapper),
+                                ty: p_ty,
+                                target,
+                                unwrapped: ty,
+                            });
+                        }
+                    } else {
+                        if p_ty.get_has_place_target().is_none() {
+                            debug!("`{p_ty}` is dereferenced via a bound");
+                            self.record_bound_target(target.clone());
+                        }
+                        state.record(|| TraceStep::new("deref", self, Some(p_ty), target.clone()));
                     }
                     info!("resolved `{self}: {target}`");
                     Ok(target)
                 } else {
-                    Err(Error::new(p, "should implement `HasPlace`"))
+                    Err(PlaceTyError::DerefOnNonPointer {
+                        place: base.clone(),
+                        ty: p_ty,
+                    })
                 }
             }
-            Self::Index(..) | Self::FieldAccess(..) => {
+            Self::Index(..)
+            | Self::LocalIndex(..)
+            | Self::Range(..)
+            | Self::Downcast(..)
+            | Self::FieldAccess(..) => {
                 debug!("found field/index access");
-                let (p, field) = match self {
-                    Self::Index(p, _) => (p, None),
-                    Self::FieldAccess(p, field) => (p, Some(field)),
+                if let Self::LocalIndex(_, index) = self
+                    && !index.ty().is_usize()
+                {
+                    return Err(PlaceTyError::IndexNotUsize {
+                        place: PlaceExpr::LocalVar(index.clone()),
+                        ty: index.ty(),
+                    });
+                }
+                let (p, mut proj) = match self {
+                    Self::Index(p, i) => (p, Projection::Index(i.clone())),
+                    Self::LocalIndex(p, i) => (p, Projection::LocalIndex(i.clone())),
+                    Self::Range(p, start, end) => {
+                        (p, Projection::Range(start.clone(), end.clone()))
+                    }
+                    Self::FieldAccess(p, field) => (p, Projection::Field(field.clone())),
+                    Self::Downcast(p, variant) => (p, Projection::Downcast(variant.clone())),
                     _ => unreachable!(),
                 };
                 let p = &mut **p;
-                let mut wrappers: Vec<Type> = vec![];
+                let original = proj.to_place_projection();
+                // The dereferenced wrappers and whether they are lifted over the projection.
+                let mut wrappers: Vec<(Type, bool)> = vec![];
+                let mut input = None;
                 loop {
-                    let p_ty = p.compute_ty()?;
-                    if let Some(mut ty) = match field {
-                        None => p_ty.get_array_or_slice_element(),
-                        Some(ref field) => p_ty.get_field(field).map(|f| f.ty()),
-                    } {
+                    // After inserting a dereference, `p` is computed anew, but its steps are
+                    // already traced.
+                    let p_ty = if wrappers.is_empty() {
+                        p.compute_ty_uncached(state)?
+                    } else {
+                        state.untraced(|state| p.compute_ty_uncached(state))?
+                    };
+                    if wrappers.is_empty() && p.is_value() {
+                        return Err(PlaceTyError::ProjectionOnValue {
+                            place: p.clone(),
+                            ty: p_ty,
+                        });
+                    }
+                    let input = input.get_or_insert_with(|| p_ty.clone()).clone();
+                    if let Some(mut ty) = proj.resolve(&p_ty) {
                         debug!("field/index found on `{p_ty}` with type `{ty}`");
-                        for wrapper in wrappers.drain(..).rev() {
+                        if p_ty.is_union() && matches!(proj, Projection::Field(_)) {
+                            debug!("`{p_ty}` is a union, accessing its field requires `unsafe`");
+                            state.report.requires_unsafe = true;
+                        }
+                        if let Projection::Field(field) = &proj {
+                            state.report.fields.extend(p_ty.get_field(field));
+                        }
+                        if let Some(len) = p_ty.array_len()
+                            && let Some(index) = proj.out_of_bounds(len)
+                        {
+                            debug!("`{proj}` is out of bounds for `{p_ty}`");
+                            return Err(PlaceTyError::IndexOutOfBounds {
+                                place: p.clone(),
+                                ty: p_ty,
+                                len,
+                                index,
+                            });
+                        }
+                        if let Some(len) = p_ty.array_len()
+                            && let Projection::Index(i) = &proj
+                            && let Some(n) = i.from_end()
+                        {
+                            debug!("`{proj}` counts from the end of `{p_ty}`");
+                            proj = Projection::Index(Expr((len - n).to_string()));
+                        }
+                        let inserted_derefs = wrappers.len();
+                        if proj.to_place_projection() != original {
+                            self.replace_projection(proj.clone());
+                        }
+                        let mut lifted = vec![];
+                        for (wrapper, lift) in wrappers.drain(..).rev() {
+                            if !lift {
+                                debug!("not lifting `{wrapper}` over the projection");
+                                break;
+                            }
+                            state.check_registered_wrapper(self, &ty, &wrapper)?;
                             match wrapper.wrap_type(ty.clone()) {
                                 Some(new_ty) => {
                                     debug!("wrapping with `{wrapper}`, result: `{new_ty}`");
                                     ty = new_ty;
-                                    self.wrap_in_place(wrapper);
+                                    lifted.extend(wrapper.wrapper_name().map(str::to_string));
+                                    // A wrapper lifted before was stripped again when it was
+                                    // dereferenced, so this lift undoes the original dereference.
+                                    let undone = self
+                                        .wrapper_derefs()
+                                        .last()
+                                        .map(|deref| deref.base().unwrap().ty().unwrap())
+                                        .filter(|undone| {
+                                            undone.wrapper_name() == wrapper.wrapper_name()
+                                        });
+                                    self.wrap_in_place(undone.unwrap_or(wrapper));
                                 }
                                 None => {
                                     debug!("cannot wrap with `{wrapper}`");
@@ -500,40 +2963,497 @@ impl PlaceExpr {
                             }
                         }
                         info!("resolved `{self}: {ty}`");
+                        state.record(|| TraceStep {
+                            inserted_derefs,
+                            lifted_wrappers: lifted.clone(),
+                            ..TraceStep::new(proj.kind(), self, Some(input), ty.clone())
+                        });
+                        state.report.lifted_wrappers.extend(lifted);
                         return Ok(ty);
                     }
-                    if p_ty.get_has_place_target().is_none() {
+                    if p_ty.is_raw_ptr() {
+                        debug!("no field/index found on raw pointer `{p_ty}`, not adding a deref");
+                        return Err(PlaceTyError::ImplicitRawPtrDeref {
+                            place: p.clone(),
+                            ty: p_ty,
+                        });
+                    }
+                    if let Some(projection) = p_ty.cell_projection() {
+                        debug!("no field/index found on cell `{p_ty}`, not skipping it");
+                        return Err(PlaceTyError::CellProjectionRequired {
+                            place: p.clone(),
+                            ty: p_ty.clone(),
+                            projection: projection.to_string(),
+                        });
+                    }
+                    let Some(target) = state.options.generics.deref_target(&p_ty) else {
                         debug!(
                             "no field/index found on `{p_ty}`, which also doesn't impl `HasPlace`"
                         );
-                        return Err(Error::new(p, "should implement `HasPlace`"));
+                        return Err(proj.not_found(p, p_ty));
+                    };
+                    if !state.options.auto_deref {
+                        debug!("not adding a deref to `{p}`, implicit dereferences are disabled");
+                        let deref = PlaceExpr::Deref(Box::new(p.clone()));
+                        return Err(PlaceTyError::ExplicitDerefRequired {
+                            place: p.clone(),
+                            ty: p_ty,
+                            suggestion: proj.to_place_projection().apply(deref),
+                        });
+                    }
+                    if let Some(limit) = state.options.max_auto_deref
+                        && state.report.auto_deref_count == limit
+                    {
+                        debug!("not adding a deref to `{p}`, the limit of {limit} is reached");
+                        return Err(PlaceTyError::AutoDerefLimit {
+                            place: p.clone(),
+                            ty: p_ty,
+                            attempted: limit + 1,
+                            limit,
+                        });
                     }
                     debug!("no field/index found on `{p_ty}`, adding a deref to `{p}`");
-                    wrappers.push(p_ty);
+                    let lift = proj.through_wrapper(&p_ty, &target).map_err(|projection| {
+                        PlaceTyError::InvalidWrapperProjection {
+                            place: p.clone(),
+                            ty: p_ty.clone(),
+                            projection,
+                        }
+                    })?;
+                    wrappers.push((p_ty.clone(), lift));
+                    state.report.auto_deref_count += 1;
                     p.deref_in_place();
+                    state.record(|| TraceStep::new("deref", p, Some(p_ty), target));
                 }
             }
-            Self::Wrap(p, wrapper) => wrapper
-                .wrap_type(p.compute_ty()?)
-                .ok_or(Error::new(p, "should implement `PlaceWrapper`")),
-        });
-        if let Ok(ty) = &res {
-            CACHE.lock().unwrap().insert(self.clone(), ty.clone());
+            Self::Wrap(p, wrapper) => {
+                let p_ty = p.compute_ty_uncached(state)?;
+                if p.is_value() {
+                    return Err(PlaceTyError::ProjectionOnValue {
+                        place: (**p).clone(),
+                        ty: p_ty,
+                    });
+                }
+                let ty = wrapper.wrap_type(p_ty.clone()).ok_or_else(|| {
+                    PlaceTyError::NotAPlaceWrapper {
+                        place: (**p).clone(),
+                        ty: p_ty.clone(),
+                        wrapper: wrapper.clone(),
+                    }
+                })?;
+                Self::check_wrapper_annotation(p, &p_ty, wrapper, &ty)?;
+                state.check_registered_wrapper(p, &p_ty, wrapper)?;
+                state.record(|| TraceStep::new("wrap", self, Some(p_ty), ty.clone()));
+                Ok(ty)
+            }
+        })
+    }
+}
+
+/// Replaces every identifier in `name` that is one of the parameters in `substs`.
+fn subst_name(name: &str, substs: &[(&str, Type)]) -> String {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut res = String::new();
+    let mut rest = name;
+    while let Some(c) = rest.chars().next() {
+        let len = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+        if len == 0 {
+            res.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let (ident, tail) = rest.split_at(len);
+        match substs.iter().find(|(param, _)| *param == ident) {
+            Some((_, ty)) => res.push_str(&ty.to_string()),
+            None => res.push_str(ident),
+        }
+        rest = tail;
+    }
+    res
+}
+
+/// The recursion limit used by [`PlaceExpr::compute_ty`], see
+/// [`PlaceExpr::compute_ty_with_recursion_limit`].
+pub const DEFAULT_RECURSION_LIMIT: usize = 128;
+
+/// The result of [`PlaceExpr::compute_projected`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Projected {
+    /// The expression is a place of the given type.
+    Place(Type),
+    /// The expression is a value of the given type, it is not a place.
+    Value(Type),
+}
+
+/// Options for [`PlaceExpr::compute_ty_with_options`].
+///
+/// The default options are the ones used by [`PlaceExpr::compute_ty`].
+#[derive(Clone, Debug)]
+pub struct ComputeOptions {
+    /// The maximum number of implicit dereferences inserted into the whole place, unbounded if
+    /// `None`. Exceeding it results in a [`PlaceTyError::AutoDerefLimit`].
+    pub max_auto_deref: Option<usize>,
+    /// See [`PlaceExpr::compute_ty_with_recursion_limit`].
+    pub recursion_limit: usize,
+    /// Whether dereferences are inserted implicitly. If not, every dereference has to be written
+    /// out, e.g. `(*p).field` instead of `p.field`, and a projection that would need one results in
+    /// a [`PlaceTyError::ExplicitDerefRequired`].
+    pub auto_deref: bool,
+    /// Whether the place is read from or written to.
+    pub access: Access,
+    /// The bounds of the generic parameters, which let places of a generic type be dereferenced.
+    pub generics: GenericEnv,
+    /// The wrappers known by name. A place wrapper expression `@%Name p` or a wrapper lifted over
+    /// a projection whose name is registered as [`WrapperBehavior::Transparent`] results in a
+    /// [`PlaceTyError::WrapperBehaviorMismatch`], since such a wrapper is dereferenced instead.
+    pub registry: Arc<WrapperRegistry>,
+}
+
+// Registries cannot be compared, since their constructors cannot, so options only compare equal
+// if they share the registry or both use an unchanged standard registry, which without the `std`
+// feature is not shared.
+impl PartialEq for ComputeOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_auto_deref == other.max_auto_deref
+            && self.recursion_limit == other.recursion_limit
+            && self.auto_deref == other.auto_deref
+            && self.access == other.access
+            && self.generics == other.generics
+            && (Arc::ptr_eq(&self.registry, &other.registry)
+                || self.registry.is_standard() && other.registry.is_standard())
+    }
+}
+
+impl Eq for ComputeOptions {}
+
+/// How a place is used, see [`ComputeOptions::access`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Access {
+    /// The place is read from, e.g. `let x = p.field`, so every dereference goes through `Deref`.
+    #[default]
+    Read,
+    /// The place is written to or borrowed mutably, e.g. `p.field = x`, so every dereference goes
+    /// through `DerefMut`. Dereferencing a shared reference or a `*const` pointer results in a
+    /// [`PlaceTyError::WriteThroughShared`].
+    Write,
+}
+
+impl Default for ComputeOptions {
+    fn default() -> Self {
+        Self {
+            max_auto_deref: None,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            auto_deref: true,
+            access: Access::Read,
+            generics: GenericEnv::new(),
+            registry: WrapperRegistry::shared_standard(),
+        }
+    }
+}
+
+/// The state threaded through computing the type of a place expression.
+struct Desugaring {
+    report: DesugarReport,
+    options: ComputeOptions,
+    /// The number of places whose type is currently being computed.
+    depth: usize,
+    /// The steps taken so far if they should be traced, see [`PlaceExpr::compute_ty_traced`].
+    trace: Option<Vec<TraceStep>>,
+    /// The places computed before if they should be reused, see [`PlaceTyCtxt`].
+    memo: Option<ctxt::Memo>,
+    /// The number of places taken from `memo`.
+    memo_hits: usize,
+}
+
+impl Desugaring {
+    fn new(options: ComputeOptions) -> Self {
+        Self {
+            report: DesugarReport::default(),
+            options,
+            depth: 0,
+            trace: None,
+            memo: None,
+            memo_hits: 0,
+        }
+    }
+
+    /// Checks that `place` of type `ty` may be wrapped in the place wrapper `wrapper`, i.e. that
+    /// its name is not registered as a transparent wrapper.
+    fn check_registered_wrapper(
+        &self,
+        place: &PlaceExpr,
+        ty: &Type,
+        wrapper: &Type,
+    ) -> Result<(), PlaceTyError> {
+        let behavior = wrapper
+            .wrapper_name()
+            .and_then(|name| self.options.registry.behavior(name));
+        if behavior == Some(WrapperBehavior::Transparent) {
+            return Err(PlaceTyError::WrapperBehaviorMismatch {
+                place: place.clone(),
+                ty: ty.clone(),
+                wrapper: wrapper.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, step: impl FnOnce() -> TraceStep) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(step());
+        }
+    }
+
+    /// Returns the number of steps traced so far, to drop the steps after it when a place is
+    /// computed anew.
+    fn trace_mark(&self) -> usize {
+        self.trace.as_ref().map_or(0, Vec::len)
+    }
+
+    fn truncate_trace(&mut self, mark: usize) {
+        if let Some(trace) = &mut self.trace {
+            trace.truncate(mark);
         }
+    }
+
+    /// Runs `f` without tracing its steps.
+    fn untraced<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let trace = self.trace.take();
+        let res = f(self);
+        self.trace = trace;
         res
     }
 }
 
+/// Displays the range `a..b` of a range index, both bounds are optional.
+struct RangeDisplay<'a>(&'a Option<Expr>, &'a Option<Expr>);
+
+impl Display for RangeDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(start) = self.0 {
+            write!(f, "{start}")?;
+        }
+        write!(f, "..")?;
+        if let Some(end) = self.1 {
+            write!(f, "{end}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A single field or index projection, used while computing the type of a place expression.
+#[derive(Clone)]
+enum Projection {
+    Field(String),
+    Index(Expr),
+    LocalIndex(Local),
+    Range(Option<Expr>, Option<Expr>),
+    Downcast(String),
+}
+
+impl Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Projection::Field(field) => write!(f, ".{field}"),
+            Projection::Index(i) => write!(f, "[{i}]"),
+            Projection::LocalIndex(i) => write!(f, "[{i}]"),
+            Projection::Range(start, end) => write!(f, "[{}]", RangeDisplay(start, end)),
+            Projection::Downcast(variant) => write!(f, " as {variant}"),
+        }
+    }
+}
+
+impl Projection {
+    /// Returns the name of this kind of projection used in traces.
+    fn kind(&self) -> &'static str {
+        match self {
+            Projection::Field(_) => "field",
+            Projection::Index(_) => "index",
+            Projection::LocalIndex(_) => "local_index",
+            Projection::Range(..) => "range",
+            Projection::Downcast(_) => "downcast",
+        }
+    }
+
+    fn to_place_projection(&self) -> PlaceProjection {
+        match self {
+            Projection::Field(field) => PlaceProjection::Field(field.clone()),
+            Projection::Index(i) => PlaceProjection::Index(i.clone()),
+            Projection::LocalIndex(i) => PlaceProjection::LocalIndex(i.clone()),
+            Projection::Range(start, end) => PlaceProjection::Range(start.clone(), end.clone()),
+            Projection::Downcast(variant) => PlaceProjection::Downcast(variant.clone()),
+        }
+    }
+
+    /// Converts the projection returned by a [`WrapperKind`], returning it as the error if it is
+    /// a dereference or a place wrapper expression.
+    fn from_place_projection(proj: PlaceProjection) -> Result<Self, PlaceProjection> {
+        Ok(match proj {
+            PlaceProjection::Field(field) => Projection::Field(field),
+            PlaceProjection::Index(i) => Projection::Index(i),
+            PlaceProjection::LocalIndex(i) => Projection::LocalIndex(i),
+            PlaceProjection::Range(start, end) => Projection::Range(start, end),
+            PlaceProjection::Downcast(variant) => Projection::Downcast(variant),
+            PlaceProjection::Deref | PlaceProjection::Wrap(_) => return Err(proj),
+        })
+    }
+
+    /// Applies this projection to the place `p`.
+    fn apply(self, p: Box<PlaceExpr>) -> PlaceExpr {
+        match self {
+            Projection::Field(field) => PlaceExpr::FieldAccess(p, field),
+            Projection::Index(i) => PlaceExpr::Index(p, i),
+            Projection::LocalIndex(i) => PlaceExpr::LocalIndex(p, i),
+            Projection::Range(start, end) => PlaceExpr::Range(p, start, end),
+            Projection::Downcast(variant) => PlaceExpr::Downcast(p, variant),
+        }
+    }
+
+    /// Resolves this projection through the wrapper `wrapper` whose contents have the type
+    /// `inner`, returning whether the wrapper is lifted over it.
+    ///
+    /// Returns the projection the kind of the wrapper resolved to if it cannot replace this one,
+    /// see [`Self::from_place_projection`].
+    fn through_wrapper(&mut self, wrapper: &Type, inner: &Type) -> Result<bool, PlaceProjection> {
+        let Some(kind) = wrapper.wrapper_kind() else {
+            return Ok(false);
+        };
+        let (proj, lift) = match kind.project(&self.to_place_projection(), inner) {
+            ProjectionResult::Lift(proj) => (proj, true),
+            ProjectionResult::Transparent(proj) => (proj, false),
+        };
+        *self = Self::from_place_projection(proj)?;
+        Ok(lift)
+    }
+
+    /// Returns the type of applying this projection to a place of type `ty`.
+    fn resolve(&self, ty: &Type) -> Option<Type> {
+        match self {
+            _ if ty.is_never() => Some(ty.clone()),
+            Projection::Field(field) => ty.get_field(field).map(|f| f.ty()),
+            Projection::Index(_) | Projection::LocalIndex(_) => ty.get_array_or_slice_element(),
+            Projection::Range(..) => ty.get_subslice(),
+            Projection::Downcast(variant) => ty.get_variant(variant),
+        }
+    }
+
+    /// Checks literal indices, including ones counting from the end like `^1`, against the length
+    /// of an array, returning the offending index or range if they are out of bounds. A range whose
+    /// start is after its end, like `3..1`, is out of bounds as well.
+    fn out_of_bounds(&self, len: usize) -> Option<String> {
+        let literal = |e: &Option<Expr>| e.as_ref().and_then(|e| e.0.parse::<usize>().ok());
+        match self {
+            Projection::Field(_) | Projection::LocalIndex(_) | Projection::Downcast(_) => None,
+            Projection::Index(i) if i.from_end().is_some_and(|n| n == 0 || n > len) => {
+                Some(i.to_string())
+            }
+            Projection::Index(i) => match i.0.parse::<usize>() {
+                Ok(idx) if idx >= len => Some(i.to_string()),
+                _ => None,
+            },
+            Projection::Range(start, end) => {
+                let reversed = literal(start)
+                    .zip(literal(end))
+                    .is_some_and(|(start, end)| start > end);
+                if reversed
+                    || literal(start).is_some_and(|i| i > len)
+                    || literal(end).is_some_and(|i| i > len)
+                {
+                    Some(RangeDisplay(start, end).to_string())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Creates the error for when this projection cannot be applied to `p`.
+    fn not_found(&self, p: &PlaceExpr, ty: Type) -> PlaceTyError {
+        let place = p.clone();
+        match self {
+            _ if ty.is_dyn() => PlaceTyError::FieldOnUnsized {
+                place,
+                ty,
+                projection: self.to_string(),
+            },
+            Projection::Field(field) if ty.variants_with_field(field).len() > 1 => {
+                PlaceTyError::AmbiguousField {
+                    place,
+                    variants: ty.variants_with_field(field),
+                    ty,
+                    field: field.clone(),
+                }
+            }
+            Projection::Field(field) if ty.has_fields() => PlaceTyError::UnknownField {
+                place,
+                on_type: ty,
+                field: field.clone(),
+            },
+            Projection::Field(field) => PlaceTyError::FieldOnNonStruct {
+                place,
+                ty,
+                field: field.clone(),
+            },
+            Projection::Index(_) | Projection::LocalIndex(_) | Projection::Range(..) => {
+                PlaceTyError::IndexOnNonIndexable {
+                    place,
+                    kind: ty.non_indexable_kind(),
+                    ty,
+                }
+            }
+            Projection::Downcast(variant) => PlaceTyError::UnknownVariant {
+                place,
+                on_type: ty,
+                variant: variant.clone(),
+            },
+        }
+    }
+}
+
+/// Builds a [`PlaceExpr`] from Rust-like place syntax, e.g. `place_expr!(@%mb (*p).field[0])`.
+///
+/// Local variables and place wrappers are written as the names of variables holding a [`Local`]
+/// and a wrapper [`Type`], `(_: ty)` is a temporary of type `ty`. With `place_expr!(in wrappers;
+/// ..)`, the wrappers are looked up by name instead, like [`PlaceExpr::parse`] does.
+///
+/// An index that is an identifier like `p[i]` is the [`Local`] `i`, any other index like `p[42]`
+/// or `p[n - 1]` is kept as written. To index by a [`Local`] computed at runtime, interpolate it
+/// with `#`: `p[#locals[0]]` indexes by the value of the expression `locals[0]`, which should be
+/// a `Local`. Either way, computing the type checks that the local is a `usize`.
+///
+/// The place records where the macro was invoked, see [`PlaceExpr::location`], which the
+/// alternate display `{:#}` of a [`PlaceTyError`] points at. This is one location for the whole
+/// place, not one per projection: `column!()` in a `macro_rules!` macro always resolves to the
+/// outermost invocation.
 #[macro_export]
 macro_rules! place_expr {
+    (in $wrappers:expr; $($rest:tt)+) => {{
+        let wrappers: &$crate::Map<String, $crate::Type> = &$wrappers;
+        $crate::bind_wrappers!(wrappers; $($rest)+);
+        $crate::place_expr!($($rest)+)
+    }};
+    ((_ : $ty:expr)) => {
+        Box::new($crate::PlaceExpr::LocalVar(
+            $crate::Local::new_temp($ty.clone()).at($crate::location!()),
+        ))
+    };
     (($($rest:tt)*)) => {
         $crate::place_expr!($($rest)*)
     };
+    (* $p:tt as $variant:ident) => {
+        Box::new($crate::PlaceExpr::Downcast(
+            Box::new($crate::PlaceExpr::Deref($crate::place_expr!($p))),
+            stringify!($variant).to_string(),
+        ))
+    };
+    ($p:tt as $variant:ident) => {
+        Box::new($crate::PlaceExpr::Downcast($crate::place_expr!($p), stringify!($variant).to_string()))
+    };
     (*$($rest:tt)*) => {
         Box::new($crate::PlaceExpr::Deref($crate::place_expr!($($rest)*)))
     };
     ($p:ident) => {
-        Box::new($crate::PlaceExpr::LocalVar($p.clone()))
+        Box::new($crate::PlaceExpr::LocalVar($p.at($crate::location!())))
     };
     ($p:tt . $field:ident $($rest:tt)+) => {
         $crate::place_expr!(($p . $field) $($rest)+)
@@ -541,6 +3461,53 @@ macro_rules! place_expr {
     ($p:tt . $field:ident) => {
         Box::new($crate::PlaceExpr::FieldAccess($crate::place_expr!($p), stringify!($field).to_string()))
     };
+    ($p:tt . $field:literal $($rest:tt)+) => {
+        $crate::place_expr!(($p . $field) $($rest)+)
+    };
+    ($p:tt . $field:literal) => {
+        $crate::tuple_field_path($crate::place_expr!($p), stringify!($field))
+    };
+    ($p:tt [.. $($end:tt)?] $($rest:tt)+) => {
+        $crate::place_expr!(($p [.. $($end)?]) $($rest)+)
+    };
+    ($p:tt [.. $($end:tt)?]) => {
+        Box::new($crate::PlaceExpr::Range(
+            $crate::place_expr!($p),
+            None,
+            $crate::range_bound!($($end)?),
+        ))
+    };
+    ($p:tt [$start:tt .. $($end:tt)?] $($rest:tt)+) => {
+        $crate::place_expr!(($p [$start .. $($end)?]) $($rest)+)
+    };
+    ($p:tt [$start:tt .. $($end:tt)?]) => {
+        Box::new($crate::PlaceExpr::Range(
+            $crate::place_expr!($p),
+            $crate::range_bound!($start),
+            $crate::range_bound!($($end)?),
+        ))
+    };
+    ($p:tt [^ $n:literal] $($rest:tt)+) => {
+        $crate::place_expr!(($p [^ $n]) $($rest)+)
+    };
+    ($p:tt [^ $n:literal]) => {
+        Box::new($crate::PlaceExpr::Index(
+            $crate::place_expr!($p),
+            $crate::Expr(concat!("^", stringify!($n)).to_string()),
+        ))
+    };
+    ($p:tt [# $i:expr] $($rest:tt)+) => {
+        $crate::place_expr!(($p [# $i]) $($rest)+)
+    };
+    ($p:tt [# $i:expr]) => {
+        Box::new($crate::PlaceExpr::LocalIndex($crate::place_expr!($p), $i.at($crate::location!())))
+    };
+    ($p:tt [$i:ident] $($rest:tt)+) => {
+        $crate::place_expr!(($p [$i]) $($rest)+)
+    };
+    ($p:tt [$i:ident]) => {
+        Box::new($crate::PlaceExpr::LocalIndex($crate::place_expr!($p), $i.at($crate::location!())))
+    };
     ($p:tt [$i:expr] $($rest:tt)+) => {
         $crate::place_expr!(($p [$i]) $($rest)+)
     };
@@ -551,3 +3518,63 @@ macro_rules! place_expr {
         Box::new($crate::PlaceExpr::Wrap($crate::place_expr!($($p)+), $wrapper.clone()))
     };
 }
+
+/// Binds every place wrapper `@%Wrapper` named in a `place_expr!` to the wrapper of that name.
+///
+/// This is used by `place_expr!(in wrappers; ...)`, the wrappers are looked up like
+/// [`PlaceExpr::parse`] does.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! bind_wrappers {
+    ($wrappers:ident;) => {};
+    ($wrappers:ident; @% $wrapper:ident $($rest:tt)*) => {
+        #[allow(non_snake_case)]
+        let $wrapper = $crate::wrapper_by_name($wrappers, stringify!($wrapper));
+        $crate::bind_wrappers!($wrappers; $($rest)*);
+    };
+    ($wrappers:ident; ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::bind_wrappers!($wrappers; $($inner)* $($rest)*);
+    };
+    ($wrappers:ident; [$($inner:tt)*] $($rest:tt)*) => {
+        $crate::bind_wrappers!($wrappers; $($inner)* $($rest)*);
+    };
+    ($wrappers:ident; $other:tt $($rest:tt)*) => {
+        $crate::bind_wrappers!($wrappers; $($rest)*);
+    };
+}
+
+/// Turns an optional range bound of `place_expr!` into an `Option<Expr>`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! range_bound {
+    () => {
+        None
+    };
+    ($bound:tt) => {
+        Some($crate::Expr(stringify!($bound).to_string()))
+    };
+}
+
+/// Returns the location of the outermost macro invocation, i.e. of `place_expr!` as written.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! location {
+    () => {
+        $crate::Location {
+            file: file!(),
+            line: line!(),
+            column: column!(),
+        }
+    };
+}
+
+/// Builds the field accesses for a tuple field path like `0` or `0.1`.
+///
+/// The latter is lexed as a single float literal, so `place_expr!` cannot split it on its own.
+#[doc(hidden)]
+pub fn tuple_field_path(mut p: Box<PlaceExpr>, path: &str) -> Box<PlaceExpr> {
+    for field in path.split('.') {
+        p = Box::new(PlaceExpr::FieldAccess(p, field.to_string()));
+    }
+    p
+}
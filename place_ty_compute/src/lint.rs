@@ -0,0 +1,48 @@
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+
+use crate::{PlaceExpr, Type};
+
+/// A surprising but valid pattern in a desugared place expression, see [`PlaceExpr::lint`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lint {
+    /// The dereference `deref` goes through the place wrapper `wrapper`, but the result is not
+    /// wrapped in `@%Wrapper` again.
+    ///
+    /// Place wrappers like `MaybeUninit<T>` do not implement `PlaceDeref`, so the resulting place
+    /// cannot be used, see [`PlaceExpr::compute_ty_checked`].
+    StrictWrapperDeref { deref: PlaceExpr, wrapper: Type },
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StrictWrapperDeref { deref, wrapper } => write!(
+                f,
+                "warning: `{deref}` dereferences the place wrapper `{wrapper}` without wrapping \
+                 the result in `@%{}`",
+                wrapper.wrapper_name().unwrap_or("?")
+            ),
+        }
+    }
+}
+
+impl PlaceExpr {
+    /// Returns the surprising patterns in this place expression, from the local variable
+    /// outwards.
+    ///
+    /// [`Self::compute_ty`] accepts these patterns, since it only computes types. E.g. `p.field`
+    /// with `p: MaybeUninit<&MaybeUninit<Struct>>` desugars to `@%MaybeUninit (***p).field`, which
+    /// lifts the inner `MaybeUninit` but dereferences the outer one in `*p`, so this reports a
+    /// [`Lint::StrictWrapperDeref`] for `*p`. Run [`Self::compute_ty`] first, otherwise the
+    /// implicit dereferences are missing.
+    pub fn lint(&self) -> Vec<Lint> {
+        self.wrapper_derefs()
+            .into_iter()
+            .map(|deref| Lint::StrictWrapperDeref {
+                deref: deref.clone(),
+                wrapper: deref.base().unwrap().ty().unwrap(),
+            })
+            .collect()
+    }
+}
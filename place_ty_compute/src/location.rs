@@ -0,0 +1,46 @@
+use core::fmt::{self, Display};
+
+use crate::{Local, PlaceExpr};
+
+/// Where in the source code a place expression was written, see [`PlaceExpr::location`].
+///
+/// `place_expr!` records the location of its invocation, so every projection of a place written
+/// by one invocation has the same location. Telling the projections apart would need the spans of
+/// the tokens, which only a procedural macro has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    pub file: &'static str,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl Local {
+    /// Returns this local variable marked as used at `location`.
+    ///
+    /// The result is still the same local variable, the location is only carried along, so that
+    /// errors about places starting at it can tell where the place was written.
+    pub fn at(&self, location: Location) -> Local {
+        Local(self.0.clone(), Some(location))
+    }
+
+    /// Returns where this local variable was used, if it was marked via [`Self::at`].
+    pub fn location(&self) -> Option<Location> {
+        self.1
+    }
+}
+
+impl PlaceExpr {
+    /// Returns where this place was written, if it was written via `place_expr!`.
+    ///
+    /// The location is carried by the local variable the place starts at, so it is the same for
+    /// the place and all places it projects from, including the place it desugars to.
+    pub fn location(&self) -> Option<Location> {
+        self.root_local().location()
+    }
+}
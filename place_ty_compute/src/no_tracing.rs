@@ -0,0 +1,44 @@
+//! Stand-ins for the `tracing` macros used when the `tracing` feature is disabled.
+//!
+//! The log macros still type check their format arguments, but never evaluate them.
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = alloc::format!($($arg)*);
+        }
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::no_tracing::debug!($($arg)*)
+    };
+}
+
+macro_rules! enabled {
+    ($($arg:tt)*) => {
+        false
+    };
+}
+
+macro_rules! info_span {
+    ($($arg:tt)*) => {
+        $crate::no_tracing::Span
+    };
+}
+
+pub(crate) use {debug, enabled, info, info_span};
+
+/// A span that does nothing.
+pub(crate) struct Span;
+
+impl Span {
+    pub(crate) fn entered(self) -> Self {
+        self
+    }
+
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+}
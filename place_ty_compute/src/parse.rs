@@ -0,0 +1,357 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use core::fmt::{self, Display};
+
+use crate::{Expr, ExprOfPlace, Local, Map, Mutability, PlaceExpr, Type, WrapperRegistry};
+
+/// An error returned by [`PlaceExpr::parse`] and [`ExprOfPlace::parse`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Something other than `expected` was found at byte offset `pos`, `found` is `None` at the
+    /// end of the input.
+    Unexpected {
+        pos: usize,
+        expected: &'static str,
+        found: Option<char>,
+    },
+    /// The local variable `name` at byte offset `pos` is not in the map of locals.
+    UnknownLocal { pos: usize, name: String },
+    /// The type `name` of the temporary at byte offset `pos` is neither in the map of locals nor
+    /// the type of one of them.
+    UnknownType { pos: usize, name: String },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unexpected {
+                pos,
+                expected,
+                found: Some(found),
+            } => write!(f, "expected {expected} at offset {pos}, found `{found}`"),
+            Self::Unexpected {
+                pos,
+                expected,
+                found: None,
+            } => write!(f, "expected {expected} at offset {pos}, found the end"),
+            Self::UnknownLocal { pos, name } => {
+                write!(f, "unknown local variable `{name}` at offset {pos}")
+            }
+            Self::UnknownType { pos, name } => {
+                write!(f, "unknown type `{name}` at offset {pos}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+impl PlaceExpr {
+    /// Parses a place expression from its textual representation, e.g. `@%MaybeUninit (*p).f`.
+    ///
+    /// This accepts the same syntax as `place_expr!` and as printed by the `Display`
+    /// implementation. Local variables are looked up in `locals`, every occurrence of the same
+    /// name refers to the same [`Local`]. An index consisting of a single identifier is an index
+    /// by that local variable. A temporary `(_: Type)` uses the type named `Type` in `locals` if
+    /// there is one, and otherwise the type of a local variable that is displayed as `Type`, so
+    /// a printed place starting at a temporary can be parsed again. A place wrapper `@%Wrapper`
+    /// uses the type named `Wrapper` in
+    /// `locals` if there is one, just like `place_expr!` uses the variable, then the wrapper of
+    /// that name in [`WrapperRegistry::standard`], and otherwise creates the wrapper via
+    /// [`Type::wrapper`].
+    pub fn parse(src: &str, locals: &Map<String, Type>) -> Result<PlaceExpr, ParseError> {
+        Self::parse_with_registry(src, locals, &WrapperRegistry::shared_standard())
+    }
+
+    /// Parses a place expression like [`Self::parse`], looking up the place wrappers that are not
+    /// in `locals` in `registry` instead of the standard registry.
+    pub fn parse_with_registry(
+        src: &str,
+        locals: &Map<String, Type>,
+        registry: &WrapperRegistry,
+    ) -> Result<PlaceExpr, ParseError> {
+        Self::parse_with_locals(src, locals, registry, &mut Map::new())
+    }
+
+    /// Parses a place expression like [`Self::parse_with_registry`], reusing the local variables
+    /// in `parsed_locals` and adding the newly parsed ones to it.
+    pub(crate) fn parse_with_locals(
+        src: &str,
+        locals: &Map<String, Type>,
+        registry: &WrapperRegistry,
+        parsed_locals: &mut Map<String, Local>,
+    ) -> Result<PlaceExpr, ParseError> {
+        let mut parser = Parser {
+            src,
+            pos: 0,
+            locals,
+            registry,
+            parsed_locals,
+        };
+        let place = parser.place()?;
+        parser.skip_whitespace();
+        if parser.pos < src.len() {
+            return Err(parser.unexpected("the end"));
+        }
+        Ok(place)
+    }
+}
+
+impl ExprOfPlace {
+    /// Parses a value computed from a place, e.g. `&raw const (*p).f`, looking up the local
+    /// variables and place wrappers like [`PlaceExpr::parse`].
+    pub fn parse(src: &str, locals: &Map<String, Type>) -> Result<ExprOfPlace, ParseError> {
+        let mut parsed_locals = Map::new();
+        let registry = WrapperRegistry::shared_standard();
+        let mut parser = Parser {
+            src,
+            pos: 0,
+            locals,
+            registry: &registry,
+            parsed_locals: &mut parsed_locals,
+        };
+        if !parser.eat("&raw") {
+            return Err(parser.unexpected("`&raw`"));
+        }
+        let pos = parser.pos;
+        let mutability = match parser.ident("`const` or `mut`", false)? {
+            "const" => Mutability::Not,
+            "mut" => Mutability::Mut,
+            _ => {
+                parser.pos = pos;
+                return Err(parser.unexpected("`const` or `mut`"));
+            }
+        };
+        let place = parser.place()?;
+        parser.skip_whitespace();
+        if parser.pos < src.len() {
+            return Err(parser.unexpected("the end"));
+        }
+        Ok(ExprOfPlace::RawRef(mutability, place))
+    }
+}
+
+/// Returns the type named `name` in `types`, or the wrapper of that name if there is none, see
+/// [`PlaceExpr::parse`].
+#[doc(hidden)]
+pub fn wrapper_by_name(types: &Map<String, Type>, name: &str) -> Type {
+    resolve_wrapper(types, &WrapperRegistry::shared_standard(), name)
+}
+
+fn resolve_wrapper(types: &Map<String, Type>, registry: &WrapperRegistry, name: &str) -> Type {
+    if let Some(ty) = types.get(name) {
+        return ty.clone();
+    }
+    let t = Type::new_generic("T");
+    registry
+        .wrap(name, &t)
+        .unwrap_or_else(|| Type::wrapper(name, &t))
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    locals: &'a Map<String, Type>,
+    registry: &'a WrapperRegistry,
+    parsed_locals: &'a mut Map<String, Local>,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.src.len() - self.rest().trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn unexpected(&mut self, expected: &'static str) -> ParseError {
+        ParseError::Unexpected {
+            pos: self.pos,
+            expected,
+            found: self.peek(),
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    /// Parses an identifier, or a number if `allow_number` is set.
+    fn ident(&mut self, expected: &'static str, allow_number: bool) -> Result<&'a str, ParseError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let starts_with_digit = rest.starts_with(|c: char| c.is_ascii_digit());
+        let is_number = rest[..len].chars().all(|c| c.is_ascii_digit());
+        if len == 0 || (starts_with_digit && !(allow_number && is_number)) {
+            return Err(self.unexpected(expected));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn local(&mut self, pos: usize, name: &'a str) -> Result<Local, ParseError> {
+        if let Some(local) = self.parsed_locals.get(name) {
+            return Ok(local.clone());
+        }
+        let ty = self
+            .locals
+            .get(name)
+            .ok_or_else(|| ParseError::UnknownLocal {
+                pos,
+                name: name.to_string(),
+            })?;
+        let local = Local::new(ty.clone(), name);
+        self.parsed_locals.insert(name.to_string(), local.clone());
+        Ok(local)
+    }
+
+    /// Parses a place, i.e. a place wrapper expression, a dereference or a postfix place.
+    fn place(&mut self) -> Result<PlaceExpr, ParseError> {
+        if self.eat("@%") {
+            let name = self.ident("a place wrapper", false)?;
+            let wrapper = resolve_wrapper(self.locals, self.registry, name);
+            return Ok(PlaceExpr::Wrap(Box::new(self.place()?), wrapper));
+        }
+        if self.eat("*") {
+            return Ok(PlaceExpr::Deref(Box::new(self.place()?)));
+        }
+        let mut place = self.atom()?;
+        loop {
+            if self.eat(".") {
+                let field = self.ident("a field", true)?;
+                place = PlaceExpr::FieldAccess(Box::new(place), field.to_string());
+            } else if self.eat("[") {
+                place = self.index(place)?;
+            } else {
+                return Ok(place);
+            }
+        }
+    }
+
+    /// Parses a local variable, a parenthesized place or a downcast.
+    fn atom(&mut self) -> Result<PlaceExpr, ParseError> {
+        if self.eat("(") {
+            if let Some(temp) = self.temp()? {
+                return Ok(PlaceExpr::LocalVar(temp));
+            }
+            let place = self.place()?;
+            let place = if self.eat("as ") {
+                let variant = self.ident("a variant", false)?;
+                PlaceExpr::Downcast(Box::new(place), variant.to_string())
+            } else {
+                place
+            };
+            if !self.eat(")") {
+                return Err(self.unexpected("`)`"));
+            }
+            return Ok(place);
+        }
+        self.skip_whitespace();
+        let pos = self.pos;
+        let name = self.ident("a place", false)?;
+        Ok(PlaceExpr::LocalVar(self.local(pos, name)?))
+    }
+
+    /// Parses the rest of a temporary `(_: Type)` after the opening parenthesis, or returns `None`
+    /// if there is none.
+    fn temp(&mut self) -> Result<Option<Local>, ParseError> {
+        self.skip_whitespace();
+        let Some(rest) = self.rest().strip_prefix('_') else {
+            return Ok(None);
+        };
+        if !rest.trim_start().starts_with(':') {
+            return Ok(None);
+        }
+        self.pos = self.src.len() - rest.trim_start().len() + 1;
+        self.skip_whitespace();
+        let pos = self.pos;
+        let mut depth = 0;
+        let len = self
+            .rest()
+            .find(|c| {
+                match c {
+                    '(' | '[' | '<' => depth += 1,
+                    ')' if depth == 0 => return true,
+                    ')' | ']' | '>' => depth -= 1,
+                    _ => {}
+                }
+                false
+            })
+            .ok_or(ParseError::Unexpected {
+                pos: self.src.len(),
+                expected: "`)`",
+                found: None,
+            })?;
+        let name = self.rest()[..len].trim();
+        let ty = match self.locals.get(name) {
+            Some(ty) => ty.clone(),
+            None => self
+                .locals
+                .values()
+                .find(|ty| ty.to_string() == name)
+                .cloned()
+                .ok_or_else(|| ParseError::UnknownType {
+                    pos,
+                    name: name.to_string(),
+                })?,
+        };
+        self.pos += len + 1;
+        Ok(Some(Local::new_temp(ty)))
+    }
+
+    /// Parses the rest of an index `p[i]` or `p[a..b]` after the opening bracket.
+    fn index(&mut self, place: PlaceExpr) -> Result<PlaceExpr, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let mut depth = 0;
+        let len = self
+            .rest()
+            .find(|c| {
+                match c {
+                    '[' | '(' => depth += 1,
+                    ']' | ')' if depth > 0 => depth -= 1,
+                    ']' => return true,
+                    _ => {}
+                }
+                false
+            })
+            .ok_or(ParseError::Unexpected {
+                pos: self.src.len(),
+                expected: "`]`",
+                found: None,
+            })?;
+        let index = self.rest()[..len].trim();
+        self.pos += len + 1;
+        let bound = |b: &str| (!b.trim().is_empty()).then(|| Expr(b.trim().to_string()));
+        if let Some((a, b)) = index.split_once("..") {
+            return Ok(PlaceExpr::Range(Box::new(place), bound(a), bound(b)));
+        }
+        if index.is_empty() {
+            self.pos = start;
+            return Err(self.unexpected("an index"));
+        }
+        let is_ident = index.chars().all(|c| c.is_alphanumeric() || c == '_')
+            && !index.starts_with(|c: char| c.is_ascii_digit());
+        if is_ident {
+            let local = self.local(start, index)?;
+            return Ok(PlaceExpr::LocalIndex(Box::new(place), local));
+        }
+        Ok(PlaceExpr::Index(Box::new(place), Expr(index.to_string())))
+    }
+}
@@ -0,0 +1,129 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt;
+
+use crate::{Map, Type};
+
+/// Builds a wrapper type around its argument, e.g. `MaybeUninit<T>` from `T`.
+type WrapperCtor = Box<dyn Fn(&Type) -> Type + Send + Sync>;
+
+/// How projections through a registered wrapper are resolved, see [`WrapperRegistry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapperBehavior {
+    /// A place wrapper like `MaybeUninit<T>`, which is lifted over projections, see
+    /// [`Type::wrapper`].
+    Lifting,
+    /// A wrapper like `Box<T>` that is dereferenced implicitly, see
+    /// [`Type::transparent_wrapper`].
+    Transparent,
+}
+
+/// Resolves wrapper names like `MaybeUninit` to the wrapper types they stand for.
+///
+/// [`PlaceExpr::parse_with_registry`] uses a registry to resolve the place wrapper expressions
+/// `@%Name`, [`PlaceExpr::parse`] and `place_expr!(in ..)` use [`Self::standard`]. Computing the
+/// type of a place checks its place wrapper expressions against [`ComputeOptions::registry`], and
+/// deserialized wrappers of the standard registry are built by their registered constructors.
+///
+/// [`PlaceExpr::parse_with_registry`]: crate::PlaceExpr::parse_with_registry
+/// [`PlaceExpr::parse`]: crate::PlaceExpr::parse
+/// [`ComputeOptions::registry`]: crate::ComputeOptions::registry
+#[derive(Default)]
+pub struct WrapperRegistry {
+    wrappers: Map<String, (WrapperBehavior, WrapperCtor)>,
+    /// Whether this registry is unchanged since [`Self::standard`] created it.
+    standard: bool,
+}
+
+impl WrapperRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with the wrappers of the standard library: the place wrapper
+    /// `MaybeUninit`, the transparent wrappers `Box`, `Rc`, `Arc` and `ManuallyDrop`, and `Pin`,
+    /// whose argument is the pointer it pins.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        registry.register("MaybeUninit", WrapperBehavior::Lifting, |inner| {
+            Type::wrapper("MaybeUninit", inner)
+        });
+        for name in ["Box", "Rc", "Arc", "ManuallyDrop"] {
+            registry.register(name, WrapperBehavior::Transparent, move |inner| {
+                Type::transparent_wrapper(name, inner)
+            });
+        }
+        registry.register("Pin", WrapperBehavior::Transparent, |pointer| {
+            Type::new_pin(pointer.clone())
+        });
+        registry.standard = true;
+        registry
+    }
+
+    /// Returns the registry created by [`Self::standard`], which is only created once and then
+    /// shared. Without the `std` feature there is no lock to guard it with, so it is created anew
+    /// every time.
+    pub fn shared_standard() -> Arc<Self> {
+        #[cfg(feature = "std")]
+        {
+            static STANDARD: std::sync::OnceLock<Arc<WrapperRegistry>> = std::sync::OnceLock::new();
+            STANDARD.get_or_init(|| Arc::new(Self::standard())).clone()
+        }
+        #[cfg(not(feature = "std"))]
+        Arc::new(Self::standard())
+    }
+
+    /// Registers the wrapper `name`, replacing a wrapper registered before under the same name.
+    ///
+    /// `ctor` builds the wrapper type around its argument and should return a type with the
+    /// given `behavior`.
+    pub fn register(
+        &mut self,
+        name: &str,
+        behavior: WrapperBehavior,
+        ctor: impl Fn(&Type) -> Type + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.wrappers
+            .insert(name.to_string(), (behavior, Box::new(ctor)));
+        self.standard = false;
+        self
+    }
+
+    /// Returns the wrapper `name` around `inner`, or `None` if there is no such wrapper.
+    pub fn wrap(&self, name: &str, inner: &Type) -> Option<Type> {
+        self.wrappers.get(name).map(|(_, ctor)| ctor(inner))
+    }
+
+    /// Returns how projections through the wrapper `name` are resolved.
+    pub fn behavior(&self, name: &str) -> Option<WrapperBehavior> {
+        self.wrappers.get(name).map(|(behavior, _)| *behavior)
+    }
+
+    /// Returns whether this registry is unchanged since [`Self::standard`] created it, so it
+    /// resolves every name like any other such registry.
+    pub(crate) fn is_standard(&self) -> bool {
+        self.standard
+    }
+
+    /// Returns the names of the registered wrappers in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.wrappers.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl fmt::Debug for WrapperRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let behaviors = self
+            .names()
+            .into_iter()
+            .map(|name| (name, self.behavior(name)));
+        f.debug_map().entries(behaviors).finish()
+    }
+}
@@ -0,0 +1,443 @@
+//! `serde` support for [`Type`] and [`PlaceExpr`].
+//!
+//! A place expression is serialized as its root local variable followed by the chain of
+//! projections applied to it, starting at the local:
+//!
+//! ```json
+//! { "local": { "name": "p", "ty": { .. } }, "projections": ["deref", { "field": "x" }] }
+//! ```
+//!
+//! The wrapper function of a place wrapper cannot be serialized. When deserializing a wrapper of
+//! the [`WrapperRegistry::standard`] registry, it is replaced by the registered constructor if
+//! that builds an equal type. Any other wrapper function is replaced by a function that builds
+//! the wrapped type structurally, naming it `Wrapper<Inner>`, where `Inner` replaces the lifted
+//! argument for wrappers with multiple type arguments. Since types are compared structurally, the
+//! result is equal to what the original wrapper function produces. Neither can the
+//! [`WrapperKind`] of a custom wrapper be serialized, it is deserialized as a regular place
+//! wrapper unless the registered constructor creates a custom wrapper.
+//!
+//! [`WrapperKind`]: crate::WrapperKind
+//!
+//! Fields are serialized in declaration order. A public field without an offset is serialized as
+//! its type, any other field as an object with its type `ty`, its visibility `vis` and its
+//! `offset`.
+
+use alloc::collections::BTreeMap;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, MapAccess, Visitor},
+    ser::SerializeMap,
+};
+
+use crate::{
+    Expr, Field, Ident, Layout, Local, PlaceExpr, Type, TypeKind, Visibility, WrapFn,
+    WrapperRegistry,
+};
+
+#[derive(Serialize, Deserialize)]
+struct TypeRepr {
+    display: String,
+    kind: TypeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<Type>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    element: Option<Type>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wrapper_name: Option<String>,
+    #[serde(default, skip_serializing_if = "FieldsRepr::is_empty")]
+    fields: FieldsRepr,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    variants: BTreeMap<String, Type>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<Type>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    lifted_arg: usize,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    idempotent: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layout: Option<Layout>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    explanation: Option<String>,
+}
+
+impl Serialize for Type {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TypeRepr {
+            display: self.0.name.clone(),
+            kind: self.0.kind,
+            target: self.0.has_place_target.clone(),
+            element: self.0.array_slice_elem.clone(),
+            wrapper_name: self.0.wrapper_name.clone(),
+            fields: FieldsRepr(
+                self.fields_in_order()
+                    .into_iter()
+                    .map(|(name, _)| self.get_field(name).unwrap().clone())
+                    .collect(),
+            ),
+            variants: self
+                .sorted_variants()
+                .into_iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+            args: self.0.args.clone(),
+            lifted_arg: self.0.lifted_arg,
+            idempotent: self.0.idempotent,
+            layout: self.0.layout,
+            explanation: self.0.explanation.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Type {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = TypeRepr::deserialize(deserializer)?;
+        if let Some(invalid) = repr.invalid() {
+            return Err(de::Error::custom(format!(
+                "invalid type `{}`: {invalid}",
+                repr.display
+            )));
+        }
+        let wrapper_wrap = repr.wrapper_name.clone().map(|name| {
+            structural_wrapper(
+                name,
+                repr.kind,
+                repr.idempotent,
+                repr.args.clone(),
+                repr.lifted_arg,
+            )
+        });
+        let field_order = repr
+            .fields
+            .0
+            .iter()
+            .map(|field| field.0.name.clone())
+            .collect();
+        let fields = repr
+            .fields
+            .0
+            .into_iter()
+            .map(|field| (field.0.name.clone(), field))
+            .collect();
+        let registered = repr
+            .wrapper_name
+            .as_deref()
+            .zip(repr.target.as_ref())
+            .and_then(|(name, target)| WrapperRegistry::shared_standard().wrap(name, target));
+        let ty = Type::new(
+            repr.target,
+            repr.element,
+            wrapper_wrap,
+            repr.wrapper_name,
+            fields,
+            repr.display,
+        );
+        let ty = ty.with_field_order(field_order).with_inner(|inner| {
+            inner.kind = repr.kind;
+            inner.variants = repr
+                .variants
+                .into_iter()
+                .map(|(name, ty)| (Ident(name), ty))
+                .collect();
+            inner.args = repr.args;
+            inner.lifted_arg = repr.lifted_arg;
+            inner.idempotent = repr.idempotent;
+            inner.layout = repr.layout;
+            inner.explanation = repr.explanation;
+        });
+        match registered {
+            Some(registered) if registered == ty => Ok(ty.with_inner(|inner| {
+                inner.wrapper_wrap = registered.0.wrapper_wrap.clone();
+                inner.wrapper_kind = registered.0.wrapper_kind.clone();
+            })),
+            _ => Ok(ty),
+        }
+    }
+}
+
+impl TypeRepr {
+    /// Returns what is wrong with this type if it lacks a part its kind needs, which the rest of
+    /// the crate relies on being there.
+    fn invalid(&self) -> Option<String> {
+        let fields = self.fields.0.len();
+        match self.kind {
+            TypeKind::Ref(_) | TypeKind::RawPtr(_) if self.target.is_none() => {
+                Some("a pointer needs a target".into())
+            }
+            TypeKind::Array(_) | TypeKind::GenericArray if self.element.is_none() => {
+                Some("an array needs an element type".into())
+            }
+            TypeKind::GenericArray | TypeKind::Pin if self.args.len() != 1 => Some(format!(
+                "`Pin` and arrays of generic length need exactly one type argument, not {}",
+                self.args.len()
+            )),
+            TypeKind::Cell { .. } if fields != 1 || !self.display.contains('<') => Some(format!(
+                "a cell needs to be displayed as `Name<Inner>` and have exactly one field, not \
+                 {fields}"
+            )),
+            TypeKind::Tuple(len)
+                if fields != len
+                    || (0..len)
+                        .any(|i| self.fields.0.iter().all(|f| f.name() != i.to_string())) =>
+            {
+                Some(format!(
+                    "a tuple of {len} elements needs one field per element, named by its index"
+                ))
+            }
+            TypeKind::DerefTransparent if self.wrapper_name.is_none() => {
+                Some("a deref-transparent wrapper needs a wrapper name".into())
+            }
+            _ if self.wrapper_name.is_some() && self.target.is_none() => {
+                Some("a wrapper needs a target".into())
+            }
+            _ if self.wrapper_name.is_some()
+                && !self.args.is_empty()
+                && self.lifted_arg >= self.args.len() =>
+            {
+                Some(format!(
+                    "the lifted argument {} does not exist",
+                    self.lifted_arg
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// The fields of a type, serialized as a map in declaration order.
+#[derive(Default)]
+struct FieldsRepr(Vec<Field>);
+
+/// A field, serialized as its type if it is public and has no offset.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FieldRepr {
+    Full {
+        ty: Type,
+        #[serde(default)]
+        vis: Visibility,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<usize>,
+    },
+    Type(Type),
+}
+
+impl FieldsRepr {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for FieldsRepr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for field in &self.0 {
+            let repr = match (field.visibility(), field.offset()) {
+                (Visibility::Public, None) => FieldRepr::Type(field.ty()),
+                (vis, offset) => FieldRepr::Full {
+                    ty: field.ty(),
+                    vis,
+                    offset,
+                },
+            };
+            map.serialize_entry(field.name(), &repr)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldsRepr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = FieldsRepr;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map of fields")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FieldsRepr, A::Error> {
+                let mut fields = vec![];
+                while let Some((name, repr)) = map.next_entry::<String, _>()? {
+                    if fields.iter().any(|field: &Field| field.name() == name) {
+                        return Err(de::Error::custom(format!("duplicate field `{name}`")));
+                    }
+                    fields.push(match repr {
+                        FieldRepr::Full { ty, vis, offset } => {
+                            Field::new_full(&name, ty, vis, offset)
+                        }
+                        FieldRepr::Type(ty) => Field::new(&name, ty),
+                    });
+                }
+                Ok(FieldsRepr(fields))
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+/// Creates a wrapper function that builds `Wrapper<Inner>` from the structure alone.
+///
+/// For wrappers with multiple type arguments, `Inner` replaces the lifted argument in `args`.
+fn structural_wrapper(
+    wrapper_name: String,
+    kind: TypeKind,
+    idempotent: bool,
+    args: Vec<Type>,
+    lifted_arg: usize,
+) -> WrapFn {
+    Box::new(move |inner| {
+        let mut args = args.clone();
+        let name = if args.is_empty() {
+            format!("{wrapper_name}<{inner}>")
+        } else {
+            args[lifted_arg] = inner.clone();
+            let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            format!("{wrapper_name}<{}>", args.join(", "))
+        };
+        let wrap = structural_wrapper(
+            wrapper_name.clone(),
+            kind,
+            idempotent,
+            args.clone(),
+            lifted_arg,
+        );
+        Type::new(
+            Some(inner),
+            None,
+            Some(wrap),
+            Some(wrapper_name.clone()),
+            Default::default(),
+            name,
+        )
+        .with_inner(|inner| {
+            inner.kind = kind;
+            inner.idempotent = idempotent;
+            inner.args = args;
+            inner.lifted_arg = lifted_arg;
+        })
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct LocalRepr {
+    name: String,
+    ty: Type,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    mutable: bool,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    temp: bool,
+}
+
+impl Serialize for Local {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LocalRepr {
+            name: self.0.name.0.clone(),
+            ty: self.ty(),
+            mutable: self.is_mut(),
+            temp: self.is_temp(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Local {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = LocalRepr::deserialize(deserializer)?;
+        Ok(if repr.temp {
+            Local::new_temp(repr.ty)
+        } else if repr.mutable {
+            Local::new_mut(repr.ty, &repr.name)
+        } else {
+            Local::new(repr.ty, &repr.name)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ProjectionRepr {
+    Deref,
+    BoundDeref(Type),
+    Field(String),
+    Index(String),
+    LocalIndex(Local),
+    Range {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end: Option<String>,
+    },
+    Downcast(String),
+    Wrap(Type),
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlaceRepr {
+    local: Local,
+    projections: Vec<ProjectionRepr>,
+}
+
+impl Serialize for PlaceExpr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut projections = vec![];
+        let mut place = self;
+        let local = loop {
+            projections.push(match place {
+                PlaceExpr::LocalVar(local) => break local.clone(),
+                PlaceExpr::Deref(_) | PlaceExpr::AutoDeref(_) => ProjectionRepr::Deref,
+                PlaceExpr::BoundDeref(_, target) => ProjectionRepr::BoundDeref(target.clone()),
+                PlaceExpr::FieldAccess(_, field) => ProjectionRepr::Field(field.clone()),
+                PlaceExpr::Index(_, i) => ProjectionRepr::Index(i.0.clone()),
+                PlaceExpr::LocalIndex(_, i) => ProjectionRepr::LocalIndex(i.clone()),
+                PlaceExpr::Range(_, start, end) => ProjectionRepr::Range {
+                    start: start.as_ref().map(|e| e.0.clone()),
+                    end: end.as_ref().map(|e| e.0.clone()),
+                },
+                PlaceExpr::Downcast(_, variant) => ProjectionRepr::Downcast(variant.clone()),
+                PlaceExpr::Wrap(_, wrapper) => ProjectionRepr::Wrap(wrapper.clone()),
+            });
+            place = place.base().unwrap();
+        };
+        projections.reverse();
+        PlaceRepr { local, projections }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PlaceExpr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = PlaceRepr::deserialize(deserializer)?;
+        let mut place = PlaceExpr::LocalVar(repr.local);
+        for proj in repr.projections {
+            let p = Box::new(place);
+            place = match proj {
+                ProjectionRepr::Deref => PlaceExpr::Deref(p),
+                ProjectionRepr::BoundDeref(target) => PlaceExpr::BoundDeref(p, target),
+                ProjectionRepr::Field(field) => PlaceExpr::FieldAccess(p, field),
+                ProjectionRepr::Index(i) => PlaceExpr::Index(p, Expr(i)),
+                ProjectionRepr::LocalIndex(i) => PlaceExpr::LocalIndex(p, i),
+                ProjectionRepr::Range { start, end } => {
+                    PlaceExpr::Range(p, start.map(Expr), end.map(Expr))
+                }
+                ProjectionRepr::Downcast(variant) => PlaceExpr::Downcast(p, variant),
+                ProjectionRepr::Wrap(wrapper) => PlaceExpr::Wrap(p, wrapper),
+            };
+        }
+        Ok(place)
+    }
+}
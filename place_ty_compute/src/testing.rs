@@ -0,0 +1,156 @@
+//! Helpers for asserting the desugaring and type of place expressions, e.g. in tests.
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Display};
+
+use crate::{Map, PlaceExpr, PlaceProjection, PlaceTyError, ProjectionDiff, Type, WrapperRegistry};
+
+/// Computes the type of `place` and checks that it desugars to `desugaring` and has the type
+/// `expected_ty`, both compared by their `Display` output.
+///
+/// On success the computed type is returned, on failure the error describes what differs from the
+/// expected values.
+pub fn check(
+    place: &mut PlaceExpr,
+    desugaring: &str,
+    expected_ty: &str,
+) -> Result<Type, CheckError> {
+    let undesugared = place.to_string();
+    let res = place.compute_ty();
+    let context = place.context().iter().map(ToString::to_string).collect();
+    let ty = match res {
+        Ok(ty) => ty,
+        Err(err) => {
+            return Err(CheckError {
+                undesugared,
+                context,
+                kind: Box::new(CheckErrorKind::Failed(err)),
+            });
+        }
+    };
+    let computed = place.to_string();
+    let computed_ty = ty.to_string();
+    if computed != desugaring || computed_ty != expected_ty {
+        let mismatch = |expected: &str, computed: String| {
+            (computed != expected).then(|| (expected.to_string(), computed))
+        };
+        let projections = if computed != desugaring {
+            diff_desugaring(place, desugaring)
+        } else {
+            Vec::new()
+        };
+        return Err(CheckError {
+            undesugared,
+            context,
+            kind: Box::new(CheckErrorKind::Mismatch {
+                desugaring: mismatch(desugaring, computed),
+                projections,
+                ty: mismatch(expected_ty, computed_ty),
+            }),
+        });
+    }
+    Ok(ty)
+}
+
+/// Parses the expected desugaring with the local variables of `place` and compares their
+/// projections, see [`PlaceExpr::diff`].
+///
+/// Returns no difference if the expected desugaring cannot be parsed.
+fn diff_desugaring(place: &PlaceExpr, desugaring: &str) -> Vec<ProjectionDiff> {
+    let mut parsed_locals = Map::new();
+    let root = place.root_local();
+    parsed_locals.insert(root.to_string(), root.clone());
+    for proj in place.projections() {
+        if let PlaceProjection::LocalIndex(index) = proj {
+            parsed_locals.insert(index.to_string(), index);
+        }
+    }
+    let registry = WrapperRegistry::shared_standard();
+    match PlaceExpr::parse_with_locals(desugaring, &Map::new(), &registry, &mut parsed_locals) {
+        Ok(expected) => place.diff(&expected),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The error returned by [`check`].
+///
+/// Its message lists the steps taken to compute the type and the expected and computed values that
+/// differ.
+#[derive(Debug)]
+pub struct CheckError {
+    undesugared: String,
+    context: Vec<String>,
+    kind: Box<CheckErrorKind>,
+}
+
+#[derive(Debug)]
+enum CheckErrorKind {
+    /// The type could not be computed.
+    Failed(PlaceTyError),
+    /// The expected and computed desugaring and type, if they differ.
+    ///
+    /// `projections` compares the projections of the computed desugaring with the expected ones,
+    /// it is empty if the expected desugaring cannot be parsed.
+    Mismatch {
+        desugaring: Option<(String, String)>,
+        projections: Vec<ProjectionDiff>,
+        ty: Option<(String, String)>,
+    },
+}
+
+impl CheckError {
+    /// Returns the error if the type could not be computed at all.
+    pub fn place_ty_error(&self) -> Option<&PlaceTyError> {
+        match &*self.kind {
+            CheckErrorKind::Failed(err) => Some(err),
+            CheckErrorKind::Mismatch { .. } => None,
+        }
+    }
+}
+
+impl Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "analyzed the place expression `{}` with:",
+            self.undesugared
+        )?;
+        for ctx in &self.context {
+            writeln!(f, "\t{ctx}")?;
+        }
+        match &*self.kind {
+            CheckErrorKind::Failed(err) => write!(f, "\nfailed to compute the type: {err}"),
+            CheckErrorKind::Mismatch {
+                desugaring,
+                projections,
+                ty,
+            } => {
+                let diff =
+                    |f: &mut fmt::Formatter<'_>, what: &str, values: &Option<(String, String)>| {
+                        match values {
+                            Some((expected, computed)) => write!(
+                                f,
+                                "\ncomputed {what} does not match the expected {what}:\n\
+                         expected: {expected}\ncomputed: {computed}\n"
+                            ),
+                            None => Ok(()),
+                        }
+                    };
+                diff(f, "desugaring", desugaring)?;
+                if !projections.is_empty() {
+                    writeln!(f, "projections from the local outwards:")?;
+                    for proj in projections {
+                        writeln!(f, "{proj}")?;
+                    }
+                }
+                diff(f, "type", ty)
+            }
+        }
+    }
+}
+
+impl core::error::Error for CheckError {}
@@ -0,0 +1,117 @@
+#[cfg(feature = "std")]
+use alloc::format;
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+#[cfg(feature = "std")]
+use crate::{ComputeOptions, Desugaring, PlaceTyError};
+use crate::{PlaceExpr, Type};
+
+/// A single step of computing the type of a place expression, see
+/// [`PlaceExpr::compute_ty_traced`].
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) struct TraceStep {
+    /// The kind of projection, e.g. `deref` or `field`.
+    pub(crate) projection: &'static str,
+    /// The desugared place resulting from this step.
+    pub(crate) place: String,
+    /// The type of the place that is projected from, `None` for a local variable.
+    pub(crate) input: Option<Type>,
+    pub(crate) output: Type,
+    /// The number of dereferences inserted before applying the projection.
+    pub(crate) inserted_derefs: usize,
+    /// The names of the place wrappers lifted over the projection.
+    pub(crate) lifted_wrappers: Vec<String>,
+}
+
+impl TraceStep {
+    pub(crate) fn new(
+        projection: &'static str,
+        place: &PlaceExpr,
+        input: Option<Type>,
+        output: Type,
+    ) -> Self {
+        Self {
+            projection,
+            place: place.to_string(),
+            input,
+            output,
+            inserted_derefs: 0,
+            lifted_wrappers: vec![],
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn write_json(&self, w: &mut impl Write) -> io::Result<()> {
+        let input = match &self.input {
+            Some(ty) => json_string(&ty.to_string()),
+            None => "null".to_string(),
+        };
+        let lifted = self
+            .lifted_wrappers
+            .iter()
+            .map(|name| json_string(name))
+            .collect::<Vec<_>>();
+        writeln!(
+            w,
+            "{{\"projection\":{},\"place\":{},\"input\":{input},\"output\":{},\
+             \"inserted_derefs\":{},\"lifted_wrappers\":[{}]}}",
+            json_string(self.projection),
+            json_string(&self.place),
+            json_string(&self.output.to_string()),
+            self.inserted_derefs,
+            lifted.join(","),
+        )
+    }
+}
+
+/// Quotes and escapes `s` as a JSON string.
+#[cfg(feature = "std")]
+fn json_string(s: &str) -> String {
+    let mut res = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if c.is_control() => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+#[cfg(feature = "std")]
+impl PlaceExpr {
+    /// Computes the type of this place expression like [`Self::compute_ty`] and writes every step
+    /// of the desugaring to `w` as a line of JSON.
+    ///
+    /// Every step is an object with the kind of `projection` (`local`, `deref`, `field`, `index`,
+    /// `local_index`, `range`, `downcast` or `wrap`), the desugared `place` and the `input` and
+    /// `output` types. Field and index projections also list the number of `inserted_derefs` and
+    /// the `lifted_wrappers`. The steps are written from the local variable outwards. If the type
+    /// cannot be computed, a last object with the `error` message follows.
+    ///
+    /// This never consults the cache of [`Self::compute_ty`], so all steps are traced.
+    pub fn compute_ty_traced(
+        &mut self,
+        w: &mut impl Write,
+    ) -> io::Result<Result<Type, PlaceTyError>> {
+        let mut state = Desugaring::new(ComputeOptions::default());
+        state.trace = Some(vec![]);
+        let res = self.compute_ty_uncached(&mut state);
+        for step in state.trace.unwrap() {
+            step.write_json(w)?;
+        }
+        if let Err(err) = &res {
+            writeln!(w, "{{\"error\":{}}}", json_string(&err.to_string()))?;
+        }
+        Ok(res)
+    }
+}
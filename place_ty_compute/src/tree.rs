@@ -0,0 +1,118 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::fmt::Write;
+
+use crate::{Expr, Local, PlaceExpr, PlaceVisitor, Type};
+
+impl PlaceExpr {
+    /// Renders this place as an indented tree for debugging, one projection per line.
+    ///
+    /// The outermost projection comes first and every projection is followed by the place it
+    /// projects from, indented one level deeper, down to the local variable. Every line shows the
+    /// type of the resulting place, or `?` if it was not computed yet. Run [`Self::compute_ty`]
+    /// first to see the desugared place with the lifted place wrappers.
+    pub fn debug_tree(&self) -> String {
+        let mut tree = PlaceTree::default();
+        self.visit(&mut tree);
+        tree.out
+    }
+}
+
+#[derive(Default)]
+struct PlaceTree {
+    out: String,
+    depth: usize,
+}
+
+impl PlaceTree {
+    fn node(&mut self, label: &str, ty: Option<Type>) {
+        let ty = ty.map_or("?".to_string(), |ty| ty.to_string());
+        writeln!(
+            self.out,
+            "{:indent$}{label}: {ty}",
+            "",
+            indent = 2 * self.depth
+        )
+        .unwrap();
+        self.depth += 1;
+    }
+}
+
+impl PlaceVisitor for PlaceTree {
+    fn visit_local(&mut self, local: &Local) {
+        self.node(&local.to_string(), Some(local.ty()));
+    }
+
+    fn visit_deref(&mut self, _: &PlaceExpr, ty: Option<Type>) {
+        self.node("*", ty);
+    }
+
+    fn visit_field(&mut self, _: &PlaceExpr, field: &str, ty: Option<Type>) {
+        self.node(&format!(".{field}"), ty);
+    }
+
+    fn visit_index(&mut self, _: &PlaceExpr, index: &Expr, ty: Option<Type>) {
+        self.node(&format!("[{}]", index.0), ty);
+    }
+
+    fn visit_local_index(&mut self, _: &PlaceExpr, index: &Local, ty: Option<Type>) {
+        self.node(&format!("[{index}]"), ty);
+    }
+
+    fn visit_range(
+        &mut self,
+        _: &PlaceExpr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        ty: Option<Type>,
+    ) {
+        let bound = |b: Option<&Expr>| b.map_or("", |b| &b.0).to_string();
+        self.node(&format!("[{}..{}]", bound(start), bound(end)), ty);
+    }
+
+    fn visit_downcast(&mut self, _: &PlaceExpr, variant: &str, ty: Option<Type>) {
+        self.node(&format!("as {variant}"), ty);
+    }
+
+    fn visit_wrapper(&mut self, _: &PlaceExpr, wrapper: &Type, ty: Option<Type>) {
+        let name = wrapper
+            .wrapper_name()
+            .map_or(wrapper.to_string(), str::to_string);
+        self.node(&format!("@%{name}"), ty);
+    }
+}
+
+impl Type {
+    /// Renders the structure of this type as an indented tree for debugging.
+    ///
+    /// Every line shows a type, followed by the types it consists of indented one level deeper:
+    /// the target of a pointer or wrapper, the element of an array or slice, the fields and the
+    /// variants.
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, "", 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, label: &str, depth: usize) {
+        write!(out, "{:indent$}{label}{self}", "", indent = 2 * depth).unwrap();
+        if let Some(name) = self.wrapper_name() {
+            write!(out, " (wrapper `{name}`)").unwrap();
+        }
+        out.push('\n');
+        if let Some(target) = self.get_has_place_target() {
+            target.write_tree(out, "target: ", depth + 1);
+        }
+        if let Some(elem) = self.get_array_or_slice_element() {
+            elem.write_tree(out, "element: ", depth + 1);
+        }
+        for (name, ty) in self.fields_in_order() {
+            ty.write_tree(out, &format!(".{name}: "), depth + 1);
+        }
+        for (name, ty) in self.sorted_variants() {
+            ty.write_tree(out, &format!("variant {name}: "), depth + 1);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use core::fmt::{self, Display};
+
+use crate::{Mutability, Operand, PlaceExpr, PlaceTyError, Precedence, Type};
+
+/// A value computed from a place expression without reading it.
+///
+/// Unlike a projection, taking a raw pointer to a place with `&raw const p` does not result in a
+/// place but in a value, so it cannot be projected from any further. Dereferencing the pointer
+/// leads back to the place.
+///
+/// The display prints the place like [`PlaceExpr`] does and parenthesizes place wrapper
+/// expressions, so it can be parsed again with [`Self::parse`].
+///
+/// [`Self::parse`]: ExprOfPlace::parse
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExprOfPlace {
+    /// Taking a raw pointer to a place, `&raw const p` or `&raw mut p`.
+    RawRef(Mutability, PlaceExpr),
+}
+
+impl ExprOfPlace {
+    /// Returns the place the value is computed from.
+    pub fn place(&self) -> &PlaceExpr {
+        match self {
+            Self::RawRef(_, place) => place,
+        }
+    }
+
+    /// Desugars the place like [`PlaceExpr::compute_ty`] and returns the type of the value.
+    ///
+    /// `&raw const p` results in `*const T` and `&raw mut p` in `*mut T`, where `T` is the type of
+    /// `p`. Whether `p` is mutable is not checked, see [`PlaceExpr::is_mutable_place`] for that.
+    pub fn compute_ty(&mut self) -> Result<Type, PlaceTyError> {
+        match self {
+            Self::RawRef(mutability, place) => {
+                let ty = place.compute_ty()?;
+                Ok(Type::new_raw_ptr(ty, *mutability == Mutability::Mut))
+            }
+        }
+    }
+}
+
+impl Display for ExprOfPlace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RawRef(mutability, place) => {
+                f.write_str(match mutability {
+                    Mutability::Not => "&raw const ",
+                    Mutability::Mut => "&raw mut ",
+                })?;
+                Operand(place, Precedence::Deref).fmt(f)
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::{self, Display};
+
+use crate::{Expr, Local, PlaceExpr, PlaceTyError, RangeDisplay, Type};
+
+/// A single projection of a place expression, used by [`PlaceExpr::map_projections`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlaceProjection {
+    /// A dereference `*p`.
+    Deref,
+    /// A field access `p.field`.
+    Field(String),
+    /// An index `p[i]`.
+    Index(Expr),
+    /// An index by a local variable `p[q]`.
+    LocalIndex(Local),
+    /// A range index `p[a..b]`.
+    Range(Option<Expr>, Option<Expr>),
+    /// A downcast `(p as Variant)`.
+    Downcast(String),
+    /// A place wrapper expression `@%Wrapper p`.
+    Wrap(Type),
+}
+
+/// Displays the projection as it is written after the place it projects from, except for the
+/// prefix operators `*` and `@%Wrapper`, e.g. `.field` or `[i]`.
+impl Display for PlaceProjection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Deref => f.write_str("*"),
+            Self::Field(field) => write!(f, ".{field}"),
+            Self::Index(i) => write!(f, "[{i}]"),
+            Self::LocalIndex(i) => write!(f, "[{i}]"),
+            Self::Range(start, end) => write!(f, "[{}]", RangeDisplay(start, end)),
+            Self::Downcast(variant) => write!(f, "as {variant}"),
+            Self::Wrap(wrapper) => write!(f, "@%{}", wrapper.wrapper_name().unwrap_or("?")),
+        }
+    }
+}
+
+/// A step in the difference between two places, see [`PlaceExpr::diff`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProjectionDiff {
+    /// The places start at different local variables, this place at the first one.
+    Local(Local, Local),
+    /// A projection both places have.
+    Same(PlaceProjection),
+    /// A projection only this place has, e.g. an extra dereference.
+    Extra(PlaceProjection),
+    /// A projection only the other place has, e.g. a missing place wrapper expression.
+    Missing(PlaceProjection),
+}
+
+/// Displays the step as a line of a unified diff from the other place to this one.
+impl Display for ProjectionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(this, other) => write!(f, "- {other}\n+ {this}"),
+            Self::Same(proj) => write!(f, "  {proj}"),
+            Self::Extra(proj) => write!(f, "+ {proj}"),
+            Self::Missing(proj) => write!(f, "- {proj}"),
+        }
+    }
+}
+
+/// What [`PlaceExpr::map_projections`] should do with a projection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rewrite {
+    /// Keeps the projection as it is.
+    Keep,
+    /// Replaces the projection with another one.
+    Replace(PlaceProjection),
+    /// Removes the projection.
+    Drop,
+}
+
+/// A visitor over the projections of a [`PlaceExpr`], driven by [`PlaceExpr::visit`].
+///
+/// Every callback receives the place that is projected from as `base` and the type of the
+/// resulting place as `ty`. The type is only available after [`PlaceExpr::compute_ty`] has run,
+/// before that it is `None` for everything except local variables. All callbacks do nothing by
+/// default, so a visitor only needs to implement the ones it is interested in.
+#[allow(unused_variables)]
+pub trait PlaceVisitor {
+    /// Called for the local variable at the root of the place.
+    fn visit_local(&mut self, local: &Local) {}
+
+    /// Called for a dereference `*base`.
+    fn visit_deref(&mut self, base: &PlaceExpr, ty: Option<Type>) {}
+
+    /// Called for a field access `base.field`.
+    fn visit_field(&mut self, base: &PlaceExpr, field: &str, ty: Option<Type>) {}
+
+    /// Called for an index `base[index]`.
+    fn visit_index(&mut self, base: &PlaceExpr, index: &Expr, ty: Option<Type>) {}
+
+    /// Called for an index by a local variable `base[index]`.
+    fn visit_local_index(&mut self, base: &PlaceExpr, index: &Local, ty: Option<Type>) {}
+
+    /// Called for a range index `base[start..end]`.
+    fn visit_range(
+        &mut self,
+        base: &PlaceExpr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        ty: Option<Type>,
+    ) {
+    }
+
+    /// Called for a downcast `(base as Variant)`.
+    fn visit_downcast(&mut self, base: &PlaceExpr, variant: &str, ty: Option<Type>) {}
+
+    /// Called for a place wrapper expression `@%Wrapper base`.
+    fn visit_wrapper(&mut self, base: &PlaceExpr, wrapper: &Type, ty: Option<Type>) {}
+}
+
+impl PlaceExpr {
+    /// Walks the projections of this place from the outermost to the innermost one, ending at the
+    /// local variable, and calls the matching callbacks of `v`.
+    ///
+    /// Run [`Self::compute_ty`] first to visit the desugared place including the implicit
+    /// dereferences and place wrapper expressions.
+    pub fn visit(&self, v: &mut impl PlaceVisitor) {
+        let mut place = self;
+        loop {
+            let ty = place.ty();
+            match place {
+                Self::LocalVar(local) => return v.visit_local(local),
+                Self::Deref(p) | Self::AutoDeref(p) | Self::BoundDeref(p, _) => {
+                    v.visit_deref(p, ty)
+                }
+                Self::FieldAccess(p, field) => v.visit_field(p, field, ty),
+                Self::Index(p, i) => v.visit_index(p, i, ty),
+                Self::LocalIndex(p, i) => v.visit_local_index(p, i, ty),
+                Self::Range(p, start, end) => v.visit_range(p, start.as_ref(), end.as_ref(), ty),
+                Self::Downcast(p, variant) => v.visit_downcast(p, variant, ty),
+                Self::Wrap(p, wrapper) => v.visit_wrapper(p, wrapper, ty),
+            }
+            place = place.base().unwrap();
+        }
+    }
+}
+
+impl PlaceExpr {
+    /// Rewrites the projections of this place, returning the new place.
+    ///
+    /// `f` is called for every projection from the innermost to the outermost one and decides
+    /// whether to keep, replace or drop it. The local variable at the root is always kept. The
+    /// result is not desugared, so run [`Self::compute_ty`] on it to compute its type anew.
+    pub fn map_projections(&self, mut f: impl FnMut(&PlaceProjection) -> Rewrite) -> PlaceExpr {
+        let mut res = PlaceExpr::LocalVar(self.root_local().clone());
+        for proj in self.projections() {
+            let proj = match f(&proj) {
+                Rewrite::Keep => proj,
+                Rewrite::Replace(proj) => proj,
+                Rewrite::Drop => continue,
+            };
+            res = proj.apply(res);
+        }
+        res
+    }
+
+    /// Returns the same projections as this place, but starting at `local` instead of the local
+    /// variable of this place, desugared and with its type computed.
+    ///
+    /// This maps a place of a callee onto the argument of a caller, e.g. `(*p).x` rebased onto
+    /// `q` is `(*q).x`. The projections are taken as they are, so a desugared place keeps its
+    /// dereferences and further ones are inserted if the type of `local` needs them. Returns an
+    /// error if the projections cannot be applied to the type of `local`.
+    pub fn rebase(&self, local: Local) -> Result<PlaceExpr, PlaceTyError> {
+        let mut res = self
+            .projections()
+            .into_iter()
+            .fold(PlaceExpr::LocalVar(local), |p, proj| proj.apply(p));
+        res.compute_ty()?;
+        Ok(res)
+    }
+
+    /// Compares the projections of this place with those of `other`, from the local variable
+    /// outwards.
+    ///
+    /// The result contains every projection of both places, marked as [`ProjectionDiff::Same`]
+    /// where they agree. It starts with a [`ProjectionDiff::Local`] if the places start at
+    /// different local variables. Place wrapper expressions are compared by the name of the
+    /// wrapper, since they wrap different types at different depths. Implicit and explicit
+    /// dereferences are the same projection, so compare the places after [`Self::compute_ty`] to
+    /// see the implicit ones.
+    pub fn diff(&self, other: &PlaceExpr) -> Vec<ProjectionDiff> {
+        let mut diff = vec![];
+        if self.root_local() != other.root_local() {
+            diff.push(ProjectionDiff::Local(
+                self.root_local().clone(),
+                other.root_local().clone(),
+            ));
+        }
+        let (this, other) = (self.projections(), other.projections());
+        // `common[i][j]` is the length of the longest common subsequence of `this[i..]` and
+        // `other[j..]`.
+        let mut common = vec![vec![0; other.len() + 1]; this.len() + 1];
+        for i in (0..this.len()).rev() {
+            for j in (0..other.len()).rev() {
+                common[i][j] = if this[i].same_as(&other[j]) {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < this.len() || j < other.len() {
+            if i < this.len() && j < other.len() && this[i].same_as(&other[j]) {
+                diff.push(ProjectionDiff::Same(this[i].clone()));
+                (i, j) = (i + 1, j + 1);
+            } else if j == other.len() || (i < this.len() && common[i + 1][j] >= common[i][j + 1]) {
+                diff.push(ProjectionDiff::Extra(this[i].clone()));
+                i += 1;
+            } else {
+                diff.push(ProjectionDiff::Missing(other[j].clone()));
+                j += 1;
+            }
+        }
+        diff
+    }
+
+    /// Returns the projections of this place from the innermost to the outermost one.
+    pub(crate) fn projections(&self) -> Vec<PlaceProjection> {
+        let mut projections = vec![];
+        let mut place = self;
+        while let Some(base) = place.base() {
+            projections.push(match place {
+                Self::LocalVar(_) => unreachable!(),
+                Self::Deref(_) | Self::AutoDeref(_) | Self::BoundDeref(..) => {
+                    PlaceProjection::Deref
+                }
+                Self::FieldAccess(_, field) => PlaceProjection::Field(field.clone()),
+                Self::Index(_, i) => PlaceProjection::Index(i.clone()),
+                Self::LocalIndex(_, i) => PlaceProjection::LocalIndex(i.clone()),
+                Self::Range(_, start, end) => PlaceProjection::Range(start.clone(), end.clone()),
+                Self::Downcast(_, variant) => PlaceProjection::Downcast(variant.clone()),
+                Self::Wrap(_, wrapper) => PlaceProjection::Wrap(wrapper.clone()),
+            });
+            place = base;
+        }
+        projections.reverse();
+        projections
+    }
+}
+
+impl PlaceProjection {
+    /// Returns the place projecting from `p` with this projection.
+    pub(crate) fn apply(self, p: PlaceExpr) -> PlaceExpr {
+        let p = Box::new(p);
+        match self {
+            Self::Deref => PlaceExpr::Deref(p),
+            Self::Field(field) => PlaceExpr::FieldAccess(p, field),
+            Self::Index(i) => PlaceExpr::Index(p, i),
+            Self::LocalIndex(i) => PlaceExpr::LocalIndex(p, i),
+            Self::Range(start, end) => PlaceExpr::Range(p, start, end),
+            Self::Downcast(variant) => PlaceExpr::Downcast(p, variant),
+            Self::Wrap(wrapper) => PlaceExpr::Wrap(p, wrapper),
+        }
+    }
+
+    /// Returns whether both projections are the same, comparing place wrappers by their name.
+    fn same_as(&self, other: &PlaceProjection) -> bool {
+        match (self, other) {
+            (Self::Wrap(a), Self::Wrap(b)) => a.wrapper_name() == b.wrapper_name(),
+            _ => self == other,
+        }
+    }
+}
+
+impl PlaceExpr {
+    /// Returns whether `other` projects from this place, e.g. `(*p).x` is a prefix of
+    /// `(*p).x.y`. Every place is a prefix of itself.
+    ///
+    /// Both places are compared after desugaring them, so `p.x` and `(*p).x` are the same place if
+    /// `p` is a reference. Place wrapper expressions only change the type of a place, not where it
+    /// is, so they are ignored. If the type of a place cannot be computed, it is compared as far
+    /// as it was desugared.
+    pub fn is_prefix_of(&self, other: &PlaceExpr) -> bool {
+        let desugar = |place: &PlaceExpr| {
+            let mut place = place.clone();
+            place.compute_ty().ok();
+            place.map_projections(|proj| match proj {
+                PlaceProjection::Wrap(_) => Rewrite::Drop,
+                _ => Rewrite::Keep,
+            })
+        };
+        let this = desugar(self);
+        let other = desugar(other);
+        let mut place = &other;
+        loop {
+            if *place == this {
+                return true;
+            }
+            match place.base() {
+                Some(base) => place = base,
+                None => return false,
+            }
+        }
+    }
+}
+
+impl PlaceExpr {
+    /// Returns the canonical form of this place, so two places that only differ in how they are
+    /// written compare equal after normalizing them, e.g. `p.field` and `(*p).field` if `p` is a
+    /// reference.
+    ///
+    /// The canonical form is the desugared place, with the implicit dereferences turned into
+    /// explicit ones and adjacent place wrapper expressions of the same idempotent wrapper
+    /// collapsed into one, as they do not change the type.
+    pub fn normalize(&self) -> Result<PlaceExpr, PlaceTyError> {
+        let mut place = self.clone();
+        place.compute_ty()?;
+        let mut last_wrapper: Option<Type> = None;
+        Ok(place.map_projections(|proj| {
+            let wrapper = match proj {
+                PlaceProjection::Wrap(wrapper) => Some(wrapper.clone()),
+                _ => None,
+            };
+            let same_name = |a: &Type, b: &Type| a.wrapper_name() == b.wrapper_name();
+            let redundant = wrapper.as_ref().is_some_and(|w| {
+                w.0.idempotent && last_wrapper.as_ref().is_some_and(|last| same_name(w, last))
+            });
+            last_wrapper = wrapper;
+            if redundant {
+                Rewrite::Drop
+            } else {
+                Rewrite::Keep
+            }
+        }))
+    }
+
+    /// Returns a key identifying where this place is, which is the same for places that only
+    /// differ in how they are written, e.g. `local:p/deref/field:x` for both `p.x` and `(*p).x` if
+    /// `p` is a reference.
+    ///
+    /// The place is desugared like [`Self::normalize`] does, as far as its type can be computed.
+    /// Place wrapper expressions only change the type of a place, so they are left out. The local
+    /// variable is identified by its name, so places of different locals with the same name have
+    /// the same path.
+    pub fn canonical_path(&self) -> String {
+        self.canonical_steps().join("/")
+    }
+
+    /// Returns the components of [`Self::canonical_path`], from the local variable outwards.
+    pub(crate) fn canonical_steps(&self) -> Vec<String> {
+        let mut place = self.clone();
+        place.compute_ty().ok();
+        let mut path = vec![format!("local:{}", place.root_local())];
+        path.extend(place.projections().into_iter().filter_map(|proj| {
+            Some(match proj {
+                PlaceProjection::Deref => "deref".to_string(),
+                PlaceProjection::Field(field) => format!("field:{field}"),
+                PlaceProjection::Index(i) => format!("index:{i}"),
+                PlaceProjection::LocalIndex(i) => format!("index:local:{i}"),
+                PlaceProjection::Range(start, end) => {
+                    format!("range:{}", RangeDisplay(&start, &end))
+                }
+                PlaceProjection::Downcast(variant) => format!("downcast:{variant}"),
+                PlaceProjection::Wrap(_) => return None,
+            })
+        }));
+        path
+    }
+}
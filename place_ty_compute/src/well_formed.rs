@@ -0,0 +1,149 @@
+use alloc::string::String;
+use core::fmt::{self, Display};
+
+use crate::{Expr, Local, PlaceExpr, Type};
+
+/// A place expression that is built wrongly, regardless of the types involved, see
+/// [`PlaceExpr::well_formed`].
+///
+/// Every variant carries the offending place expression, i.e. the projection that is malformed
+/// together with the place it projects from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MalformedPlace {
+    /// A field access `p.field` whose field is neither an identifier nor a tuple index.
+    InvalidField { place: PlaceExpr, field: String },
+    /// An index `p[i]` whose index is empty or counts from the end with `^0`, which is never in
+    /// bounds.
+    InvalidIndex { place: PlaceExpr, index: String },
+    /// An index `p[i]` by a temporary, which has no name to refer to it by.
+    TempIndex { place: PlaceExpr, index: Local },
+    /// A range `p[a..b]` with literal bounds where `a` is greater than `b`.
+    ReversedRange {
+        place: PlaceExpr,
+        start: usize,
+        end: usize,
+    },
+    /// A downcast `(p as Variant)` whose variant is not an identifier.
+    InvalidVariant { place: PlaceExpr, variant: String },
+    /// A place wrapper expression `@%Wrapper p` where `Wrapper` is not a place wrapper, e.g. a
+    /// deref-transparent wrapper like `Box<T>` or a struct.
+    NotAPlaceWrapper { place: PlaceExpr, wrapper: Type },
+}
+
+impl MalformedPlace {
+    /// Returns the place expression that is malformed.
+    pub fn place(&self) -> &PlaceExpr {
+        match self {
+            Self::InvalidField { place, .. }
+            | Self::InvalidIndex { place, .. }
+            | Self::TempIndex { place, .. }
+            | Self::ReversedRange { place, .. }
+            | Self::InvalidVariant { place, .. }
+            | Self::NotAPlaceWrapper { place, .. } => place,
+        }
+    }
+}
+
+impl Display for MalformedPlace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the place expression `{}` ", self.place())?;
+        match self {
+            Self::InvalidField { field, .. } => write!(
+                f,
+                "accesses the field `{field}`, which is neither an identifier nor a tuple index"
+            ),
+            Self::InvalidIndex { index, .. } if index.trim().is_empty() => {
+                write!(f, "has an empty index")
+            }
+            Self::InvalidIndex { index, .. } => {
+                write!(f, "has the index `{index}`, which is never in bounds")
+            }
+            Self::TempIndex { index, .. } => write!(
+                f,
+                "is indexed by the temporary `{index}`, only named local variables can be indices"
+            ),
+            Self::ReversedRange { start, end, .. } => {
+                write!(f, "has a range whose start {start} is after its end {end}")
+            }
+            Self::InvalidVariant { variant, .. } => write!(
+                f,
+                "is downcast to the variant `{variant}`, which is not an identifier"
+            ),
+            Self::NotAPlaceWrapper { wrapper, .. } => {
+                write!(f, "is wrapped in `{wrapper}`, which is not a place wrapper")
+            }
+        }
+    }
+}
+
+impl core::error::Error for MalformedPlace {}
+
+/// Returns whether `name` is an identifier like `field` or `_0`.
+fn is_ident(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Returns whether `name` is a tuple index like `0`.
+fn is_tuple_index(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+}
+
+impl PlaceExpr {
+    /// Checks that this place expression is built correctly, without computing any types.
+    ///
+    /// This catches mistakes made while constructing a place by hand or deserializing it, which
+    /// [`Self::compute_ty`] would otherwise report as type errors or not at all. A well-formed
+    /// place may still fail to type-check. Returns the innermost malformed projection.
+    pub fn well_formed(&self) -> Result<(), MalformedPlace> {
+        let literal = |bound: &Option<Expr>| bound.as_ref()?.0.trim().parse::<usize>().ok();
+        for place in self.chain() {
+            let err = match place {
+                Self::FieldAccess(_, field) if !is_ident(field) && !is_tuple_index(field) => {
+                    MalformedPlace::InvalidField {
+                        place: place.clone(),
+                        field: field.clone(),
+                    }
+                }
+                Self::Index(_, index)
+                    if index.0.trim().is_empty() || index.from_end() == Some(0) =>
+                {
+                    MalformedPlace::InvalidIndex {
+                        place: place.clone(),
+                        index: index.0.clone(),
+                    }
+                }
+                Self::LocalIndex(_, index) if index.is_temp() => MalformedPlace::TempIndex {
+                    place: place.clone(),
+                    index: index.clone(),
+                },
+                Self::Range(_, start, end)
+                    if let (Some(start), Some(end)) = (literal(start), literal(end))
+                        && start > end =>
+                {
+                    MalformedPlace::ReversedRange {
+                        place: place.clone(),
+                        start,
+                        end,
+                    }
+                }
+                Self::Downcast(_, variant) if !is_ident(variant) => {
+                    MalformedPlace::InvalidVariant {
+                        place: place.clone(),
+                        variant: variant.clone(),
+                    }
+                }
+                Self::Wrap(_, wrapper) if !wrapper.is_place_wrapper() => {
+                    MalformedPlace::NotAPlaceWrapper {
+                        place: place.clone(),
+                        wrapper: wrapper.clone(),
+                    }
+                }
+                _ => continue,
+            };
+            return Err(err);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{PlaceProjection, Type};
+
+/// Decides how field and index projections through a wrapper type are resolved.
+///
+/// When [`PlaceExpr::compute_ty`] finds a projection that cannot be applied to a wrapper, it
+/// dereferences the wrapper and asks its kind how to apply the projection to the contents. Place
+/// wrappers like `MaybeUninit<T>` behave like [`Lifting`] and deref-transparent wrappers like
+/// `Box<T>` like [`Transparent`], other kinds are attached via [`Type::custom_wrapper`].
+/// [`ByProjection`] combines two kinds to treat fields and indices differently.
+///
+/// [`PlaceExpr::compute_ty`]: crate::PlaceExpr::compute_ty
+pub trait WrapperKind: Send + Sync {
+    /// Resolves the projection `proj` through a wrapper whose contents have the type `inner`.
+    ///
+    /// `proj` is a field, index, range or downcast projection. The projection returned in the
+    /// result is applied to the contents instead of `proj`, so a kind may rename a field or turn
+    /// it into an index, but it has to be a field, index, range or downcast projection as well.
+    /// Otherwise computing the type results in a [`PlaceTyError::InvalidWrapperProjection`].
+    ///
+    /// [`PlaceTyError::InvalidWrapperProjection`]: crate::PlaceTyError::InvalidWrapperProjection
+    fn project(&self, proj: &PlaceProjection, inner: &Type) -> ProjectionResult;
+}
+
+/// How a projection through a wrapper is resolved, see [`WrapperKind::project`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProjectionResult {
+    /// Applies the projection to the contents and lifts the wrapper over it, so the result is
+    /// wrapped again.
+    Lift(PlaceProjection),
+    /// Applies the projection to the contents without wrapping the result. This also stops lifting
+    /// the wrappers dereferenced before this one.
+    Transparent(PlaceProjection),
+}
+
+/// The kind of place wrappers like `MaybeUninit<T>`, which lifts every projection.
+pub struct Lifting;
+
+impl WrapperKind for Lifting {
+    fn project(&self, proj: &PlaceProjection, _: &Type) -> ProjectionResult {
+        ProjectionResult::Lift(proj.clone())
+    }
+}
+
+/// The kind of deref-transparent wrappers like `Box<T>`, which never lifts a projection.
+pub struct Transparent;
+
+impl WrapperKind for Transparent {
+    fn project(&self, proj: &PlaceProjection, _: &Type) -> ProjectionResult {
+        ProjectionResult::Transparent(proj.clone())
+    }
+}
+
+/// A kind that resolves field projections and downcasts like `field` and index and range
+/// projections like `index`.
+///
+/// `ByProjection { field: Transparent, index: Lifting }` models a container that forwards field
+/// accesses to its contents, but lifts itself over element accesses.
+pub struct ByProjection<F, I> {
+    pub field: F,
+    pub index: I,
+}
+
+impl<F: WrapperKind, I: WrapperKind> WrapperKind for ByProjection<F, I> {
+    fn project(&self, proj: &PlaceProjection, inner: &Type) -> ProjectionResult {
+        match proj {
+            PlaceProjection::Field(_) | PlaceProjection::Downcast(_) => {
+                self.field.project(proj, inner)
+            }
+            _ => self.index.project(proj, inner),
+        }
+    }
+}
@@ -1,9 +1,15 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    sync::{Mutex, Once},
-};
+use std::sync::{Arc, Once};
 
-use place_ty_compute::{Field, Local, PlaceExpr, Type, place_expr};
+use place_ty_compute::{
+    Access, ArrayLen, ByProjection, ComputeOptions, Context, DesugarReport, Expr, ExprOfPlace,
+    Field, Fields, GenericEnv, IncrementalPlace, Layout, Lifting, Lint, Local, MalformedPlace, Map,
+    Mutability, NonIndexableKind, ParseError, PlaceBase, PlaceDerefImpls, PlaceExpr,
+    PlaceProjection, PlaceTyCtxt, PlaceTyError, PlaceVisitor, Projected, ProjectionBehavior,
+    ProjectionDiff, ProjectionResult, Rewrite, Safety, Transparent, Type, TypeBuildError,
+    UnsafeReason, Visibility, WrapperBehavior, WrapperKind, WrapperRegistry, place_expr,
+};
+#[cfg(feature = "std")]
+use place_ty_compute::{TypeCache, TypeInterner};
 
 fn init_logging() {
     use tracing_subscriber::layer::SubscriberExt;
@@ -62,62 +68,69 @@ fn check(place: &mut PlaceExpr, desugaring: &str, expected_ty: &str) {
     }
 }
 
-fn maybe_uninit(inner: &Type) -> Type {
-    static CACHE: Mutex<BTreeMap<Type, Type>> = Mutex::new(BTreeMap::new());
-    let mut cache = CACHE.lock().unwrap();
-
-    if let Some(res) = cache.get(inner) {
-        return res.clone();
+fn check_err(place: &mut PlaceExpr, expected_err: &str) {
+    init_logging();
+    let undesugared = format!("{place}");
+    match place.compute_ty() {
+        Ok(ty) => {
+            println!("analyzed the place expression `{undesugared}`");
+            println!("desugared to: `{place}: {ty}`");
+            panic!("expected an error, but desugaring succeeded");
+        }
+        Err(err) => {
+            if format!("{err}") != expected_err {
+                println!("computed error does not match the expected error:");
+                println!("expected: {expected_err}");
+                println!("computed: {err}");
+                panic!("error does not match expected value");
+            }
+        }
     }
+}
 
-    let maybe_uninit = Type::new(
-        Some(inner.clone()),
-        None,
-        Some(Box::new(|ty| maybe_uninit(&ty))),
-        Some("MaybeUninit".to_string()),
-        HashMap::new(),
-        format!("MaybeUninit<{inner}>"),
-    );
-    cache.insert(inner.clone(), maybe_uninit.clone());
-    maybe_uninit
+fn maybe_uninit(inner: &Type) -> Type {
+    Type::wrapper("MaybeUninit", inner)
 }
 
-fn shared_ref(target: &Type) -> Type {
-    static CACHE: Mutex<BTreeMap<Type, Type>> = Mutex::new(BTreeMap::new());
-    let mut cache = CACHE.lock().unwrap();
+fn boxed(target: &Type) -> Type {
+    let boxed = Type::new_deref_transparent("Box", target.clone(), Box::new(|ty| boxed(&ty)));
+    intern(boxed)
+}
 
-    if let Some(res) = cache.get(target) {
-        return res.clone();
-    }
+fn shared_ref(target: &Type) -> Type {
     let shared_ref = Type::new(
         Some(target.clone()),
         None,
         None,
         None,
-        HashMap::new(),
+        Map::new(),
         format!("&{target}"),
     );
-    cache.insert(target.clone(), shared_ref.clone());
-    shared_ref
+    intern(shared_ref)
 }
 
 fn slice(target: &Type) -> Type {
-    static CACHE: Mutex<BTreeMap<Type, Type>> = Mutex::new(BTreeMap::new());
-    let mut cache = CACHE.lock().unwrap();
-
-    if let Some(res) = cache.get(target) {
-        return res.clone();
-    }
     let slice = Type::new(
         None,
         Some(target.clone()),
         None,
         None,
-        HashMap::new(),
+        Map::new(),
         format!("[{target}]"),
     );
-    cache.insert(target.clone(), slice.clone());
-    slice
+    intern(slice)
+}
+
+/// Returns the interned `ty`, so the helpers return the same type for the same target.
+#[cfg(feature = "std")]
+fn intern(ty: Type) -> Type {
+    TypeInterner::global().intern(ty).into_type()
+}
+
+/// Without the `std` feature there is no interner, equal types are still equal though.
+#[cfg(not(feature = "std"))]
+fn intern(ty: Type) -> Type {
+    ty
 }
 
 #[test]
@@ -139,6 +152,43 @@ fn shared_ref_field() {
     check(&mut e, "(*p).field", "U");
 }
 
+#[test]
+fn shared_ref_field_without_auto_deref() {
+    let u = Type::new_generic("U");
+    let t = Type::new_struct("T", vec![Field::new("field", u.clone())]);
+    let t_ref = shared_ref(&t);
+    let p = Local::new(t_ref, "p");
+    let explicit = ComputeOptions {
+        auto_deref: false,
+        ..ComputeOptions::default()
+    };
+
+    let mut e = place_expr!(p.field);
+    let err = e.compute_ty_with_options(explicit.clone()).unwrap_err();
+    match &err {
+        PlaceTyError::ExplicitDerefRequired { suggestion, .. } => {
+            assert_eq!(suggestion.to_string(), "(*p).field");
+        }
+        err => panic!("expected `ExplicitDerefRequired`, got {err:?}"),
+    }
+    assert_eq!(
+        err.to_string(),
+        "the type of `p: &T` must be dereferenced explicitly, since implicit dereferences are \
+         disabled: write `(*p).field`"
+    );
+
+    let mut e = place_expr!((*p).field);
+    assert!(e.compute_ty_with_options(explicit.clone()).unwrap() == u);
+    assert_eq!(e.to_string(), "(*p).field");
+    let mut e = place_expr!(p.field);
+    assert!(
+        e.compute_ty_with_options(ComputeOptions::default())
+            .unwrap()
+            == u
+    );
+    assert_eq!(e.to_string(), "(*p).field");
+}
+
 #[test]
 fn blog1() {
     let field = Type::new_generic("Field");
@@ -149,6 +199,32 @@ fn blog1() {
     check(&mut e, "@%MaybeUninit (*p).field", "MaybeUninit<Field>");
 }
 
+#[test]
+fn blog1_manually_drop() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let md_struct = Type::transparent_wrapper("ManuallyDrop", &struct_);
+    assert_eq!(md_struct.to_string(), "ManuallyDrop<Struct>");
+    let p = Local::new(md_struct, "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "Field");
+
+    let md = Type::builder()
+        .target(struct_.clone())
+        .wrapper("ManuallyDrop", |ty| {
+            Type::transparent_wrapper("ManuallyDrop", &ty)
+        })
+        .transparent()
+        .display("ManuallyDrop<Struct>")
+        .build()
+        .unwrap();
+    assert!(md == Type::transparent_wrapper("ManuallyDrop", &struct_));
+    assert!(md != Type::wrapper("ManuallyDrop", &struct_));
+    let p = Local::new(md, "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "Field");
+}
+
 #[test]
 fn blog2() {
     let field = Type::new_generic("Field");
@@ -175,6 +251,25 @@ fn blog3() {
     check(&mut e, "@%MaybeUninit (****p).field", "MaybeUninit<Field>");
 }
 
+#[test]
+fn depth_of_blog3() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let mb_struct = maybe_uninit(&struct_);
+    let ty = shared_ref(&shared_ref(&shared_ref(&mb_struct)));
+    assert_eq!(ty.depth(), 4);
+    assert_eq!(mb_struct.depth(), 1);
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    assert_eq!(e.depth(), 1);
+    check(&mut e, "@%MaybeUninit (****p).field", "MaybeUninit<Field>");
+    assert_eq!(e.depth(), 6);
+    let mut e = place_expr!(****p);
+    assert_eq!(e.depth(), 4);
+    check(&mut e, "****p", "Struct");
+    assert_eq!(e.depth(), 4);
+}
+
 #[test]
 fn blog4() {
     let field = Type::new_generic("Field");
@@ -194,6 +289,159 @@ fn blog5() {
     check(&mut e, "@%MaybeUninit (*p)[42]", "MaybeUninit<u8>");
 }
 
+#[test]
+fn blog5_array() {
+    let u8 = Type::new_generic("u8");
+    let ty = maybe_uninit(&Type::new_array(u8, 4));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p[2]);
+    check(&mut e, "@%MaybeUninit (*p)[2]", "MaybeUninit<u8>");
+    let mut e = place_expr!(p[4]);
+    check_err(
+        &mut e,
+        "the type of `*p: [u8; 4]` has length 4, so the index `4` is out of bounds",
+    );
+}
+
+/// A place wrapper whose fields are the fields of its contents prefixed with `tag_`.
+struct Tagged;
+
+impl WrapperKind for Tagged {
+    fn project(&self, proj: &PlaceProjection, _: &Type) -> ProjectionResult {
+        match proj {
+            PlaceProjection::Field(field) => {
+                ProjectionResult::Lift(PlaceProjection::Field(format!("tag_{field}")))
+            }
+            _ => ProjectionResult::Lift(proj.clone()),
+        }
+    }
+}
+
+#[test]
+fn custom_wrapper_kind() {
+    let y = Type::new_generic("Y");
+    let x = Type::new_struct("X", [Field::new("tag_y", y.clone())]);
+    let s = Type::new_struct("S", [Field::new("tag_x", x.clone())]);
+    let p = Local::new(Type::custom_wrapper("Tagged", &s, Tagged), "p");
+    let mut e = place_expr!(p.x);
+    check(&mut e, "@%Tagged (*p).tag_x", "Tagged<X>");
+    let mut e = place_expr!(p.x.y);
+    check(&mut e, "@%Tagged (*p).tag_x.tag_y", "Tagged<Y>");
+    assert_eq!(
+        Type::custom_wrapper("Tagged", &s, Tagged).projection_behavior("x"),
+        ProjectionBehavior::Lift {
+            derefs: 1,
+            lifted: vec!["Tagged".to_string()],
+        }
+    );
+
+    let p = Local::new(Type::custom_wrapper("Plain", &s, Transparent), "p");
+    let mut e = place_expr!(p.tag_x.tag_y);
+    check(&mut e, "(*p).tag_x.tag_y", "Y");
+    let p = Local::new(Type::custom_wrapper("Lifted", &s, Lifting), "p");
+    let mut e = place_expr!(p.tag_x);
+    check(&mut e, "@%Lifted (*p).tag_x", "Lifted<X>");
+}
+
+/// A wrapper kind that resolves every projection to a dereference, which is not allowed.
+struct DerefOnly;
+
+impl WrapperKind for DerefOnly {
+    fn project(&self, _: &PlaceProjection, _: &Type) -> ProjectionResult {
+        ProjectionResult::Lift(PlaceProjection::Deref)
+    }
+}
+
+#[test]
+fn wrapper_kind_resolving_to_deref() {
+    let s = Type::new_struct("S", [Field::new("x", Type::new_generic("X"))]);
+    let p = Local::new(Type::custom_wrapper("Odd", &s, DerefOnly), "p");
+    let mut e = place_expr!(p.x);
+    check_err(
+        &mut e,
+        "the type of `p: Odd<S>` is a wrapper whose kind resolves a projection through it to \
+         `*`, which is not a field, index, range or downcast",
+    );
+    let err = place_expr!(p.x).compute_ty().unwrap_err();
+    assert!(matches!(
+        err,
+        PlaceTyError::InvalidWrapperProjection {
+            projection: PlaceProjection::Deref,
+            ..
+        }
+    ));
+    assert_eq!(
+        Type::custom_wrapper("Odd", &s, DerefOnly).projection_behavior("x"),
+        ProjectionBehavior::Error
+    );
+}
+
+#[test]
+fn wrapper_kind_by_projection() {
+    let u8 = Type::new_generic("u8");
+    let usize = Type::new_generic("usize");
+    let inner = Type::builder()
+        .element(u8.clone())
+        .field("len", usize)
+        .display("Inner")
+        .build()
+        .unwrap();
+    let kind = || ByProjection {
+        field: Transparent,
+        index: Lifting,
+    };
+    let exotic = Type::custom_wrapper("Exotic", &inner, kind());
+    let p = Local::new(shared_ref(&exotic), "p");
+    let mut e = place_expr!(p.len);
+    check(&mut e, "(**p).len", "usize");
+    let mut e = place_expr!(p[0]);
+    check(&mut e, "@%Exotic (**p)[0]", "Exotic<u8>");
+    let mut e = place_expr!(p[1..]);
+    check(&mut e, "@%Exotic (**p)[1..]", "Exotic<Inner>");
+}
+
+#[test]
+fn cached_wrapper_keeps_kind_of_argument() {
+    let g = Type::new_generic("G");
+    let s = Type::new_struct("S", [Field::new("x", g)]);
+    let transparent = Type::custom_wrapper("Tag", &s, Transparent);
+    let outer_transparent = Type::wrapper("Outer", &transparent);
+    let outer_lifting = Type::wrapper("Outer", &Type::wrapper("Tag", &s));
+    assert!(outer_transparent == outer_lifting);
+    let p = Local::new(outer_lifting, "p");
+    let mut e = place_expr!(p.x);
+    check(&mut e, "@%Outer @%Tag (**p).x", "Outer<Tag<G>>");
+    let p = Local::new(outer_transparent.clone(), "p");
+    let mut e = place_expr!(p.x);
+    check(&mut e, "(**p).x", "G");
+    let again = Type::wrapper("Outer", &transparent);
+    assert_eq!(
+        again.projection_behavior("x"),
+        ProjectionBehavior::DerefThenProject { derefs: 2 }
+    );
+}
+
+#[test]
+fn blog5_cow() {
+    let u8 = Type::new_generic("u8");
+    let ty = Type::transparent_wrapper("Cow", &slice(&u8));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p[42]);
+    check(&mut e, "(*p)[42]", "u8");
+    let mut e = place_expr!(p[1..][0]);
+    check(&mut e, "(*p)[1..][0]", "u8");
+
+    let arr = Type::new_array(u8.clone(), 4);
+    let p = Local::new(Type::transparent_wrapper("Cow", &arr), "p");
+    let mut e = place_expr!(p[1..]);
+    check(&mut e, "(*p)[1..]", "[u8]");
+    let mut e = place_expr!(p[4]);
+    check_err(
+        &mut e,
+        "the type of `*p: [u8; 4]` has length 4, so the index `4` is out of bounds",
+    );
+}
+
 #[test]
 fn multi_field_auto_deref() {
     let z = Type::new_generic("Z");
@@ -205,6 +453,53 @@ fn multi_field_auto_deref() {
     check(&mut e, "*(*(*(*p).x).y).z", "Z");
 }
 
+#[test]
+fn deref_chain() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", shared_ref(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", shared_ref(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", shared_ref(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+    let mut e = place_expr!(*p.x.y.z);
+    check(&mut e, "*(*(*(*p).x).y).z", "Z");
+    let derefs = e
+        .deref_chain()
+        .iter()
+        .map(|(from, to)| format!("{from} -> {to}"))
+        .collect::<Vec<_>>();
+    assert_eq!(derefs, ["&E -> E", "&X -> X", "&Y -> Y", "&Z -> Z"]);
+}
+
+#[test]
+fn alternate_display_marks_auto_derefs() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", shared_ref(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", shared_ref(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", shared_ref(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+    let mut e = place_expr!(*p.x.y.z);
+    check(&mut e, "*(*(*(*p).x).y).z", "Z");
+    assert_eq!(format!("{e:#}"), "*(*⟨auto⟩(*⟨auto⟩(*⟨auto⟩p).x).y).z");
+}
+
+#[test]
+fn types_along_chain() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", shared_ref(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", shared_ref(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", shared_ref(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+    let mut e = place_expr!(*p.x.y.z);
+    assert_eq!(e.types_along_chain().len(), 1);
+    check(&mut e, "*(*(*(*p).x).y).z", "Z");
+    let types = e
+        .types_along_chain()
+        .iter()
+        .map(|ty| ty.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(types, ["&E", "E", "&X", "X", "&Y", "Y", "&Z", "Z"]);
+}
+
 #[test]
 fn multi_wrapper() {
     let z = Type::new_generic("Z");
@@ -220,6 +515,90 @@ fn multi_wrapper() {
     );
 }
 
+#[test]
+fn context_of_multi_wrapper() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", maybe_uninit(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+    let mut e = place_expr!(p.x.y.z);
+    e.compute_ty().unwrap();
+    let ctx = e.context();
+    let lifts = ctx
+        .iter()
+        .filter(|c| matches!(c, Context::LiftWrapper(_)))
+        .count();
+    assert_eq!(lifts, 2);
+    let ctx = ctx.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        ctx,
+        [
+            "p: &E",
+            "deref of `&E`",
+            "E.x: MaybeUninit<X>",
+            "deref of `MaybeUninit<X>` (via `MaybeUninit`)",
+            "X.y: MaybeUninit<Y>",
+            "deref of `MaybeUninit<Y>` (via `MaybeUninit`)",
+            "Y.z: MaybeUninit<Z>",
+            "lift of place wrapper `MaybeUninit`",
+            "lift of place wrapper `MaybeUninit`",
+        ]
+    );
+}
+
+#[test]
+fn debug_tree_of_multi_wrapper() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", maybe_uninit(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+    let mut e = place_expr!(p.x.y.z);
+    e.compute_ty().unwrap();
+    assert_eq!(
+        e.debug_tree(),
+        "\
+@%MaybeUninit: MaybeUninit<MaybeUninit<MaybeUninit<Z>>>
+  @%MaybeUninit: MaybeUninit<MaybeUninit<Z>>
+    .z: MaybeUninit<Z>
+      *: Y
+        .y: MaybeUninit<Y>
+          *: X
+            .x: MaybeUninit<X>
+              *: E
+                p: &E
+"
+    );
+    assert_eq!(
+        maybe_uninit(&x).debug_tree(),
+        "\
+MaybeUninit<X> (wrapper `MaybeUninit`)
+  target: X
+    .y: MaybeUninit<Y> (wrapper `MaybeUninit`)
+      target: Y
+        .z: MaybeUninit<Z> (wrapper `MaybeUninit`)
+          target: Z
+"
+    );
+}
+
+#[test]
+fn generics_of_wrapper_graph() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", maybe_uninit(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    let ty = shared_ref(&e);
+    assert_eq!(ty.generics().into_iter().collect::<Vec<_>>(), ["Z"]);
+
+    let concrete = ty.subst(&[("Z", Type::new_generic("u8"))]);
+    assert_eq!(concrete.generics().into_iter().collect::<Vec<_>>(), ["u8"]);
+    let pair = Type::new_tuple([maybe_uninit(&Type::new_generic("A")), slice(&z)]);
+    assert_eq!(pair.generics().into_iter().collect::<Vec<_>>(), ["A", "Z"]);
+    assert!(Type::new_struct("Unit", []).generics().is_empty());
+}
+
 #[test]
 fn multi_wrapper2() {
     let z = Type::new_generic("Z");
@@ -238,6 +617,26 @@ fn multi_wrapper2() {
     );
 }
 
+#[test]
+fn place_expr_wrappers_by_name() {
+    let z = Type::new_generic("Z");
+    let mbz = maybe_uninit(&z);
+    let y = Type::new_struct("Y", [Field::new("z", mbz.clone())]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+    let wrappers = Map::from([("MaybeUninit".to_string(), maybe_uninit(&z))]);
+    let mut e =
+        place_expr!(in wrappers; @%MaybeUninit @%MaybeUninit @%MaybeUninit *(*(*(*p).x).y).z);
+    check(
+        &mut e,
+        "@%MaybeUninit @%MaybeUninit @%MaybeUninit *(*(*(*p).x).y).z",
+        "MaybeUninit<MaybeUninit<MaybeUninit<Z>>>",
+    );
+    let mut e = place_expr!(in Map::new(); @%MaybeUninit *(*p).x);
+    check(&mut e, "@%MaybeUninit *(*p).x", "MaybeUninit<X>");
+}
+
 #[test]
 fn unexpected_maybe_uninit() {
     let field = Type::new_generic("Field");
@@ -258,3 +657,3249 @@ fn unexpected_maybe_uninit() {
         "MaybeUninit<Field>",
     );
 }
+
+#[test]
+fn lint() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let ty = maybe_uninit(&shared_ref(&maybe_uninit(&struct_)));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "@%MaybeUninit (***p).field", "MaybeUninit<Field>");
+    let lints = e.lint();
+    assert_eq!(lints.len(), 1);
+    let Lint::StrictWrapperDeref { deref, wrapper } = &lints[0];
+    assert_eq!(deref.to_string(), "*p");
+    assert!(*wrapper == p.ty());
+    assert_eq!(
+        lints[0].to_string(),
+        "warning: `*p` dereferences the place wrapper `MaybeUninit<&MaybeUninit<Struct>>` without \
+         wrapping the result in `@%MaybeUninit`"
+    );
+
+    let p = Local::new(maybe_uninit(&struct_), "p");
+    let mut e = place_expr!(p.field);
+    e.compute_ty().unwrap();
+    assert_eq!(e.lint(), []);
+    let mut e = place_expr!((*p).field);
+    e.compute_ty().unwrap();
+    assert_eq!(e.lint().len(), 1);
+}
+
+#[test]
+fn tuple_field() {
+    let a = Type::new_generic("A");
+    let b = Type::new_generic("B");
+    let tup = Type::new_tuple([a, b]);
+    let p = Local::new(shared_ref(&tup), "p");
+    let mut e = place_expr!(p.1);
+    check(&mut e, "(*p).1", "B");
+}
+
+#[test]
+fn nested_tuple_field() {
+    let a = Type::new_generic("A");
+    let b = Type::new_generic("B");
+    let inner = Type::new_tuple([a.clone(), b]);
+    let tup = Type::new_tuple([inner, a]);
+    let p = Local::new(tup, "p");
+    let mut e = place_expr!(p.0.1);
+    check(&mut e, "p.0.1", "B");
+}
+
+#[test]
+fn tuple_field_maybe_uninit() {
+    let a = Type::new_generic("A");
+    let b = Type::new_generic("B");
+    let tup = Type::new_tuple([a, b]);
+    let p = Local::new(maybe_uninit(&tup), "p");
+    let mut e = place_expr!(p.0);
+    check(&mut e, "@%MaybeUninit (*p).0", "MaybeUninit<A>");
+}
+
+#[test]
+fn tuple_field_out_of_range() {
+    let a = Type::new_generic("A");
+    let b = Type::new_generic("B");
+    let tup = Type::new_tuple([a, b]);
+    let p = Local::new(shared_ref(&tup), "p");
+    let mut e = place_expr!(p.3);
+    check_err(
+        &mut e,
+        "the type of `*p: (A, B)` has no field `3`, it only has 2 elements",
+    );
+}
+
+#[test]
+fn mut_ref_deref() {
+    let t = Type::new_generic("T");
+    let p = Local::new(Type::new_mut_ref(t), "p");
+    let mut e = place_expr!(*p);
+    check(&mut e, "*p", "T");
+    assert_eq!(e.deref_mutability(), Some(Mutability::Mut));
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"deref of `&mut T` (mutable)".to_string()));
+}
+
+#[test]
+fn mut_ref_to_shared_ref_field() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field)]);
+    let ty = Type::new_mut_ref(Type::new_ref(struct_));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(**p).field", "Field");
+    assert_eq!(e.deref_mutability(), Some(Mutability::Not));
+}
+
+#[test]
+fn shared_ref_to_mut_ref_field() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field)]);
+    let ty = Type::new_ref(Type::new_mut_ref(struct_));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(**p).field", "Field");
+    assert_eq!(e.deref_mutability(), Some(Mutability::Mut));
+}
+
+#[test]
+fn raw_ptr_deref() {
+    let t = Type::new_generic("T");
+    let p = Local::new(Type::new_raw_ptr(t, true), "p");
+    let mut e = place_expr!(*p);
+    check(&mut e, "*p", "T");
+    assert_eq!(e.deref_mutability(), Some(Mutability::Mut));
+}
+
+#[test]
+fn raw_ptr_explicit_deref_field() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field)]);
+    let p = Local::new(Type::new_raw_ptr(struct_, false), "p");
+    let mut e = place_expr!((*p).field);
+    check(&mut e, "(*p).field", "Field");
+}
+
+#[test]
+fn raw_ptr_no_auto_deref() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field)]);
+    let p = Local::new(shared_ref(&Type::new_raw_ptr(struct_, false)), "p");
+    let mut e = place_expr!(p.field);
+    check_err(
+        &mut e,
+        "the type of `*p: *const Struct` is a raw pointer, which must be dereferenced explicitly",
+    );
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"explicit deref of raw pointer `*const Struct` required".to_string()));
+}
+
+#[test]
+fn array_index() {
+    let u8 = Type::new_generic("u8");
+    let ty = shared_ref(&Type::new_array(u8, 4));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p[3]);
+    check(&mut e, "(*p)[3]", "u8");
+}
+
+#[test]
+fn array_index_out_of_bounds() {
+    let u8 = Type::new_generic("u8");
+    let ty = Type::new_array(u8, 4);
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p[4]);
+    check_err(
+        &mut e,
+        "the type of `p: [u8; 4]` has length 4, so the index `4` is out of bounds",
+    );
+}
+
+#[test]
+fn slice_index_unchecked() {
+    let u8 = Type::new_generic("u8");
+    let p = Local::new(shared_ref(&slice(&u8)), "p");
+    let mut e = place_expr!(p[100]);
+    check(&mut e, "(*p)[100]", "u8");
+}
+
+#[test]
+fn range_index() {
+    let u8 = Type::new_generic("u8");
+    let s = slice(&u8);
+    let p = Local::new(shared_ref(&s), "p");
+    let mut e = place_expr!(p[1..3]);
+    check(&mut e, "(*p)[1..3]", "[u8]");
+    let mut e = place_expr!(p[1..]);
+    check(&mut e, "(*p)[1..]", "[u8]");
+    let mut e = place_expr!(p[..3]);
+    check(&mut e, "(*p)[..3]", "[u8]");
+    let mut e = place_expr!(p[..]);
+    check(&mut e, "(*p)[..]", "[u8]");
+}
+
+#[test]
+fn range_index_array() {
+    let u8 = Type::new_generic("u8");
+    let p = Local::new(Type::new_array(u8.clone(), 4), "p");
+    let mut e = place_expr!(p[1..4][0]);
+    check(&mut e, "p[1..4][0]", "u8");
+    let mut e = place_expr!(p[2..5]);
+    check_err(
+        &mut e,
+        "the type of `p: [u8; 4]` has length 4, so the index `2..5` is out of bounds",
+    );
+    let mut e = place_expr!(p[3..1]);
+    check_err(
+        &mut e,
+        "the type of `p: [u8; 4]` has length 4, so the index `3..1` is out of bounds",
+    );
+    let mut e = place_expr!(p[2..2]);
+    check(&mut e, "p[2..2]", "[u8]");
+}
+
+#[test]
+fn range_index_maybe_uninit() {
+    let u8 = Type::new_generic("u8");
+    let ty = maybe_uninit(&slice(&u8));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p[1..]);
+    check(&mut e, "@%MaybeUninit (*p)[1..]", "MaybeUninit<[u8]>");
+}
+
+fn option(t: &Type) -> Type {
+    Type::new_enum(
+        &format!("Option<{t}>"),
+        vec![
+            ("None".to_string(), vec![]),
+            ("Some".to_string(), vec![Field::new("0", t.clone())]),
+        ],
+    )
+}
+
+#[test]
+fn downcast() {
+    let t = Type::new_generic("T");
+    let p = Local::new(shared_ref(&option(&t)), "p");
+    let mut e = place_expr!((p as Some).0);
+    check(&mut e, "(*p as Some).0", "T");
+}
+
+#[test]
+fn downcast_maybe_uninit() {
+    let t = Type::new_generic("T");
+    let p = Local::new(maybe_uninit(&option(&t)), "p");
+    let mut e = place_expr!((p as Some).0);
+    check(&mut e, "@%MaybeUninit (*p as Some).0", "MaybeUninit<T>");
+}
+
+#[test]
+fn downcast_unknown_variant() {
+    let t = Type::new_generic("T");
+    let p = Local::new(option(&t), "p");
+    let mut e = place_expr!((p as Ok).0);
+    check_err(
+        &mut e,
+        "the type of `p: Option<T>` has no variant named `Ok`",
+    );
+}
+
+#[test]
+fn ambiguous_enum_field() {
+    let u8 = Type::new_generic("u8");
+    let u16 = Type::new_generic("u16");
+    let message = Type::new_enum(
+        "Message",
+        vec![
+            ("Short".to_string(), vec![Field::new("tag", u8.clone())]),
+            ("Long".to_string(), vec![Field::new("tag", u16.clone())]),
+        ],
+    );
+    let p = Local::new(shared_ref(&message), "p");
+    let mut e = place_expr!(p.tag);
+    let err = e.compute_ty().unwrap_err();
+    let PlaceTyError::AmbiguousField { variants, .. } = &err else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(variants, &["Long", "Short"]);
+    assert_eq!(
+        err.to_string(),
+        "the type of `*p: Message` is an enum whose variants `Long`, `Short` all have a field \
+         `tag`, so it must be downcast to one of them first"
+    );
+    let mut e = place_expr!((p as Long).tag);
+    check(&mut e, "(*p as Long).tag", "u16");
+
+    let t = Type::new_generic("T");
+    let p = Local::new(option(&t), "p");
+    let mut e = place_expr!(p.0);
+    check_err(
+        &mut e,
+        "the type of `p: Option<T>` is not a struct, so it has no field `0`",
+    );
+}
+
+#[test]
+fn box_field() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let p = Local::new(boxed(&struct_), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "Field");
+}
+
+#[test]
+fn box_maybe_uninit_field() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let p = Local::new(boxed(&maybe_uninit(&struct_)), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "@%MaybeUninit (**p).field", "MaybeUninit<Field>");
+    let p = Local::new(maybe_uninit(&boxed(&struct_)), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(**p).field", "Field");
+}
+
+#[test]
+fn place_deref_checked() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let impls = PlaceDerefImpls::new();
+
+    let p = Local::new(maybe_uninit(&struct_), "p");
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty_with_place_deref(&impls).ok().unwrap();
+    assert_eq!(
+        format!("{e}: {ty}"),
+        "@%MaybeUninit (*p).field: MaybeUninit<Field>"
+    );
+
+    let ty = maybe_uninit(&shared_ref(&maybe_uninit(&struct_)));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    let err = e.compute_ty_with_place_deref(&impls).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "the type of `p: MaybeUninit<&MaybeUninit<Struct>>` does not implement `PlaceDeref`, so it \
+         cannot be dereferenced in `*p` without wrapping the result in `@%MaybeUninit`"
+    );
+
+    let mut impls = PlaceDerefImpls::new();
+    impls.register_wrapper("MaybeUninit");
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty_with_place_deref(&impls).ok().unwrap();
+    assert_eq!(
+        format!("{e}: {ty}"),
+        "@%MaybeUninit (***p).field: MaybeUninit<Field>"
+    );
+}
+
+#[test]
+fn unexpected_maybe_uninit_checked() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let ty = shared_ref(&maybe_uninit(&shared_ref(&maybe_uninit(&struct_))));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    let err = e.compute_ty_checked().err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "the type of `*p: MaybeUninit<&MaybeUninit<Struct>>` does not implement `PlaceDeref`, so \
+         it cannot be dereferenced in `**p` without wrapping the result in `@%MaybeUninit`"
+    );
+
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty().ok().unwrap();
+    assert_eq!(
+        format!("{e}: {ty}"),
+        "@%MaybeUninit (****p).field: MaybeUninit<Field>"
+    );
+}
+
+#[test]
+fn structural_type_eq() {
+    let t = Type::new_generic("T");
+    let other = Type::new(
+        Some(t.clone()),
+        None,
+        Some(Box::new(|ty| maybe_uninit(&ty))),
+        Some("MaybeUninit".to_string()),
+        Map::new(),
+        format!("core::mem::MaybeUninit<{t}>"),
+    );
+    assert!(maybe_uninit(&t) == other);
+    assert!(maybe_uninit(&t) == maybe_uninit(&Type::new_generic("T")));
+    assert!(shared_ref(&maybe_uninit(&t)) == shared_ref(&other));
+    assert!(maybe_uninit(&t) != maybe_uninit(&Type::new_generic("U")));
+    assert!(Type::new_ref(t.clone()) != Type::new_mut_ref(t.clone()));
+    assert!(Type::new_array(t.clone(), 3) != Type::new_array(t.clone(), 4));
+
+    let field = Type::new_generic("Field");
+    let a = Type::new_struct("A", [Field::new("field", field.clone())]);
+    let b = Type::new_struct("B", [Field::new("field", field.clone())]);
+    assert!(a == Type::new_struct("A", [Field::new("field", field.clone())]));
+    assert!(a != b);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_type_round_trip() {
+    let u8 = Type::new_generic("u8");
+    let ty = maybe_uninit(&slice(&u8));
+    let json = serde_json::to_string(&ty).unwrap();
+    let de: Type = serde_json::from_str(&json).unwrap();
+    assert!(de == ty);
+    assert_eq!(de.to_string(), "MaybeUninit<[u8]>");
+
+    let p = Local::new(de, "p");
+    let mut e = place_expr!(p[42]);
+    check(&mut e, "@%MaybeUninit (*p)[42]", "MaybeUninit<u8>");
+
+    let res = Type::wrapper_with_args("Result", &[slice(&u8), Type::new_generic("E")], 0);
+    let json = serde_json::to_string(&res).unwrap();
+    let de: Type = serde_json::from_str(&json).unwrap();
+    assert!(de == res);
+    let p = Local::new(de, "p");
+    let mut e = place_expr!(p[42]);
+    check(&mut e, "@%Result (*p)[42]", "Result<u8, E>");
+
+    let s = Type::new_struct("S", [Field::new("b", u8.clone()), Field::new("a", u8)]);
+    let json = serde_json::to_string(&s).unwrap();
+    assert!(json.find("\"b\"").unwrap() < json.find("\"a\"").unwrap());
+    let de: Type = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&de).unwrap(), json);
+
+    let u32 = Type::new_generic("u32");
+    let private = Field::new_full("b", u32.clone(), Visibility::Private, Some(4));
+    let s = Type::new_struct("S", [Field::new("a", u32), private]);
+    let json = serde_json::to_string(&s).unwrap();
+    let de: Type = serde_json::from_str(&json).unwrap();
+    assert!(de == s);
+    assert!(json.contains(r#""vis":"Private","offset":4"#));
+
+    let json = r#"{"display":"S","kind":"Struct","fields":{"a":{"display":"u8","kind":"Generic"},"a":{"display":"u8","kind":"Generic"}}}"#;
+    let err = serde_json::from_str::<Type>(json).unwrap_err();
+    assert!(err.to_string().starts_with("duplicate field `a`"), "{err}");
+
+    let invalid = [
+        r#"{"display":"Pin<X>","kind":"Pin"}"#,
+        r#"{"display":"&X","kind":{"Ref":"Not"}}"#,
+        r#"{"display":"[X; 2]","kind":{"Array":2}}"#,
+        r#"{"display":"Cell","kind":{"Cell":{"value":false}}}"#,
+        r#"{"display":"(X,)","kind":{"Tuple":1}}"#,
+        r#"{"display":"W<X>","kind":"Opaque","wrapper_name":"W"}"#,
+    ];
+    for json in invalid {
+        let err = serde_json::from_str::<Type>(json).unwrap_err();
+        assert!(err.to_string().starts_with("invalid type"), "{json}: {err}");
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_place_round_trip() {
+    let z = Type::new_generic("Z");
+    let mbz = maybe_uninit(&z);
+    let mb2z = maybe_uninit(&mbz);
+    let mb3z = maybe_uninit(&mb2z);
+    let y = Type::new_struct("Y", [Field::new("z", mbz.clone())]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+    let mut e = place_expr!(@%mb3z @%mb2z @%mbz *(*(*(*p).x).y).z);
+    let json = serde_json::to_string(&e).unwrap();
+    let mut de: Box<PlaceExpr> = serde_json::from_str(&json).unwrap();
+    assert_eq!(de.to_string(), e.to_string());
+    let ty = e.compute_ty().ok().unwrap();
+    let de_ty = de.compute_ty().ok().unwrap();
+    assert_eq!(de.to_string(), e.to_string());
+    assert!(de_ty == ty);
+    assert_eq!(
+        serde_json::to_string(&de).unwrap(),
+        serde_json::to_string(&e).unwrap()
+    );
+}
+
+#[test]
+fn structured_errors() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let p = Local::new(shared_ref(&struct_), "p");
+
+    let mut e = place_expr!(p.missing);
+    match e.compute_ty() {
+        Err(PlaceTyError::UnknownField { on_type, field, .. }) => {
+            assert!(on_type == struct_);
+            assert_eq!(field, "missing");
+        }
+        res => panic!("expected `UnknownField`, got {res:?}"),
+    }
+
+    let mut e = place_expr!(p[0]);
+    let err = e.compute_ty().unwrap_err();
+    assert!(matches!(err, PlaceTyError::IndexOnNonIndexable { .. }));
+    assert_eq!(
+        err.to_string(),
+        "the type of `*p: Struct` is a struct, so it cannot be indexed and no indexable type is \
+         reachable through dereferences"
+    );
+
+    let mut e = place_expr!(**p);
+    let err = e.compute_ty().unwrap_err();
+    assert!(matches!(err, PlaceTyError::DerefOnNonPointer { .. }));
+    assert_eq!(
+        err.to_string(),
+        "the type of `*p: Struct` should implement `HasPlace`"
+    );
+
+    let ty = maybe_uninit(&shared_ref(&maybe_uninit(&struct_)));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    match e.compute_ty_checked() {
+        Err(PlaceTyError::WrapperDerefNotAllowed { wrapper, deref, .. }) => {
+            assert_eq!(wrapper.to_string(), "MaybeUninit<&MaybeUninit<Struct>>");
+            assert_eq!(deref.to_string(), "*p");
+        }
+        res => panic!("expected `WrapperDerefNotAllowed`, got {res:?}"),
+    }
+}
+
+#[test]
+fn error_location() {
+    let struct_ = Type::new_struct("Struct", vec![]);
+    let p = Local::new(shared_ref(&struct_), "p");
+    let i = Local::new(struct_.clone(), "i");
+    let arr = Local::new(Type::new_array(struct_.clone(), 4), "arr");
+
+    let line = line!() + 1;
+    let mut e = place_expr!(p.missing);
+    let err = e.compute_ty().unwrap_err();
+    let location = err.location().unwrap();
+    assert_eq!((location.file, location.line), (file!(), line));
+    assert_eq!(e.location(), Some(location));
+    assert_eq!(
+        format!("{err:#}"),
+        format!("{err}\n  --> {}:{line}:{}", file!(), location.column)
+    );
+
+    // The error is about the index local, which is marked by the same invocation.
+    let line = line!() + 1;
+    let mut e = place_expr!(arr[i]);
+    let err = e.compute_ty().unwrap_err();
+    assert!(matches!(err, PlaceTyError::IndexNotUsize { .. }));
+    assert_eq!(err.location().map(|location| location.line), Some(line));
+    let locals = [i.clone()];
+    let line = line!() + 1;
+    let mut interpolated = place_expr!(arr[#locals[0]]);
+    let err = interpolated.compute_ty().unwrap_err();
+    assert_eq!(err.location().map(|location| location.line), Some(line));
+
+    // Marking a local variable does not make it a different one.
+    assert_eq!(arr.at(err.location().unwrap()), arr);
+    let locals = Map::from([("p".to_string(), p.ty())]);
+    let mut parsed = PlaceExpr::parse("p.missing", &locals).unwrap();
+    let err = parsed.compute_ty().unwrap_err();
+    assert_eq!(err.location(), None);
+    assert_eq!(format!("{err:#}"), format!("{err}"));
+}
+
+#[test]
+fn type_builder() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::builder()
+        .field("field", field.clone())
+        .display("Struct")
+        .build()
+        .unwrap();
+    let inner = struct_.clone();
+    let ty = Type::builder()
+        .target(struct_.clone())
+        .wrapper("MaybeUninit", |ty| maybe_uninit(&ty))
+        .display(format!("MaybeUninit<{inner}>"))
+        .build()
+        .unwrap();
+    assert!(ty == maybe_uninit(&struct_));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "@%MaybeUninit (*p).field", "MaybeUninit<Field>");
+
+    let err = Type::builder()
+        .target(field.clone())
+        .element(field.clone())
+        .display("Weird")
+        .build()
+        .unwrap_err();
+    assert_eq!(err, TypeBuildError::TargetAndElement);
+    let err = Type::builder()
+        .wrapper("MaybeUninit", |ty| maybe_uninit(&ty))
+        .display("MaybeUninit")
+        .build()
+        .unwrap_err();
+    assert_eq!(err, TypeBuildError::WrapperWithoutTarget);
+    let err = Type::builder()
+        .target(struct_.clone())
+        .wrapper("MaybeUninit", |ty| Type::wrapper("Vec", &ty))
+        .display(format!("MaybeUninit<{inner}>"))
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        TypeBuildError::WrapperNameMismatch {
+            name: "MaybeUninit".into(),
+            found: Some("Vec".into()),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "the wrapper `MaybeUninit` wraps types in `Vec` instead"
+    );
+    fn mu(ty: Type) -> Type {
+        Type::builder()
+            .target(ty.clone())
+            .wrapper("MU", mu)
+            .display(format!("MU<{ty}>"))
+            .build()
+            .unwrap()
+    }
+    let ty = mu(struct_.clone());
+    assert_eq!(ty.to_string(), format!("MU<{inner}>"));
+    assert_eq!(ty.wrapper_name(), Some("MU"));
+    let err = Type::builder()
+        .target(struct_.clone())
+        .wrapper("MaybeUninit", mu)
+        .display(format!("MaybeUninit<{inner}>"))
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        TypeBuildError::WrapperNameMismatch {
+            name: "MaybeUninit".into(),
+            found: Some("MU".into()),
+        }
+    );
+    let err = Type::builder()
+        .field("a", field.clone())
+        .field("a", field.clone())
+        .display("S")
+        .build()
+        .unwrap_err();
+    assert_eq!(err, TypeBuildError::DuplicateField("a".to_string()));
+    let err = Type::builder()
+        .target(field.clone())
+        .transparent()
+        .display("ManuallyDrop")
+        .build()
+        .unwrap_err();
+    assert_eq!(err, TypeBuildError::TransparentWithoutWrapper);
+}
+
+#[test]
+fn wrapper_factory_is_cached() {
+    let t = Type::new_generic("T");
+    let a = Type::wrapper("MaybeUninit", &t);
+    let b = Type::wrapper("MaybeUninit", &Type::new_generic("T"));
+    assert!(a == b);
+    assert_eq!(a.to_string(), "MaybeUninit<T>");
+    assert!(a != Type::wrapper("ManuallyDrop", &t));
+}
+
+#[test]
+fn pin_deref() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let p = Local::new(Type::new_pin(Type::new_mut_ref(struct_.clone())), "p");
+    let mut e = place_expr!(*p);
+    check(&mut e, "*p", "Struct");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "Field");
+}
+
+#[test]
+fn pin_without_deref() {
+    let t = Type::new_generic("T");
+    let p = Local::new(Type::new_pin(t), "p");
+    let mut e = place_expr!(*p);
+    check_err(
+        &mut e,
+        "the type of `p: Pin<T>` should implement `HasPlace`",
+    );
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(
+        &"`Pin<T>` cannot be dereferenced, since `T` does not implement `Deref`".to_string()
+    ));
+}
+
+#[test]
+fn cell_projection() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let cell = Type::new_cell("Cell", "get", struct_);
+    let p = Local::new(shared_ref(&cell), "p");
+    let mut e = place_expr!(p.get);
+    check(&mut e, "(*p).get", "Struct");
+    let mut e = place_expr!(p.get.field);
+    check(&mut e, "(*p).get.field", "Field");
+    let mut e = place_expr!(p.field);
+    let err = e.compute_ty().unwrap_err();
+    assert!(matches!(err, PlaceTyError::CellProjectionRequired { .. }));
+    assert_eq!(
+        err.to_string(),
+        "the type of `*p: Cell<Struct>` is a cell, its contents must be accessed explicitly via \
+         `.get`"
+    );
+}
+
+#[test]
+fn simplify_wrappers() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let mb = Type::idempotent_wrapper("MaybeUninit", &struct_);
+    let mb_mb = Type::idempotent_wrapper("MaybeUninit", &mb);
+    let p = Local::new(mb_mb.clone(), "p");
+    let mut e = place_expr!(p.field);
+    check(
+        &mut e,
+        "@%MaybeUninit @%MaybeUninit (**p).field",
+        "MaybeUninit<MaybeUninit<Field>>",
+    );
+    let ty = e.compute_ty().unwrap().simplify_wrappers();
+    assert_eq!(ty.to_string(), "MaybeUninit<Field>");
+    assert!(ty == Type::idempotent_wrapper("MaybeUninit", &field));
+    assert!(mb_mb.simplify_wrappers() == mb);
+
+    let nested = maybe_uninit(&maybe_uninit(&struct_));
+    assert_eq!(
+        nested.simplify_wrappers().to_string(),
+        "MaybeUninit<MaybeUninit<Struct>>"
+    );
+    assert!(shared_ref(&mb_mb).simplify_wrappers() == shared_ref(&mb_mb));
+}
+
+#[test]
+fn generic_subst() {
+    let t = Type::new_generic("T");
+    let u = Type::new_generic("U");
+    let u8_ty = Type::new_generic("u8");
+    let i32_ty = Type::new_generic("i32");
+    let pair = Type::new_struct(
+        "Pair<T, U>",
+        vec![
+            Field::new("first", Type::new_mut_ref(t.clone())),
+            Field::new("second", Type::new_array(maybe_uninit(&u), 2)),
+            Field::new("boxed", boxed(&Type::new_tuple([t.clone(), u.clone()]))),
+        ],
+    );
+    let substs = [("T", u8_ty.clone()), ("U", i32_ty.clone())];
+    let concrete = pair.subst(&substs);
+    assert_eq!(concrete.to_string(), "Pair<u8, i32>");
+
+    let p = Local::new(concrete, "p");
+    let mut e = place_expr!(*p.first);
+    check(&mut e, "*p.first", "u8");
+    let mut e = place_expr!(p.second[0]);
+    check(&mut e, "p.second[0]", "MaybeUninit<i32>");
+    let mut e = place_expr!(p.boxed.1);
+    check(&mut e, "(*p.boxed).1", "i32");
+    let mut e = place_expr!(p.boxed);
+    let ty = e.compute_ty().unwrap();
+    assert!(ty == boxed(&Type::new_tuple([u8_ty.clone(), i32_ty])));
+
+    assert!(maybe_uninit(&t).subst(&substs) == maybe_uninit(&u8_ty));
+    assert!(pair.subst(&[]) == pair);
+}
+
+/// Checks that desugaring `place` prints as `printed`, and that parsing the printed form via
+/// `place_expr!`, given as `reparsed`, results in the same place with the same type.
+fn round_trip(mut place: Box<PlaceExpr>, printed: &str, mut reparsed: Box<PlaceExpr>) {
+    init_logging();
+    let ty = place.compute_ty().unwrap();
+    assert_eq!(place.to_string(), printed);
+    assert_eq!(reparsed, place);
+    assert!(reparsed.compute_ty().unwrap() == ty);
+    assert_eq!(reparsed.to_string(), printed);
+}
+
+#[test]
+fn display_precedence_round_trip() {
+    let t = Type::new_generic("T");
+    let inner = Type::new_struct("Inner", vec![Field::new("g", t.clone())]);
+    let outer = Type::new_struct(
+        "Outer",
+        vec![
+            Field::new("f", Type::new_mut_ref(inner.clone())),
+            Field::new("a", Type::new_array(Type::new_ref(inner.clone()), 2)),
+            Field::new("o", Type::new_ref(option(&t))),
+        ],
+    );
+    let p = Local::new(outer.clone(), "p");
+    let q = Local::new(Type::new_ref(outer.clone()), "q");
+    let r = Local::new(Type::new_ref(Type::new_array(inner.clone(), 2)), "r");
+    let mb = maybe_uninit(&inner);
+    let m = Local::new(mb.clone(), "m");
+
+    round_trip(place_expr!(*p.f), "*p.f", place_expr!(*p.f));
+    round_trip(place_expr!(q.f), "(*q).f", place_expr!((*q).f));
+    round_trip(place_expr!(*p.a[0]), "*p.a[0]", place_expr!(*p.a[0]));
+    round_trip(place_expr!(r[0]), "(*r)[0]", place_expr!((*r)[0]));
+    round_trip(place_expr!(r[..1]), "(*r)[..1]", place_expr!((*r)[..1]));
+    round_trip(place_expr!(q.f.g), "(*(*q).f).g", place_expr!((*(*q).f).g));
+    round_trip(
+        place_expr!(p.a[1].g),
+        "(*p.a[1]).g",
+        place_expr!((*p.a[1]).g),
+    );
+    round_trip(
+        place_expr!(m.g),
+        "@%MaybeUninit (*m).g",
+        place_expr!(@%mb (*m).g),
+    );
+    round_trip(
+        place_expr!((p.o as Some).0),
+        "((*p.o) as Some).0",
+        place_expr!(((*p.o) as Some).0),
+    );
+    round_trip(
+        place_expr!((q.o as Some).0),
+        "((*(*q).o) as Some).0",
+        place_expr!(((*(*q).o) as Some).0),
+    );
+}
+
+#[test]
+fn visitor_counts_derefs() {
+    #[derive(Default)]
+    struct Counter {
+        derefs: Vec<String>,
+        fields: Vec<String>,
+        wrappers: usize,
+        local: Option<String>,
+    }
+
+    impl PlaceVisitor for Counter {
+        fn visit_local(&mut self, local: &Local) {
+            self.local = Some(local.to_string());
+        }
+
+        fn visit_deref(&mut self, base: &PlaceExpr, _ty: Option<Type>) {
+            self.derefs.push(base.ty().unwrap().to_string());
+        }
+
+        fn visit_field(&mut self, _base: &PlaceExpr, field: &str, ty: Option<Type>) {
+            self.fields.push(format!("{field}: {}", ty.unwrap()));
+        }
+
+        fn visit_wrapper(&mut self, _base: &PlaceExpr, _wrapper: &Type, _ty: Option<Type>) {
+            self.wrappers += 1;
+        }
+    }
+
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let p = Local::new(shared_ref(&shared_ref(&maybe_uninit(&struct_))), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "@%MaybeUninit (***p).field", "MaybeUninit<Field>");
+    let mut counter = Counter::default();
+    e.visit(&mut counter);
+    assert_eq!(
+        counter.derefs,
+        [
+            "MaybeUninit<Struct>",
+            "&MaybeUninit<Struct>",
+            "&&MaybeUninit<Struct>"
+        ]
+    );
+    assert_eq!(counter.fields, ["field: Field"]);
+    assert_eq!(counter.wrappers, 1);
+    assert_eq!(counter.local.as_deref(), Some("p"));
+}
+
+#[test]
+fn map_projections_strip_maybe_uninit() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let p = Local::new(shared_ref(&maybe_uninit(&struct_)), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "@%MaybeUninit (**p).field", "MaybeUninit<Field>");
+
+    let mut stripped = e.map_projections(|proj| match proj {
+        PlaceProjection::Wrap(w) if w.wrapper_name() == Some("MaybeUninit") => Rewrite::Drop,
+        _ => Rewrite::Keep,
+    });
+    check(&mut stripped, "(**p).field", "Field");
+    assert!(matches!(
+        stripped.compute_ty_checked(),
+        Err(PlaceTyError::WrapperDerefNotAllowed { .. })
+    ));
+
+    let mut renamed = stripped.map_projections(|proj| match proj {
+        PlaceProjection::Field(_) => Rewrite::Replace(PlaceProjection::Field("other".into())),
+        _ => Rewrite::Keep,
+    });
+    assert_eq!(renamed.to_string(), "(**p).other");
+    check_err(
+        &mut renamed,
+        "the type of `**p: Struct` has no field `other`",
+    );
+}
+
+#[test]
+fn ctxt_reuses_prefixes() {
+    let z = Type::new_generic("Z");
+    let x = Type::new_struct(
+        "X",
+        [Field::new("y", z.clone()), Field::new("w", shared_ref(&z))],
+    );
+    let s = Type::new_struct("S", [Field::new("x", x)]);
+    let mut ctxt = PlaceTyCtxt::new(Map::from([("p".to_string(), shared_ref(&s))]));
+    let mut a = ctxt.parse("p.x.y").unwrap();
+    assert!(ctxt.compute(&mut a).unwrap() == z);
+    assert_eq!(a.to_string(), "(*p).x.y");
+
+    let hits = ctxt.cache_hits();
+    let mut b = ctxt.parse("*p.x.w").unwrap();
+    assert!(ctxt.compute(&mut b).unwrap() == z);
+    assert_eq!(b.to_string(), "*(*p).x.w");
+    assert!(ctxt.cache_hits() > hits);
+
+    let hits = ctxt.cache_hits();
+    let p = ctxt.local("p").unwrap();
+    let mut c = place_expr!(p.x.y);
+    assert!(ctxt.compute(&mut c).unwrap() == z);
+    assert_eq!(c.to_string(), "(*p).x.y");
+    assert_eq!(ctxt.cache_hits(), hits + 1);
+
+    let mut d = ctxt.parse("p.v").unwrap();
+    assert!(ctxt.compute(&mut d).is_err());
+}
+
+#[test]
+fn ctxt_with_registry() {
+    let z = Type::new_generic("Z");
+    let s = Type::new_struct("CtxtS", [Field::new("z", z.clone())]);
+    let mut registry = WrapperRegistry::new();
+    registry.register("Guard", WrapperBehavior::Lifting, |inner| {
+        Type::wrapper("Guard", inner)
+    });
+    registry.register("Box", WrapperBehavior::Lifting, |inner| {
+        Type::wrapper("Box", inner)
+    });
+    let types = Map::from([
+        ("p".to_string(), Type::wrapper("Guard", &s)),
+        ("q".to_string(), Type::wrapper("Box", &s)),
+    ]);
+    let mut ctxt = PlaceTyCtxt::with_registry(types.clone(), registry);
+    let mut e = ctxt.parse("@%Guard (*p).z").unwrap();
+    assert!(ctxt.compute(&mut e).unwrap() == Type::wrapper("Guard", &z));
+    let mut e = ctxt.parse("q.z").unwrap();
+    assert!(ctxt.compute(&mut e).unwrap() == Type::wrapper("Box", &z));
+
+    // The standard registry dereferences `Box` instead.
+    let mut ctxt = PlaceTyCtxt::new(types);
+    let mut e = ctxt.parse("q.z").unwrap();
+    assert!(matches!(
+        ctxt.compute(&mut e),
+        Err(PlaceTyError::WrapperBehaviorMismatch { .. })
+    ));
+}
+
+#[test]
+fn prefix_after_desugaring() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", z.clone())]);
+    let x = Type::new_struct("X", [Field::new("y", y), Field::new("w", z.clone())]);
+    let s = Type::new_struct("S", [Field::new("x", x)]);
+    let p = Local::new(shared_ref(&s), "p");
+    assert!(place_expr!((*p).x).is_prefix_of(&place_expr!((*p).x.y)));
+    assert!(place_expr!(p.x).is_prefix_of(&place_expr!((*p).x.y)));
+    assert!(place_expr!((*p).x).is_prefix_of(&place_expr!(p.x.y.z)));
+    assert!(place_expr!(p.x).is_prefix_of(&place_expr!((*p).x)));
+    assert!(place_expr!(p).is_prefix_of(&place_expr!(p.x)));
+    assert!(!place_expr!(p.x.y).is_prefix_of(&place_expr!(p.x)));
+    assert!(!place_expr!(p.x.y).is_prefix_of(&place_expr!(p.x.w)));
+
+    let q = Local::new(shared_ref(&maybe_uninit(&s)), "q");
+    assert!(place_expr!(q.x).is_prefix_of(&place_expr!(q.x.y)));
+    assert!(!place_expr!(q.x).is_prefix_of(&place_expr!(p.x.y)));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn type_interner() {
+    let interner = TypeInterner::new();
+    let t = Type::new_generic("T");
+    let a = interner.intern(maybe_uninit(&t));
+    let b = interner.intern(Type::wrapper("MaybeUninit", &Type::new_generic("T")));
+    assert_eq!(a, b);
+    assert_ne!(a, interner.intern(t.clone()));
+    assert_eq!(interner.len(), 2);
+
+    let deep = interner.intern(shared_ref(&shared_ref(&shared_ref(&t))));
+    let p = Local::new(deep.clone(), "p");
+    let mut e = place_expr!(***p);
+    check(&mut e, "***p", "T");
+    assert_eq!(interner.intern(p.ty()), deep);
+}
+
+#[test]
+fn desugar_report() {
+    let field = Type::new_generic("Field");
+    let struct_field = Field::new("field", field.clone());
+    let struct_ = Type::new_struct("Struct", vec![struct_field.clone()]);
+    let ty = shared_ref(&shared_ref(&shared_ref(&maybe_uninit(&struct_))));
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    let report = e.desugar_report().unwrap();
+    assert_eq!(e.to_string(), "@%MaybeUninit (****p).field");
+    assert_eq!(
+        report,
+        DesugarReport {
+            auto_deref_count: 4,
+            lifted_wrappers: vec!["MaybeUninit".to_string()],
+            explicit_projections: 1,
+            requires_unsafe: false,
+            fields: vec![struct_field],
+        }
+    );
+    assert_eq!(e.ty().unwrap().to_string(), "MaybeUninit<Field>");
+
+    let report = e.desugar_report().unwrap();
+    assert_eq!(report.auto_deref_count, 0);
+    assert_eq!(report.explicit_projections, 6);
+}
+
+#[test]
+fn index_by_local() {
+    let u8 = Type::new_generic("u8");
+    let usize = Type::new_generic("usize");
+    let p = Local::new(shared_ref(&slice(&u8)), "p");
+    let q = Local::new(usize, "q");
+    let mut e = place_expr!(p[q]);
+    check(&mut e, "(*p)[q]", "u8");
+    assert!(e.context().contains(&Context::Local(q.clone())));
+
+    let arr = Local::new(Type::new_array(u8.clone(), 4), "arr");
+    let mut e = place_expr!(arr[q]);
+    check(&mut e, "arr[q]", "u8");
+
+    let r = Local::new(u8, "r");
+    let mut e = place_expr!(p[r]);
+    check_err(
+        &mut e,
+        "the type of `r: u8` cannot be used as an index, it should be `usize`",
+    );
+}
+
+#[test]
+fn recursion_limit() {
+    let field = Type::new_generic("Field");
+    let mut ty = Type::new_struct("Struct", vec![Field::new("field", field)]);
+    for _ in 0..4 {
+        ty = Type::new_ref(ty);
+    }
+    let p = Local::new(ty, "p");
+    let mut e = place_expr!(p.field);
+    assert_eq!(
+        e.compute_ty_with_recursion_limit(5).unwrap().to_string(),
+        "Field"
+    );
+
+    let mut e = place_expr!(p.field);
+    let err = e.compute_ty_with_recursion_limit(4).unwrap_err();
+    let PlaceTyError::RecursionLimit { limit, trace, .. } = &err else {
+        panic!("expected a recursion limit error, got {err}");
+    };
+    assert_eq!(*limit, 4);
+    let trace = trace.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    assert_eq!(trace, ["p", "*p", "**p", "***p", "****p", "(****p).field"]);
+    assert_eq!(
+        err.to_string(),
+        "the type of `p: &&&&Struct` is projected too often in `(****p).field`, which exceeds \
+         the recursion limit of 4"
+    );
+}
+
+#[test]
+fn parse_place() {
+    let t = Type::new_generic("T");
+    let usize = Type::new_generic("usize");
+    let inner = Type::new_struct("Inner", vec![Field::new("g", t.clone())]);
+    let outer = Type::new_struct(
+        "Outer",
+        vec![
+            Field::new("f", maybe_uninit(&inner)),
+            Field::new("a", Type::new_array(Type::new_ref(inner.clone()), 2)),
+            Field::new("o", Type::new_ref(option(&t))),
+            Field::new(
+                "t",
+                Type::new_tuple([t.clone(), Type::new_tuple([t.clone()])]),
+            ),
+        ],
+    );
+    let p = Local::new(shared_ref(&outer), "p");
+    let q = Local::new(usize.clone(), "q");
+    let locals = Map::from([("p".to_string(), p.ty()), ("q".to_string(), usize.clone())]);
+    let parse = |src: &str| {
+        let mut place = PlaceExpr::parse(src, &locals).unwrap();
+        assert_eq!(place.to_string(), src);
+        place.compute_ty().unwrap()
+    };
+
+    let cases = [
+        ("@%MaybeUninit (*(*p).f).g", place_expr!(p.f.g)),
+        ("(*(*p).a[1]).g", place_expr!(p.a[1].g)),
+        ("(*p).a[q]", place_expr!(p.a[q])),
+        ("(*p).a[..1]", place_expr!(p.a[..1])),
+        ("((*(*p).o) as Some).0", place_expr!(((*p.o) as Some).0)),
+        ("(*p).t.1.0", place_expr!(p.t.1.0)),
+    ];
+    for (src, mut expected) in cases {
+        let ty = expected.compute_ty().unwrap();
+        assert_eq!(expected.to_string(), src);
+        assert!(parse(src) == ty, "`{src}` has a different type");
+    }
+
+    assert_eq!(
+        PlaceExpr::parse("(*r).f", &locals).unwrap_err(),
+        ParseError::UnknownLocal {
+            pos: 2,
+            name: "r".to_string()
+        }
+    );
+    assert_eq!(
+        PlaceExpr::parse("(*p).f)", &locals)
+            .unwrap_err()
+            .to_string(),
+        "expected the end at offset 6, found `)`"
+    );
+    assert_eq!(
+        PlaceExpr::parse("p.", &locals).unwrap_err().to_string(),
+        "expected a field at offset 2, found the end"
+    );
+}
+
+#[test]
+fn multi_arg_wrapper() {
+    let u8 = Type::new_generic("u8");
+    let e = Type::new_generic("E");
+    let k = Type::new_generic("K");
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+
+    let res = Type::wrapper_with_args("Result", &[maybe_uninit(&u8), e.clone()], 0);
+    assert_eq!(res.to_string(), "Result<MaybeUninit<u8>, E>");
+    assert!(res == Type::wrapper_with_args("Result", &[maybe_uninit(&u8), e.clone()], 0));
+    assert!(res != Type::wrapper_with_args("Result", &[maybe_uninit(&u8), u8.clone()], 0));
+    assert!(res != maybe_uninit(&maybe_uninit(&u8)));
+
+    let p = Local::new(
+        Type::wrapper_with_args("Result", &[struct_.clone(), e.clone()], 0),
+        "p",
+    );
+    let mut place = place_expr!(p.field);
+    check(&mut place, "@%Result (*p).field", "Result<Field, E>");
+
+    let map = Type::wrapper_with_args("HashMap", &[k.clone(), struct_.clone()], 1);
+    let p = Local::new(map, "p");
+    let mut place = place_expr!(p.field);
+    check(&mut place, "@%HashMap (*p).field", "HashMap<K, Field>");
+
+    let generic = Type::wrapper_with_args("Result", &[Type::new_generic("T"), e.clone()], 0);
+    let ty = generic.subst(&[("T", u8.clone()), ("E", k.clone())]);
+    assert!(ty == Type::wrapper_with_args("Result", &[u8, k], 0));
+}
+
+#[test]
+fn field_on_non_struct() {
+    let t = Type::new_generic("T");
+    let p = Local::new(t.clone(), "p");
+    let mut e = place_expr!(p.field);
+    check_err(
+        &mut e,
+        "the type of `p: T` is not a struct, so it has no field `field`",
+    );
+
+    let p = Local::new(Type::new_ref(Type::new_ref(t.clone())), "p");
+    let mut e = place_expr!(p.field);
+    let err = e.compute_ty().unwrap_err();
+    match &err {
+        PlaceTyError::FieldOnNonStruct { place, ty, field } => {
+            assert_eq!(place.to_string(), "**p");
+            assert!(*ty == t);
+            assert_eq!(field, "field");
+        }
+        err => panic!("expected `FieldOnNonStruct`, got {err:?}"),
+    }
+    let mut ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    ctx.sort();
+    assert_eq!(
+        ctx,
+        [
+            "deref of `&&T` (shared)",
+            "deref of `&T` (shared)",
+            "p: &&T"
+        ]
+    );
+
+    let p = Local::new(shared_ref(&slice(&t)), "p");
+    let mut e = place_expr!(p.len);
+    check_err(
+        &mut e,
+        "the type of `*p: [T]` is not a struct, so it has no field `len`",
+    );
+}
+
+#[test]
+fn shadowed_field() {
+    let a = Type::new_generic("A");
+    let b = Type::new_generic("B");
+    let inner = Type::new_struct("Inner", vec![Field::new("f", b.clone())]);
+    let smart = Type::builder()
+        .target(shared_ref(&inner))
+        .field("f", a.clone())
+        .display("Smart")
+        .build()
+        .unwrap();
+    let p = Local::new(smart, "p");
+    let mut e = place_expr!(p.f);
+    check(&mut e, "p.f", "A");
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"warning: `Smart.f` shadows `Inner.f: B`".to_string()));
+
+    let mut e = place_expr!((**p).f);
+    check(&mut e, "(**p).f", "B");
+    assert!(
+        !e.context()
+            .iter()
+            .any(|c| matches!(c, Context::ShadowedField(..)))
+    );
+}
+
+#[test]
+fn rc_arc_deref() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    for name in ["Rc", "Arc"] {
+        let p = Local::new(Type::transparent_wrapper(name, &struct_), "p");
+        let mut e = place_expr!(p.field);
+        check(&mut e, "(*p).field", "Field");
+        let ctx = e.context();
+        let derefs = ctx
+            .iter()
+            .filter_map(|c| match c {
+                Context::Deref(ty) => Some(ty),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(derefs.len(), 1);
+        assert_eq!(derefs[0].wrapper_name(), Some(name));
+        let ctx = ctx.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert!(ctx.contains(&format!("deref of `{name}<Struct>` (via `{name}`)")));
+    }
+
+    let p = Local::new(shared_ref(&Type::transparent_wrapper("Rc", &struct_)), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(**p).field", "Field");
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"deref of `&Rc<Struct>`".to_string()));
+    assert!(ctx.contains(&"deref of `Rc<Struct>` (via `Rc`)".to_string()));
+}
+
+#[test]
+fn max_auto_deref() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let p = Local::new(shared_ref(&shared_ref(&shared_ref(&struct_))), "p");
+    let options = |max_auto_deref| ComputeOptions {
+        max_auto_deref,
+        ..ComputeOptions::default()
+    };
+
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty_with_options(options(None)).unwrap();
+    assert_eq!(ty.to_string(), "Field");
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty_with_options(options(Some(3))).unwrap();
+    assert_eq!(ty.to_string(), "Field");
+
+    let mut e = place_expr!(p.field);
+    let err = e.compute_ty_with_options(options(Some(2))).unwrap_err();
+    assert!(matches!(
+        err,
+        PlaceTyError::AutoDerefLimit {
+            attempted: 3,
+            limit: 2,
+            ..
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "the type of `**p: &Struct` would need implicit dereference number 3, which exceeds the \
+         limit of 2"
+    );
+}
+
+#[test]
+fn mutable_place() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let mut_ref = Type::new_mut_ref(struct_.clone());
+    let cases = [
+        (Local::new(struct_.clone(), "p"), false),
+        (Local::new_mut(struct_.clone(), "p"), true),
+        (Local::new_mut(mut_ref.clone(), "p"), true),
+        (Local::new_mut(boxed(&mut_ref), "p"), true),
+        (Local::new_mut(Type::new_ref(struct_.clone()), "p"), false),
+        (
+            Local::new_mut(Type::new_mut_ref(Type::new_ref(struct_)), "p"),
+            false,
+        ),
+    ];
+    for (p, mutable) in cases {
+        let mut e = place_expr!(p.field);
+        assert_eq!(e.is_mutable_place(), Some(p.is_mut()));
+        e.compute_ty().unwrap();
+        assert_eq!(e.is_mutable_place(), Some(mutable), "{e}: {}", p.ty());
+    }
+}
+
+#[test]
+fn union_field() {
+    let a = Type::new_generic("A");
+    let b = Type::new_generic("B");
+    let union_ = Type::new_union("U", [Field::new("a", a), Field::new("b", maybe_uninit(&b))]);
+    let p = Local::new(shared_ref(&union_), "p");
+
+    let mut e = place_expr!(p.a);
+    check(&mut e, "(*p).a", "A");
+    assert!(
+        e.context()
+            .iter()
+            .any(|c| matches!(c, Context::UnionField(ty, _) if *ty == union_))
+    );
+    let mut e = place_expr!(p.a);
+    assert!(e.desugar_report().unwrap().requires_unsafe);
+
+    let mut e = place_expr!(p.b);
+    check(&mut e, "(*p).b", "MaybeUninit<B>");
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"U.b: MaybeUninit<B> (union field, requires `unsafe`)".to_string()));
+
+    let struct_ = Type::new_struct("S", [Field::new("u", maybe_uninit(&union_))]);
+    let p = Local::new(shared_ref(&struct_), "p");
+    let mut e = place_expr!(p.u.b);
+    check(
+        &mut e,
+        "@%MaybeUninit (*(*p).u).b",
+        "MaybeUninit<MaybeUninit<B>>",
+    );
+    let mut e = place_expr!(p.u);
+    assert!(!e.desugar_report().unwrap().requires_unsafe);
+}
+
+#[test]
+fn safety_reasons() {
+    let t = Type::new_generic("T");
+    let cell = Type::new_cell("UnsafeCell", "value", t.clone());
+    let union_ = Type::new_union("U", [Field::new("f", t.clone()), Field::new("g", cell)]);
+    let s = Type::new_struct("S", [Field::new("u", union_)]);
+    let p = Local::new(Type::new_raw_ptr(s.clone(), false), "p");
+    let mut e = place_expr!((*p).u.f);
+    check(&mut e, "(*p).u.f", "T");
+    assert_eq!(
+        e.safety(),
+        Safety::Unsafe(vec![
+            UnsafeReason::RawPtrDeref(*place_expr!(*p)),
+            UnsafeReason::UnionField(*place_expr!((*p).u.f)),
+        ])
+    );
+
+    let q = Local::new(shared_ref(&s), "q");
+    let mut e = place_expr!(q.u);
+    check(&mut e, "(*q).u", "U");
+    assert_eq!(e.safety(), Safety::Safe);
+    let mut e = place_expr!(q.u.g.value);
+    check(&mut e, "(*q).u.g.value", "T");
+    let Safety::Unsafe(reasons) = e.safety() else {
+        panic!("`{e}` should be unsafe");
+    };
+    assert!(matches!(
+        &reasons[..],
+        [
+            UnsafeReason::UnionField(_),
+            UnsafeReason::UnsafeCellProjection(_)
+        ]
+    ));
+}
+
+#[test]
+fn structurally_eq() {
+    let t = Type::new_generic("T");
+    let new_ref = |display: &str| {
+        Type::new(
+            Some(t.clone()),
+            None,
+            None,
+            None,
+            Map::new(),
+            display.to_string(),
+        )
+    };
+    let (a, b) = (new_ref("&T"), new_ref("& T"));
+    assert!(a.structurally_eq(&b));
+    assert!(!a.display_eq(&b));
+    assert!(a.display_eq(&new_ref("&T")));
+
+    let u = Type::new_generic("U");
+    assert!(!t.structurally_eq(&u));
+    assert!(!Type::new_ref(t.clone()).structurally_eq(&Type::new_mut_ref(t)));
+}
+
+#[test]
+fn empty_struct() {
+    let empty = Type::new_struct("Empty", []);
+    let p = Local::new(shared_ref(&empty), "p");
+    let mut e = place_expr!(p.field);
+    check_err(&mut e, "the type of `*p: Empty` has no field `field`");
+    assert!(matches!(
+        e.compute_ty(),
+        Err(PlaceTyError::UnknownField { .. })
+    ));
+}
+
+#[test]
+fn field_named_like_wrapper() {
+    let t = Type::new_generic("T");
+    let s = Type::new_struct("S", [Field::new("S", t.clone())]);
+    let p = Local::new(shared_ref(&s), "p");
+    let mut e = place_expr!(p.S);
+    check(&mut e, "(*p).S", "T");
+
+    let s = Type::new_struct("S", [Field::new("MaybeUninit", t.clone())]);
+    let p = Local::new(maybe_uninit(&s), "p");
+    let mut e = place_expr!(p.MaybeUninit);
+    check(&mut e, "@%MaybeUninit (*p).MaybeUninit", "MaybeUninit<T>");
+
+    let p = Local::new(s, "p");
+    let mut e = place_expr!(p.MaybeUninit);
+    check(&mut e, "p.MaybeUninit", "T");
+}
+
+#[test]
+fn deref_target() {
+    let t = Type::new_generic("T");
+    for ty in [
+        shared_ref(&t),
+        Type::new_mut_ref(t.clone()),
+        Type::new_raw_ptr(t.clone(), false),
+        boxed(&t),
+        Type::new_pin(boxed(&t)),
+    ] {
+        assert_eq!(ty.deref_target(), Some(&t), "{ty}");
+        assert!(ty.is_dereferenceable());
+        assert!(!ty.is_lifting_wrapper());
+    }
+    let ty = maybe_uninit(&t);
+    assert_eq!(ty.deref_target(), None);
+    assert!(ty.is_lifting_wrapper());
+    for ty in [t.clone(), Type::new_pin(t.clone()), Type::new_slice(t)] {
+        assert!(!ty.is_dereferenceable(), "{ty}");
+        assert!(!ty.is_lifting_wrapper());
+    }
+}
+
+#[test]
+fn wrapper_annotation_mismatch() {
+    let z = Type::new_generic("Z");
+    let mbz = maybe_uninit(&z);
+    let mb2z = maybe_uninit(&mbz);
+    let mb3z = maybe_uninit(&mb2z);
+    let y = Type::new_struct("Y", [Field::new("z", mbz.clone())]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    let p = Local::new(shared_ref(&e), "p");
+
+    let mut e = place_expr!(@%mb3z @%mb2z *(*(*(*p).x).y).z);
+    check_err(
+        &mut e,
+        "the type of `*(*(*(*p).x).y).z: Z` is wrapped in `@%MaybeUninit<MaybeUninit<Z>>`, but \
+         wrapping it results in `MaybeUninit<Z>`",
+    );
+    match e.compute_ty() {
+        Err(PlaceTyError::WrapperAnnotationMismatch {
+            expected, found, ..
+        }) => {
+            assert!(expected == mbz);
+            assert!(found == mb2z);
+        }
+        res => panic!("expected `WrapperAnnotationMismatch`, got {res:?}"),
+    }
+
+    let mut e = place_expr!(@%mb3z @%mb3z @%mb2z @%mbz *(*(*(*p).x).y).z);
+    check_err(
+        &mut e,
+        "the type of `@%MaybeUninit @%MaybeUninit @%MaybeUninit *(*(*(*p).x).y).z: \
+         MaybeUninit<MaybeUninit<MaybeUninit<Z>>>` is wrapped in \
+         `@%MaybeUninit<MaybeUninit<MaybeUninit<Z>>>`, but wrapping it results in \
+         `MaybeUninit<MaybeUninit<MaybeUninit<MaybeUninit<Z>>>>`",
+    );
+
+    let manually_drop = Type::wrapper("ManuallyDrop", &Type::new_generic("T"));
+    let mut e = place_expr!(@%manually_drop *(*(*(*p).x).y).z);
+    check_err(
+        &mut e,
+        "the type of `*(*(*(*p).x).y).z: Z` is wrapped in `@%ManuallyDrop<T>`, but wrapping it \
+         results in `MaybeUninit<Z>`",
+    );
+}
+
+#[test]
+fn lift_over_several_projections() {
+    let u8 = Type::new_generic("u8");
+    let b = Type::new_struct("B", [Field::new("c", Type::new_array(u8.clone(), 4))]);
+    let a = Type::new_struct("A", [Field::new("b", b)]);
+    let s = Type::new_struct("S", [Field::new("a", a)]);
+    let p = Local::new(Type::new_mut_ref(maybe_uninit(&s)), "p");
+    let mut e = place_expr!(p.a.b.c);
+    check(&mut e, "@%MaybeUninit (**p).a.b.c", "MaybeUninit<[u8; 4]>");
+    let mut e = place_expr!(p.a.b.c[1..][1]);
+    check(
+        &mut e,
+        "@%MaybeUninit (**p).a.b.c[1..][1]",
+        "MaybeUninit<u8>",
+    );
+}
+
+#[test]
+fn field_declaration_order() {
+    let names = ["zeta", "alpha", "mid", "beta"];
+    let fields = names.map(|name| Field::new(name, Type::new_generic("T")));
+    let s = Type::new_struct("S", fields);
+    let tree = s.debug_tree();
+    let listed = tree
+        .lines()
+        .skip(1)
+        .map(|line| line.trim().split(':').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(listed, [".zeta", ".alpha", ".mid", ".beta"]);
+
+    let elems = (0..12).map(|i| Type::new_generic(&format!("T{i}")));
+    let tuple = Type::new_tuple(elems);
+    let listed = tuple
+        .debug_tree()
+        .lines()
+        .skip(1)
+        .map(|line| line.trim().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(listed[2], ".2: T2");
+    assert_eq!(listed[10], ".10: T10");
+}
+
+#[test]
+fn slice_behind_ref_or_raw_ptr() {
+    let t = Type::new_generic("T");
+    let p = Local::new(Type::new_ref(slice(&t)), "p");
+    let mut e = place_expr!(p[0]);
+    check(&mut e, "(*p)[0]", "T");
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"deref of `&[T]` (shared)".to_string()));
+
+    let p = Local::new(Type::new_raw_ptr(slice(&t), false), "p");
+    let mut e = place_expr!(p[0]);
+    check_err(
+        &mut e,
+        "the type of `p: *const [T]` is a raw pointer, which must be dereferenced explicitly",
+    );
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"explicit deref of raw pointer `*const [T]` required".to_string()));
+
+    let mut e = place_expr!((*p)[0]);
+    check(&mut e, "(*p)[0]", "T");
+    let ctx = e
+        .context()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert!(ctx.contains(&"deref of `*const [T]` (raw)".to_string()));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn compute_ty_traced() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", maybe_uninit(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let p = Local::new(shared_ref(&x), "p");
+    let mut e = place_expr!(p.y.z);
+    let mut out = vec![];
+    let ty = e.compute_ty_traced(&mut out).unwrap().unwrap();
+    assert_eq!(ty.to_string(), "MaybeUninit<MaybeUninit<Z>>");
+    let steps = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let projections = steps
+        .iter()
+        .map(|s| s["projection"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(projections, ["local", "deref", "field", "deref", "field"]);
+    assert_eq!(steps[0]["input"], serde_json::Value::Null);
+    assert_eq!(steps[2]["input"], "&X");
+    assert_eq!(steps[2]["inserted_derefs"], 1);
+    assert_eq!(steps[4]["place"], "@%MaybeUninit (*(*p).y).z");
+    assert_eq!(steps[4]["input"], "MaybeUninit<Y>");
+    assert_eq!(steps[4]["output"], "MaybeUninit<MaybeUninit<Z>>");
+    assert_eq!(
+        steps[4]["lifted_wrappers"],
+        serde_json::json!(["MaybeUninit"])
+    );
+
+    let mut e = place_expr!(p.w);
+    let mut out = vec![];
+    assert!(e.compute_ty_traced(&mut out).unwrap().is_err());
+    let out = String::from_utf8(out).unwrap();
+    let last = serde_json::from_str::<serde_json::Value>(out.lines().last().unwrap()).unwrap();
+    assert_eq!(last["error"], "the type of `*p: X` has no field `w`");
+}
+
+#[test]
+fn struct_from_pairs() {
+    let z = Type::new_generic("Z");
+    let y = Type::struct_from("Y", [("z", maybe_uninit(&z)), ("w", z.clone())]);
+    let x = Type::struct_from("X", [("y".to_string(), maybe_uninit(&y))]);
+    let p = Local::new(shared_ref(&x), "p");
+    let mut e = place_expr!(p.y.z);
+    check(
+        &mut e,
+        "@%MaybeUninit (*(*p).y).z",
+        "MaybeUninit<MaybeUninit<Z>>",
+    );
+
+    let fields = [("b", z.clone()), ("a", z.clone())]
+        .into_iter()
+        .collect::<Fields>();
+    let s = Type::new_struct("S", fields);
+    assert!(s == Type::new_struct("S", [Field::new("a", z.clone()), Field::new("b", z)]));
+    assert_eq!(s.debug_tree(), "S\n  .b: Z\n  .a: Z\n");
+}
+
+#[test]
+fn projection_behavior() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let lift = |derefs, lifted: &[&str]| ProjectionBehavior::Lift {
+        derefs,
+        lifted: lifted.iter().map(|name| name.to_string()).collect(),
+    };
+    let cases = [
+        (struct_.clone(), ProjectionBehavior::Project),
+        (
+            shared_ref(&struct_),
+            ProjectionBehavior::DerefThenProject { derefs: 1 },
+        ),
+        (maybe_uninit(&struct_), lift(1, &["MaybeUninit"])),
+        (
+            shared_ref(&shared_ref(&struct_)),
+            ProjectionBehavior::DerefThenProject { derefs: 2 },
+        ),
+        (
+            maybe_uninit(&maybe_uninit(&struct_)),
+            lift(2, &["MaybeUninit", "MaybeUninit"]),
+        ),
+        (
+            shared_ref(&maybe_uninit(&struct_)),
+            lift(2, &["MaybeUninit"]),
+        ),
+        (
+            maybe_uninit(&shared_ref(&struct_)),
+            ProjectionBehavior::DerefThenProject { derefs: 2 },
+        ),
+        (
+            maybe_uninit(&shared_ref(&maybe_uninit(&struct_))),
+            lift(3, &["MaybeUninit"]),
+        ),
+        (field, ProjectionBehavior::Error),
+        (Type::new_raw_ptr(struct_, false), ProjectionBehavior::Error),
+    ];
+    for (ty, behavior) in cases {
+        assert_eq!(ty.projection_behavior("field"), behavior, "{ty}");
+        let p = Local::new(ty.clone(), "p");
+        let mut e = place_expr!(p.field);
+        let report = e.desugar_report();
+        match behavior {
+            ProjectionBehavior::Project => assert_eq!(report.unwrap().auto_deref_count, 0),
+            ProjectionBehavior::DerefThenProject { derefs } => {
+                let report = report.unwrap();
+                assert_eq!(report.auto_deref_count, derefs);
+                assert!(report.lifted_wrappers.is_empty());
+            }
+            ProjectionBehavior::Lift { derefs, lifted } => {
+                let report = report.unwrap();
+                assert_eq!(report.auto_deref_count, derefs);
+                assert_eq!(report.lifted_wrappers, lifted);
+            }
+            ProjectionBehavior::Error => assert!(report.is_err()),
+        }
+    }
+}
+
+#[test]
+fn conditional_place_deref() {
+    let struct_ = Type::builder()
+        .field("field", Type::new_generic("Field"))
+        .display("Struct")
+        .build()
+        .unwrap();
+    let pin = |ptr: Type| {
+        Type::builder()
+            .target(ptr.clone())
+            .deref_when(|ptr| ptr.is_dereferenceable())
+            .display(format!("Pin<{ptr}>"))
+            .build()
+            .unwrap()
+    };
+    let p = Local::new(pin(Type::new_mut_ref(struct_)), "p");
+    let mut e = place_expr!(p.field);
+    assert_eq!(e.compute_ty_checked().unwrap().to_string(), "Field");
+    assert_eq!(e.to_string(), "(**p).field");
+
+    let p = Local::new(pin(Type::new_generic("T")), "p");
+    let mut e = place_expr!(*p);
+    match e.compute_ty_checked() {
+        Err(err @ PlaceTyError::DerefConditionFailed { .. }) => {
+            assert_eq!(err.ty().to_string(), "Pin<T>");
+            assert!(err.to_string().contains("cannot be dereferenced in `*p`"));
+        }
+        res => panic!("expected `DerefConditionFailed`, got {res:?}"),
+    }
+    assert_eq!(e.compute_ty().unwrap().to_string(), "T");
+
+    let err = Type::builder()
+        .deref_when(|_| true)
+        .display("S")
+        .build()
+        .unwrap_err();
+    assert_eq!(err, TypeBuildError::DerefConditionWithoutTarget);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn testing_check() {
+    use place_ty_compute::testing;
+
+    let x = Type::new_struct("X", [Field::new("field", Type::new_generic("Field"))]);
+    let p = Local::new(shared_ref(&x), "p");
+    let mut e = place_expr!(p.field);
+    let ty = testing::check(&mut e, "(*p).field", "Field").unwrap();
+    assert_eq!(ty.to_string(), "Field");
+
+    let mut e = place_expr!(p.field);
+    let err = testing::check(&mut e, "(*p).field", "X").unwrap_err();
+    assert!(err.place_ty_error().is_none());
+    let msg = err.to_string();
+    assert!(msg.starts_with("analyzed the place expression `p.field` with:\n"));
+    assert!(msg.ends_with(
+        "\ncomputed type does not match the expected type:\nexpected: X\ncomputed: Field\n"
+    ));
+    assert!(!msg.contains("desugaring does not match"));
+
+    let mut e = place_expr!(p.field);
+    let err = testing::check(&mut e, "(**p).field", "Field").unwrap_err();
+    assert!(err.to_string().ends_with(
+        "\ncomputed desugaring does not match the expected desugaring:\n\
+         expected: (**p).field\ncomputed: (*p).field\n\
+         projections from the local outwards:\n  *\n- *\n  .field\n"
+    ));
+
+    let mut e = place_expr!(p.missing);
+    let err = testing::check(&mut e, "(*p).missing", "Field").unwrap_err();
+    assert!(matches!(
+        err.place_ty_error(),
+        Some(PlaceTyError::UnknownField { .. })
+    ));
+}
+
+#[test]
+fn user_smart_pointer() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", [Field::new("field", field.clone())]);
+    let ptr = Type::new_smart_pointer(
+        "MySmartPtr<Struct>",
+        [Field::new("count", Type::new_generic("usize"))],
+        struct_.clone(),
+    );
+    assert!(ptr.deref_target() == Some(&struct_));
+    let p = Local::new(ptr.clone(), "p");
+
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "Field");
+    assert_eq!(
+        e.context()[1].to_string(),
+        "deref of `MySmartPtr<Struct>` (via user `Deref` impl)"
+    );
+    let mut e = place_expr!(*p);
+    check(&mut e, "*p", "Struct");
+    let mut e = place_expr!(p.count);
+    check(&mut e, "p.count", "usize");
+
+    let r = Local::new(shared_ref(&ptr), "r");
+    let mut e = place_expr!(r.field);
+    check(&mut e, "(**r).field", "Field");
+}
+
+#[test]
+fn subst_smart_pointer() {
+    let t = Type::new_generic("T");
+    let struct_ = Type::new_struct("Struct<T>", [Field::new("field", t.clone())]);
+    let ptr = Type::new_smart_pointer(
+        "MySmartPtr<Struct<T>>",
+        [Field::new("count", Type::new_generic("usize"))],
+        struct_.clone(),
+    );
+    let u8 = Type::new_generic("u8");
+    let concrete = ptr.subst(&[("T", u8.clone())]);
+    assert_eq!(concrete.to_string(), "MySmartPtr<Struct<u8>>");
+    assert!(concrete.deref_target() == Some(&struct_.subst(&[("T", u8)])));
+    let p = Local::new(concrete, "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "u8");
+
+    let newtype = Type::new_deref_newtype("Wrapper<T>", struct_);
+    let p = Local::new(newtype.subst(&[("T", Type::new_generic("i32"))]), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "i32");
+}
+
+#[test]
+fn symbolic_array_len() {
+    let u8 = Type::new_generic("u8");
+    let arr = Type::new_array_generic(u8.clone(), ArrayLen::Generic("N".into()));
+    assert_eq!(arr.to_string(), "[u8; N]");
+    assert!(Type::new_array_generic(u8.clone(), ArrayLen::Const(4)) == Type::new_array(u8, 4));
+    let p = Local::new(shared_ref(&arr), "p");
+
+    let mut e = place_expr!(p[100]);
+    check(&mut e, "(*p)[100]", "u8");
+    assert!(
+        e.context()
+            .iter()
+            .any(|ctx| matches!(ctx, Context::SymbolicArrayLen(_)))
+    );
+    assert_eq!(
+        e.context().last().unwrap().to_string(),
+        "`[u8; N]` has the symbolic length `N`, the index is not checked"
+    );
+    let mut e = place_expr!(p[1..]);
+    check(&mut e, "(*p)[1..]", "[u8]");
+}
+
+#[test]
+fn field_metadata() {
+    let u32 = Type::new_generic("u32");
+    let inner = Type::new_struct(
+        "Inner",
+        [Field::new_full(
+            "secret",
+            u32.clone(),
+            Visibility::Private,
+            Some(4),
+        )],
+    );
+    let outer = Type::new_struct(
+        "Outer",
+        [
+            Field::new_full("len", u32.clone(), Visibility::Public, Some(0)),
+            Field::new_full("inner", inner, Visibility::Public, Some(8)),
+        ],
+    );
+    let p = Local::new(shared_ref(&outer), "p");
+    let mut e = place_expr!(p.inner.secret);
+    let report = e.desugar_report().unwrap();
+    let fields = report
+        .fields
+        .iter()
+        .map(|f| (f.name(), f.visibility(), f.offset()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        [
+            ("inner", Visibility::Public, Some(8)),
+            ("secret", Visibility::Private, Some(4)),
+        ]
+    );
+    let warnings = e
+        .context()
+        .iter()
+        .filter(|ctx| matches!(ctx, Context::PrivateField(..)))
+        .map(|ctx| ctx.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(warnings, ["warning: `Inner.secret` is private"]);
+
+    let default = Field::new("len", u32);
+    assert_eq!(default.visibility(), Visibility::Public);
+    assert_eq!(default.offset(), None);
+}
+
+#[test]
+fn temporary_base() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", [Field::new("field", field)]);
+    let ref_struct = shared_ref(&struct_);
+    let mut e = place_expr!((_: ref_struct).field);
+    check(&mut e, "(*(_: &Struct)).field", "Field");
+    assert_eq!(e.place_base(), PlaceBase::Temp(ref_struct.clone()));
+    let mut e = place_expr!((_: struct_).field);
+    check(&mut e, "(_: Struct).field", "Field");
+    assert_eq!(e.is_mutable_place(), Some(true));
+
+    let p = Local::new(ref_struct.clone(), "p");
+    let e = place_expr!(p.field);
+    assert_eq!(e.place_base(), PlaceBase::Local(p));
+
+    let locals = Map::from([
+        ("Struct".to_string(), struct_),
+        ("p".to_string(), ref_struct),
+    ]);
+    for src in ["(_: Struct).field", "(*(_: &Struct)).field"] {
+        let mut parsed = PlaceExpr::parse(src, &locals).unwrap();
+        assert!(parsed.root_local().is_temp());
+        check(&mut parsed, src, "Field");
+    }
+    assert_eq!(
+        PlaceExpr::parse("(_: Other).field", &locals).unwrap_err(),
+        ParseError::UnknownType {
+            pos: 4,
+            name: "Other".to_string()
+        }
+    );
+}
+
+#[test]
+fn normalize_places() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", [Field::new("field", field.clone())]);
+    let p = Local::new(shared_ref(&struct_), "p");
+    let sugared = place_expr!(p.field).normalize().unwrap();
+    let explicit = place_expr!((*p).field).normalize().unwrap();
+    assert_eq!(sugared, explicit);
+    assert_eq!(format!("{sugared:#}"), "(*p).field");
+    assert!(place_expr!(p.missing).normalize().is_err());
+
+    let mb = Type::idempotent_wrapper("MaybeUninit", &field);
+    let mb2 = Type::idempotent_wrapper("MaybeUninit", &mb);
+    let q = Local::new(shared_ref(&struct_), "q");
+    let wrapped = place_expr!(@%mb2 @%mb (*q).field).normalize().unwrap();
+    assert_eq!(wrapped.to_string(), "@%MaybeUninit (*q).field");
+}
+
+#[test]
+fn index_from_end() {
+    let u8 = Type::new_generic("u8");
+    let p = Local::new(shared_ref(&Type::new_array(u8.clone(), 4)), "p");
+    let mut e = place_expr!(p[^1]);
+    assert_eq!(e.to_string(), "p[^1]");
+    check(&mut e, "(*p)[3]", "u8");
+    let mut e = place_expr!(p[^5]);
+    check_err(
+        &mut e,
+        "the type of `*p: [u8; 4]` has length 4, so the index `^5` is out of bounds",
+    );
+
+    let s = Local::new(shared_ref(&slice(&u8)), "s");
+    let mut e = place_expr!(s[^2]);
+    check(&mut e, "(*s)[^2]", "u8");
+    assert_eq!(
+        e.context().last().unwrap().to_string(),
+        "index `^2` counts from the end of `[u8]`"
+    );
+    let types = Map::from([("s".to_string(), shared_ref(&slice(&u8)))]);
+    let parsed = PlaceExpr::parse("(*s)[^2]", &types).unwrap();
+    assert_eq!(parsed.to_string(), "(*s)[^2]");
+}
+
+/// A xorshift random number generator, so that the randomized tests are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    /// Returns a random number below `n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+
+    /// Returns a random projection, which may or may not be valid for the place it is applied to.
+    fn projection(&mut self, fields: &[&str], index: &Local) -> PlaceProjection {
+        match self.below(7) {
+            0 => PlaceProjection::Deref,
+            1 | 2 => PlaceProjection::Field(self.pick(fields).to_string()),
+            3 => PlaceProjection::Index(Expr(self.pick(&["0", "3", "4", "^1", "^9"]).to_string())),
+            4 => PlaceProjection::LocalIndex(index.clone()),
+            5 => {
+                let start = Expr(self.pick(&["0", "3", "4", "^1", "^9"]).to_string());
+                PlaceProjection::Range(Some(start), None)
+            }
+            _ => PlaceProjection::Downcast(self.pick(&["A", "B", "C"]).to_string()),
+        }
+    }
+}
+
+/// Returns the struct `Outer` the randomized tests project from. Its fields `x` and `y` reach an
+/// array, a slice and an enum through a place wrapper and a reference, `extra` returns the other
+/// fields given the struct `Inner` behind `x`.
+fn random_outer(extra: impl FnOnce(&Type) -> Vec<Field>) -> Type {
+    let u8 = Type::new_generic("u8");
+    let inner = Type::new_struct(
+        "Inner",
+        [
+            Field::new("x", Type::new_array(u8.clone(), 4)),
+            Field::new("y", shared_ref(&slice(&u8))),
+        ],
+    );
+    let enum_ = Type::new_enum(
+        "E",
+        vec![
+            ("A".into(), vec![Field::new("x", inner.clone())]),
+            ("B".into(), vec![Field::new("y", u8.clone())]),
+        ],
+    );
+    let fields = [
+        Field::new("x", maybe_uninit(&inner)),
+        Field::new("y", shared_ref(&enum_)),
+    ];
+    Type::new_struct("Outer", fields.into_iter().chain(extra(&inner)))
+}
+
+/// Returns `e` projected by `proj`.
+fn apply(e: PlaceExpr, proj: PlaceProjection) -> PlaceExpr {
+    let e = Box::new(e);
+    match proj {
+        PlaceProjection::Deref => PlaceExpr::Deref(e),
+        PlaceProjection::Field(field) => PlaceExpr::FieldAccess(e, field),
+        PlaceProjection::Index(i) => PlaceExpr::Index(e, i),
+        PlaceProjection::LocalIndex(i) => PlaceExpr::LocalIndex(e, i),
+        PlaceProjection::Range(start, end) => PlaceExpr::Range(e, start, end),
+        PlaceProjection::Downcast(variant) => PlaceExpr::Downcast(e, variant),
+        PlaceProjection::Wrap(wrapper) => PlaceExpr::Wrap(e, wrapper),
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn try_compute_ty_never_panics() {
+    struct Broken;
+
+    impl WrapperKind for Broken {
+        fn project(&self, _: &PlaceProjection, _: &Type) -> ProjectionResult {
+            panic!("broken wrapper")
+        }
+    }
+
+    let u8 = Type::new_generic("u8");
+    let outer = random_outer(|inner| {
+        vec![
+            Field::new("r", Type::new_raw_ptr(inner.clone(), false)),
+            Field::new("t", Type::new_tuple([u8.clone(), inner.clone()])),
+            Field::new("w", Type::custom_wrapper("Broken", inner, Broken)),
+        ]
+    });
+    let p = Local::new(Type::new_mut_ref(outer), "p");
+    let i = Local::new(u8, "i");
+
+    let mut e = place_expr!(p.w.x);
+    match e.try_compute_ty() {
+        Err(err @ PlaceTyError::Internal { .. }) => {
+            assert_eq!(e.to_string(), "p.w.x");
+            assert!(
+                err.to_string()
+                    .ends_with("computing it panicked: broken wrapper")
+            );
+        }
+        res => panic!("expected `Internal`, got {res:?}"),
+    }
+
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..2000 {
+        let mut e = PlaceExpr::LocalVar(p.clone());
+        for _ in 0..rng.below(8) {
+            let proj = rng.projection(&["x", "y", "r", "t", "w", "0", "1"], &i);
+            e = apply(e, proj);
+        }
+        let written = e.to_string();
+        if let Err(PlaceTyError::Internal { message, .. }) = e.try_compute_ty() {
+            assert_eq!(message, "broken wrapper", "{written}");
+            assert_eq!(e.to_string(), written);
+        }
+    }
+
+    // Deep places are rejected before anything recurses through them, also with a bound that
+    // would let every dereference succeed.
+    let t = Type::new_generic("T");
+    let q = Local::new(t.clone(), "q");
+    let mut deep = PlaceExpr::LocalVar(q);
+    for _ in 0..10_000 {
+        deep = PlaceExpr::Deref(Box::new(deep));
+    }
+    let err = deep.try_compute_ty().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "the type of `q: T` is projected too often, which exceeds the recursion limit of 128"
+    );
+    let mut generics = GenericEnv::new();
+    generics.add_deref_bound("T", t);
+    let options = ComputeOptions {
+        generics,
+        ..ComputeOptions::default()
+    };
+    assert!(matches!(
+        deep.compute_ty_with_options(options),
+        Err(PlaceTyError::RecursionLimit { trace, .. }) if trace.is_empty()
+    ));
+}
+
+#[test]
+fn malformed_wrappers_are_errors() {
+    let x = Type::new_generic("X");
+    let other = Type::new_generic("Other");
+    let bad = Type::new(
+        Some(Type::new_generic("T")),
+        None,
+        Some(Box::new(move |_| Type::wrapper("Bad", &other))),
+        Some("Bad".to_string()),
+        Map::new(),
+        "Bad<T>".to_string(),
+    );
+    let q = Local::new(x.clone(), "q");
+    let mut e = place_expr!(*@%bad q);
+    match e.compute_ty() {
+        Err(err @ PlaceTyError::WrapperTargetMismatch { .. }) => assert_eq!(
+            err.to_string(),
+            "the type of `@%Bad q: Bad<Other>` dereferences to `Other`, but the place it wraps \
+             has the type `X`"
+        ),
+        res => panic!("expected `WrapperTargetMismatch`, got {res:?}"),
+    }
+
+    let s = Type::new_struct("S", []);
+    let mut e = place_expr!(@%s q);
+    assert_eq!(e.to_string(), "@%S q");
+    check_err(
+        &mut e,
+        "the type of `q: X` cannot be wrapped, since `S` should implement `PlaceWrapper`",
+    );
+}
+
+#[test]
+fn regenerate_display() {
+    let u8 = Type::new_generic("u8");
+    let x = Type::new_struct("X", [Field::new("field", u8.clone())]);
+    let stale = Type::new_ref(x.clone()).with_display("&Stale");
+    assert_eq!(stale.to_string(), "&Stale");
+    assert!(stale == Type::new_ref(x.clone()));
+    assert_eq!(stale.regenerate_display().to_string(), "&X");
+
+    let cases = [
+        (Type::new_mut_ref(x.clone()), "&mut X"),
+        (Type::new_raw_ptr(x.clone(), false), "*const X"),
+        (Type::new_array(u8.clone(), 4), "[u8; 4]"),
+        (slice(&u8), "[u8]"),
+        (Type::new_tuple([u8.clone()]), "(u8,)"),
+        (Type::new_tuple([u8.clone(), x.clone()]), "(u8, X)"),
+        (maybe_uninit(&x), "MaybeUninit<X>"),
+        (
+            Type::wrapper_with_args("Result", &[x.clone(), Type::new_generic("E")], 0),
+            "Result<X, E>",
+        ),
+    ];
+    for (ty, display) in cases {
+        assert_eq!(
+            ty.with_display("?").regenerate_display().to_string(),
+            display
+        );
+        assert_eq!(ty.regenerate_display().to_string(), display);
+    }
+    assert_eq!(x.regenerate_display().to_string(), "X");
+    assert_eq!(x.with_display("Y").regenerate_display().to_string(), "Y");
+    let p = Local::new(stale.regenerate_display(), "p");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(*p).field", "u8");
+}
+
+#[test]
+fn index_on_non_indexable() {
+    let u8 = Type::new_generic("u8");
+    let p = Local::new(shared_ref(&slice(&u8)), "p");
+    let mut e = place_expr!(p[0]);
+    check(&mut e, "(*p)[0]", "u8");
+
+    let struct_ = Type::new_struct("Struct", [Field::new("data", slice(&u8))]);
+    let cases = [
+        (shared_ref(&struct_), "*p", NonIndexableKind::Struct),
+        (
+            Type::new_tuple([u8.clone(), u8.clone()]),
+            "p",
+            NonIndexableKind::Tuple,
+        ),
+        (
+            Type::new_enum("E", vec![("A".into(), vec![])]),
+            "p",
+            NonIndexableKind::Enum,
+        ),
+        (u8.clone(), "p", NonIndexableKind::Scalar),
+    ];
+    for (ty, place, expected) in cases {
+        let p = Local::new(ty, "p");
+        let mut e = place_expr!(p[0]);
+        match e.compute_ty() {
+            Err(PlaceTyError::IndexOnNonIndexable {
+                place: at, kind, ..
+            }) => {
+                assert_eq!(at.to_string(), place);
+                assert_eq!(kind, expected);
+            }
+            res => panic!("expected `IndexOnNonIndexable`, got {res:?}"),
+        }
+        assert!(matches!(e.context().last(), Some(Context::NotIndexable(_))));
+    }
+    let p = Local::new(u8, "p");
+    let mut e = place_expr!(p[0]);
+    check_err(
+        &mut e,
+        "the type of `p: u8` cannot be indexed and no indexable type is reachable through \
+         dereferences",
+    );
+    assert_eq!(
+        e.context().last().unwrap().to_string(),
+        "`u8` cannot be indexed and no indexable type is reachable through dereferences"
+    );
+}
+
+#[test]
+fn newtype() {
+    let f64 = Type::new_generic("f64");
+    let meters = Type::new_newtype("Meters", f64.clone());
+    let p = Local::new(shared_ref(&meters), "p");
+    let mut e = place_expr!(p.0);
+    check(&mut e, "(*p).0", "f64");
+    let mut e = place_expr!(*p);
+    check(&mut e, "*p", "Meters");
+    let mut e = place_expr!(p.value);
+    check_err(&mut e, "the type of `*p: Meters` has no field `value`");
+
+    let struct_ = Type::new_struct("Struct", [Field::new("field", f64)]);
+    let wrapper = Type::new_deref_newtype("Wrapper", struct_);
+    let p = Local::new(shared_ref(&wrapper), "p");
+    let mut e = place_expr!(p.0.field);
+    check(&mut e, "(*p).0.field", "f64");
+    let mut e = place_expr!(p.field);
+    check(&mut e, "(**p).field", "f64");
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn type_cache_across_threads() {
+    let shared = Type::new_generic("Shared");
+    let types = std::thread::scope(|s| {
+        let handles = (0..8)
+            .map(|i| {
+                let shared = shared.clone();
+                s.spawn(move || {
+                    let own = Type::new_generic(&format!("T{i}"));
+                    for _ in 0..100 {
+                        maybe_uninit(&shared);
+                        maybe_uninit(&own);
+                    }
+                    (maybe_uninit(&shared), maybe_uninit(&own))
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    for (i, (shared_mb, own_mb)) in types.iter().enumerate() {
+        assert!(*shared_mb == types[0].0);
+        assert_eq!(own_mb.to_string(), format!("MaybeUninit<T{i}>"));
+    }
+    assert!(TypeCache::global().len() >= 9);
+}
+
+#[test]
+fn incremental_matches_from_scratch() {
+    let outer = random_outer(|inner| {
+        vec![Field::new(
+            "b",
+            boxed(&maybe_uninit(&Type::new_ref(inner.clone()))),
+        )]
+    });
+    let p = Local::new(Type::new_mut_ref(outer), "p");
+    let i = Local::new(Type::new_generic("usize"), "i");
+
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..500 {
+        let mut place = IncrementalPlace::new(PlaceExpr::LocalVar(p.clone())).unwrap();
+        let mut written = vec![];
+        for _ in 0..12 {
+            if !written.is_empty() && rng.below(4) == 0 {
+                assert_eq!(place.pop_projection(), written.pop());
+            } else {
+                let proj = rng.projection(&["x", "y", "b"], &i);
+                let before = place.place().to_string();
+                if place.push_projection(proj.clone()).is_ok() {
+                    written.push(proj);
+                } else {
+                    assert_eq!(place.place().to_string(), before);
+                }
+            }
+            let mut scratch = written
+                .iter()
+                .fold(PlaceExpr::LocalVar(p.clone()), |e, proj| {
+                    apply(e, proj.clone())
+                });
+            let ty = scratch
+                .compute_ty_with_options(ComputeOptions::default())
+                .unwrap();
+            assert!(ty == *place.ty(), "{ty} != {}", place.ty());
+            assert_eq!(scratch.to_string(), place.place().to_string());
+        }
+    }
+}
+
+#[test]
+fn raw_ref_of_place() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("RawStruct", [Field::new("field", field.clone())]);
+    let p = Local::new(Type::new_ref(struct_.clone()), "p");
+    let mut e = ExprOfPlace::RawRef(Mutability::Not, *place_expr!(p.field));
+    assert_eq!(e.compute_ty().unwrap().to_string(), "*const Field");
+    assert_eq!(e.to_string(), "&raw const (*p).field");
+    assert_eq!(e.place().to_string(), "(*p).field");
+
+    let types = Map::from([
+        ("p".to_string(), Type::new_ref(struct_.clone())),
+        ("q".to_string(), maybe_uninit(&struct_)),
+    ]);
+    let mut parsed = ExprOfPlace::parse("&raw const (*p).field", &types).unwrap();
+    assert!(parsed.compute_ty().unwrap() == Type::new_raw_ptr(field.clone(), false));
+    assert_eq!(parsed.to_string(), e.to_string());
+
+    let mut e = ExprOfPlace::parse("&raw mut q.field", &types).unwrap();
+    assert_eq!(
+        e.compute_ty().unwrap().to_string(),
+        "*mut MaybeUninit<Field>"
+    );
+    assert_eq!(e.to_string(), "&raw mut (@%MaybeUninit (*q).field)");
+    let mut reparsed = ExprOfPlace::parse(&e.to_string(), &types).unwrap();
+    assert_eq!(
+        reparsed.compute_ty().unwrap().to_string(),
+        "*mut MaybeUninit<Field>"
+    );
+    assert_eq!(reparsed.to_string(), e.to_string());
+
+    assert!(ExprOfPlace::parse("&raw p", &types).is_err());
+    assert!(ExprOfPlace::parse("(*p).field", &types).is_err());
+    let mut e = ExprOfPlace::parse("&raw const p.missing", &types).unwrap();
+    assert!(matches!(
+        e.compute_ty(),
+        Err(PlaceTyError::UnknownField { .. })
+    ));
+}
+
+#[test]
+fn layout_of_composites() {
+    let u8 = Type::new_generic("u8").with_layout(Layout { size: 1, align: 1 });
+    let u32 = Type::new_generic("u32").with_layout(Layout { size: 4, align: 4 });
+    let pair = Type::new_struct(
+        "Pair",
+        [Field::new("a", u8.clone()), Field::new("b", u32.clone())],
+    );
+    assert_eq!(pair.layout(), Some(Layout { size: 8, align: 4 }));
+    let tail = Type::new_struct(
+        "Tail",
+        [Field::new("a", u32.clone()), Field::new("b", u8.clone())],
+    );
+    assert_eq!(tail.layout(), Some(Layout { size: 8, align: 4 }));
+
+    let array = Type::new_array(pair.clone(), 3);
+    assert_eq!(array.layout(), Some(Layout { size: 24, align: 4 }));
+    assert_eq!(Type::new_array(u8.clone(), 0).layout().unwrap().size, 0);
+    assert_eq!(
+        Type::new_array_generic(u8.clone(), ArrayLen::Generic("N".into())).layout(),
+        None
+    );
+
+    assert_eq!(Type::new_ref(pair.clone()).layout(), Some(Layout::POINTER));
+    let fat = Type::new_ref(Type::new_slice(u8.clone())).layout().unwrap();
+    assert_eq!(fat.size, 2 * Layout::POINTER.size);
+    assert_eq!(Type::new_slice(u8.clone()).layout(), None);
+
+    let generic = Type::new_generic("T");
+    assert_eq!(generic.layout(), None);
+    let partial = Type::new_struct("Partial", [Field::new("a", u8), Field::new("t", generic)]);
+    assert_eq!(partial.layout(), None);
+    assert_eq!(
+        Type::new_tuple([]).layout(),
+        Some(Layout { size: 0, align: 1 })
+    );
+}
+
+#[test]
+fn subst_keeps_layout() {
+    let layout = Layout { size: 8, align: 8 };
+    let t = Type::new_generic("T");
+    let opaque = Type::new_struct("Opaque", [Field::new("a", Type::new_generic("u8"))]);
+    let opaque = opaque.with_layout(layout);
+    let same = opaque.subst(&[("T", Type::new_generic("u16"))]);
+    assert!(same == opaque);
+    assert_eq!(same.layout(), Some(layout));
+
+    let generic = Type::new_struct("Generic<T>", [Field::new("t", t)]).with_layout(layout);
+    let concrete = generic.subst(&[("T", Type::new_generic("u16"))]);
+    assert_eq!(concrete.to_string(), "Generic<u16>");
+    assert_eq!(concrete.layout(), Some(layout));
+}
+
+#[test]
+fn transposed_wrapper_stack() {
+    let z = Type::new_generic("Z");
+    let guard = |inner: &Type| Type::wrapper("Guard", inner);
+    let p = Local::new(maybe_uninit(&guard(&z)), "p");
+
+    let (mbz, gz) = (maybe_uninit(&z), guard(&z));
+    let mut e = place_expr!(@%mbz @%gz **p);
+    let wrappers = Map::from([
+        ("MaybeUninit".to_string(), mbz.clone()),
+        ("Guard".to_string(), gz.clone()),
+    ]);
+    let mut named = place_expr!(in wrappers; @%MaybeUninit @%Guard **p);
+    for e in [&mut e, &mut named] {
+        check(e, "@%MaybeUninit @%Guard **p", "MaybeUninit<Guard<Z>>");
+    }
+
+    let mut e = place_expr!(in wrappers; @%Guard @%MaybeUninit **p);
+    check_err(
+        &mut e,
+        "the type of `**p: Z` is wrapped in `@%MaybeUninit<Z>`, but wrapping it results in \
+         `Guard<Z>`",
+    );
+    match e.compute_ty() {
+        Err(PlaceTyError::WrapperAnnotationMismatch {
+            expected, found, ..
+        }) => {
+            assert!(expected == gz);
+            assert_eq!(found.wrapper_name(), Some("MaybeUninit"));
+        }
+        res => panic!("expected `WrapperAnnotationMismatch`, got {res:?}"),
+    }
+}
+
+#[test]
+fn rebase_onto_other_local() {
+    let z = Type::new_generic("Z");
+    let x = Type::new_struct("RebaseX", [Field::new("z", z.clone())]);
+    let s = Type::new_struct("RebaseS", [Field::new("x", x.clone())]);
+    let p = Local::new(Type::new_ref(s.clone()), "p");
+    let q = Local::new(Type::new_mut_ref(s.clone()), "q");
+    let r = Local::new(s.clone(), "r");
+    let b = Local::new(boxed(&Type::new_ref(s.clone())), "b");
+
+    let mut e = place_expr!(p.x.z);
+    e.compute_ty().unwrap();
+    assert_eq!(e.root_local(), &p);
+    let rebased = e.rebase(q.clone()).unwrap();
+    assert_eq!(rebased.to_string(), "(*q).x.z");
+    assert_eq!(rebased.root_local(), &q);
+    assert!(rebased.ty().unwrap() == z);
+
+    let written = place_expr!(p.x.z);
+    assert_eq!(written.rebase(r.clone()).unwrap().to_string(), "r.x.z");
+    assert_eq!(written.rebase(b).unwrap().to_string(), "(**b).x.z");
+
+    match e.rebase(r) {
+        Err(PlaceTyError::DerefOnNonPointer { place, ty }) => {
+            assert_eq!(place.to_string(), "r");
+            assert!(ty == s);
+        }
+        res => panic!("expected `DerefOnNonPointer`, got {res:?}"),
+    }
+    let other = Local::new(x, "o");
+    assert!(matches!(
+        written.rebase(other),
+        Err(PlaceTyError::UnknownField { .. })
+    ));
+}
+
+#[test]
+fn write_access_through_refs() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let write = ComputeOptions {
+        access: Access::Write,
+        ..ComputeOptions::default()
+    };
+    let shared = Local::new(Type::new_ref(struct_.clone()), "p");
+    let unique = Local::new(Type::new_mut_ref(struct_.clone()), "p");
+    let nested = Local::new(Type::new_mut_ref(Type::new_ref(struct_.clone())), "p");
+
+    let p = unique.clone();
+    for mut e in [*place_expr!((*p).field), *place_expr!(p.field)] {
+        assert!(e.compute_ty_with_options(write.clone()).unwrap() == field);
+        assert_eq!(e.to_string(), "(*p).field");
+    }
+
+    let p = shared.clone();
+    for mut e in [*place_expr!((*p).field), *place_expr!(p.field)] {
+        assert!(
+            e.compute_ty_with_options(ComputeOptions::default())
+                .unwrap()
+                == field
+        );
+        let err = e.compute_ty_with_options(write.clone()).unwrap_err();
+        assert!(matches!(err, PlaceTyError::WriteThroughShared { .. }));
+        assert_eq!(
+            err.to_string(),
+            "the type of `p: &Struct` only allows reading through it, so `(*p).field` cannot be \
+             written to"
+        );
+    }
+
+    let p = nested;
+    let mut e = place_expr!(p.field);
+    match e.compute_ty_with_options(write) {
+        Err(PlaceTyError::WriteThroughShared { place, ty, written }) => {
+            assert_eq!(place.to_string(), "*p");
+            assert!(ty == Type::new_ref(struct_));
+            assert_eq!(written.to_string(), "(**p).field");
+        }
+        res => panic!("expected `WriteThroughShared`, got {res:?}"),
+    }
+}
+
+#[test]
+fn wrapper_registry() {
+    let registry = WrapperRegistry::standard();
+    assert_eq!(
+        registry.names(),
+        ["Arc", "Box", "ManuallyDrop", "MaybeUninit", "Pin", "Rc"]
+    );
+    assert_eq!(
+        registry.behavior("MaybeUninit"),
+        Some(WrapperBehavior::Lifting)
+    );
+    assert_eq!(registry.behavior("Rc"), Some(WrapperBehavior::Transparent));
+    assert_eq!(registry.behavior("Vec"), None);
+
+    let z = Type::new_generic("Z");
+    let mbz = registry.wrap("MaybeUninit", &z).unwrap();
+    assert!(mbz == maybe_uninit(&z));
+    assert!(mbz.is_lifting_wrapper());
+    let rc = registry.wrap("Rc", &z).unwrap();
+    assert_eq!(rc.to_string(), "Rc<Z>");
+    assert!(rc.is_dereferenceable() && !rc.is_lifting_wrapper());
+    let pin = registry.wrap("Pin", &Type::new_mut_ref(z.clone())).unwrap();
+    assert_eq!(pin.to_string(), "Pin<&mut Z>");
+    assert!(registry.wrap("Vec", &z).is_none());
+
+    let s = Type::new_struct("RegS", [Field::new("z", z.clone())]);
+    let locals = Map::from([("p".to_string(), Type::wrapper("Guard", &s))]);
+    let mut custom = WrapperRegistry::new();
+    custom.register("Guard", WrapperBehavior::Lifting, |inner| {
+        Type::wrapper("Guard", inner)
+    });
+    let mut e = PlaceExpr::parse_with_registry("@%Guard (*p).z", &locals, &custom).unwrap();
+    check(&mut e, "@%Guard (*p).z", "Guard<Z>");
+    let mut e = PlaceExpr::parse("@%MaybeUninit (*p).z", &locals).unwrap();
+    check_err(
+        &mut e,
+        "the type of `(*p).z: Z` is wrapped in `@%MaybeUninit<T>`, but wrapping it results in \
+         `Guard<Z>`",
+    );
+}
+
+#[test]
+fn registry_checks_wrapper_behavior() {
+    let z = Type::new_generic("Z");
+    let s = Type::new_struct("BehS", [Field::new("z", z.clone())]);
+    let lifting_box = Type::wrapper("Box", &s);
+    let p = Local::new(lifting_box.clone(), "p");
+    let err = "the type of `(*p).z: Z` is wrapped in `@%Box`, but `Box` is registered as a \
+               transparent wrapper, which is dereferenced instead";
+    check_err(&mut place_expr!(p.z), err);
+    check_err(&mut place_expr!(@%lifting_box (*p).z), err);
+
+    let mut registry = WrapperRegistry::standard();
+    registry.register("Box", WrapperBehavior::Lifting, |inner| {
+        Type::wrapper("Box", inner)
+    });
+    let options = ComputeOptions {
+        registry: Arc::new(registry),
+        ..ComputeOptions::default()
+    };
+    let mut e = place_expr!(p.z);
+    let ty = e.compute_ty_with_options(options.clone()).unwrap();
+    assert_eq!(format!("{e}: {ty}"), "@%Box (*p).z: Box<Z>");
+    assert_ne!(options, ComputeOptions::default());
+    assert_eq!(ComputeOptions::default(), ComputeOptions::default());
+}
+
+#[test]
+fn lift_over_wrapped_field() {
+    // Projecting to a field that is itself a place wrapper lifts the outer wrapper over it
+    // without collapsing the two, even for idempotent wrappers.
+    let z = Type::new_generic("Z");
+    let s = Type::new_struct("LiftS", [Field::new("f", maybe_uninit(&z))]);
+    let p = Local::new(maybe_uninit(&s), "p");
+    let mut e = place_expr!(p.f);
+    check(
+        &mut e,
+        "@%MaybeUninit (*p).f",
+        "MaybeUninit<MaybeUninit<Z>>",
+    );
+    assert!(e.ty().unwrap() == maybe_uninit(&maybe_uninit(&z)));
+
+    let inner = Type::new_struct("LiftInner", [Field::new("x", z.clone())]);
+    let s = Type::new_struct("LiftS2", [Field::new("f", maybe_uninit(&inner))]);
+    let p = Local::new(maybe_uninit(&s), "p");
+    let mut e = place_expr!(p.f.x);
+    check(
+        &mut e,
+        "@%MaybeUninit @%MaybeUninit (*(*p).f).x",
+        "MaybeUninit<MaybeUninit<Z>>",
+    );
+
+    let once = |inner: &Type| Type::idempotent_wrapper("Once", inner);
+    let s = Type::new_struct("LiftT", [Field::new("f", once(&z))]);
+    let p = Local::new(once(&s), "p");
+    let mut e = place_expr!(p.f);
+    check(&mut e, "@%Once (*p).f", "Once<Once<Z>>");
+    assert!(e.ty().unwrap().simplify_wrappers() == once(&z));
+}
+
+#[test]
+fn iterate_fields() {
+    let z = Type::new_generic("Z");
+    let y = Type::new_struct("Y", [Field::new("z", maybe_uninit(&z))]);
+    let x = Type::new_struct("X", [Field::new("y", maybe_uninit(&y))]);
+    let e = Type::new_struct("E", [Field::new("x", maybe_uninit(&x))]);
+    for (ty, name, field_ty) in [(&e, "x", &x), (&x, "y", &y), (&y, "z", &z)] {
+        let fields = ty.fields().collect::<Vec<_>>();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].0, name);
+        assert!(*fields[0].1 == maybe_uninit(field_ty));
+        assert!(ty.field(name) == Some(fields[0].1));
+        assert!(ty.field("w").is_none());
+    }
+
+    let names = ["zeta", "alpha", "mid"];
+    let s = Type::new_struct("Ordered", names.map(|name| Field::new(name, z.clone())));
+    assert_eq!(s.fields().map(|(name, _)| name).collect::<Vec<_>>(), names);
+    let tuple = Type::new_tuple([z.clone(), x.clone()]);
+    let elems = tuple.fields().collect::<Vec<_>>();
+    assert_eq!(elems.len(), 2);
+    assert!(elems[0] == ("0", &z) && elems[1] == ("1", &x));
+    assert_eq!(z.fields().count(), 0);
+}
+
+#[test]
+fn value_cell_projection() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let cell = Type::new_value_cell("Cell", "get", struct_.clone());
+    let p = Local::new(Type::new_ref(cell), "p");
+    let mut e = place_expr!(p.get);
+    assert_eq!(
+        e.compute_projected().unwrap(),
+        Projected::Value(struct_.clone())
+    );
+    assert_eq!(e.to_string(), "(*p).get");
+
+    let mut e = place_expr!(p.get.field);
+    let err = e.compute_ty().unwrap_err();
+    assert!(matches!(err, PlaceTyError::ProjectionOnValue { .. }));
+    assert_eq!(
+        err.to_string(),
+        "the type of `(*p).get: Struct` is a value read out of a cell, not a place, so it can only \
+         be dereferenced"
+    );
+    let mb = maybe_uninit(&Type::new_generic("T"));
+    let mut e = place_expr!(@%mb (*p).get);
+    assert!(matches!(
+        e.compute_ty(),
+        Err(PlaceTyError::ProjectionOnValue { .. })
+    ));
+
+    let ref_cell = Type::new_value_cell("Cell", "get", Type::new_ref(struct_.clone()));
+    let q = Local::new(ref_cell, "q");
+    let mut e = place_expr!((*q.get).field);
+    assert_eq!(
+        e.compute_projected().unwrap(),
+        Projected::Place(field.clone())
+    );
+    let mut e = place_expr!(q.get.field);
+    assert!(e.compute_ty().is_err());
+
+    let unsafe_cell = Type::new_cell("UnsafeCell", "value", struct_.clone());
+    let u = Local::new(unsafe_cell, "u");
+    let mut e = place_expr!(u.value.field);
+    assert_eq!(e.compute_projected().unwrap(), Projected::Place(field));
+}
+
+#[test]
+fn diff_desugarings() {
+    let field = Type::new_generic("Field");
+    let x = Type::new_struct("X", [Field::new("y", field.clone())]);
+    let s = Type::new_struct("S", [Field::new("x", maybe_uninit(&x))]);
+    let p = Local::new(shared_ref(&s), "p");
+    let mut e = place_expr!(p.x.y);
+    check(&mut e, "@%MaybeUninit (*(*p).x).y", "MaybeUninit<Field>");
+    let expected = place_expr!((**(*p).x).y);
+    let diff = e.diff(&expected);
+    assert_eq!(
+        diff,
+        [
+            ProjectionDiff::Same(PlaceProjection::Deref),
+            ProjectionDiff::Same(PlaceProjection::Field("x".into())),
+            ProjectionDiff::Same(PlaceProjection::Deref),
+            ProjectionDiff::Missing(PlaceProjection::Deref),
+            ProjectionDiff::Same(PlaceProjection::Field("y".into())),
+            ProjectionDiff::Extra(PlaceProjection::Wrap(maybe_uninit(&x))),
+        ]
+    );
+    let lines = diff.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        ["  *", "  .x", "  *", "- *", "  .y", "+ @%MaybeUninit"]
+    );
+
+    let mb = maybe_uninit(&Type::new_generic("T"));
+    let expected = place_expr!(@%mb (*(*p).x).y);
+    assert!(
+        e.diff(&expected)
+            .iter()
+            .all(|diff| matches!(diff, ProjectionDiff::Same(_)))
+    );
+
+    let q = Local::new(shared_ref(&s), "q");
+    let mut expected = place_expr!(q.x.y);
+    expected.compute_ty().unwrap();
+    let diff = e.diff(&expected);
+    assert_eq!(diff[0], ProjectionDiff::Local(p.clone(), q.clone()));
+    assert_eq!(diff[0].to_string(), "- q\n+ p");
+    assert!(
+        diff[1..]
+            .iter()
+            .all(|diff| matches!(diff, ProjectionDiff::Same(_)))
+    );
+}
+
+#[test]
+fn offset_of_nested_fields() {
+    let u8 = Type::new_generic("u8").with_layout(Layout { size: 1, align: 1 });
+    let u16 = Type::new_generic("u16").with_layout(Layout { size: 2, align: 2 });
+    let u64 = Type::new_generic("u64").with_layout(Layout { size: 8, align: 8 });
+    let z = Type::new_struct(
+        "Z",
+        [Field::new("tag", u8.clone()), Field::new("z", u16.clone())],
+    );
+    let y = Type::new_struct(
+        "Y",
+        [Field::new("flag", u8.clone()), Field::new("y", z.clone())],
+    );
+    let x = Type::new_struct(
+        "X",
+        [
+            Field::new("len", u64.clone()),
+            Field::new("head", u8.clone()),
+            Field::new("x", y.clone()),
+        ],
+    );
+    assert_eq!(x.offset_of(&[]), Some(0));
+    assert_eq!(x.offset_of(&["len"]), Some(0));
+    assert_eq!(x.offset_of(&["x"]), Some(10));
+    assert_eq!(x.offset_of(&["x", "y"]), Some(12));
+    assert_eq!(x.offset_of(&["x", "y", "z"]), Some(14));
+    assert_eq!(x.offset_of(&["x", "missing"]), None);
+
+    let packed = Type::new_struct(
+        "Packed",
+        [
+            Field::new_full("a", u8.clone(), Visibility::Public, Some(0)),
+            Field::new_full("b", u64.clone(), Visibility::Public, Some(1)),
+        ],
+    );
+    assert_eq!(packed.offset_of(&["b"]), Some(1));
+    let tuple = Type::new_tuple([u8.clone(), z.clone()]);
+    assert_eq!(tuple.offset_of(&["1", "z"]), Some(4));
+
+    let behind_ref = Type::new_struct("R", [Field::new("r", Type::new_ref(y.clone()))]);
+    assert_eq!(behind_ref.offset_of(&["r"]), Some(0));
+    assert_eq!(behind_ref.offset_of(&["r", "y"]), None);
+    let unknown = Type::new_struct(
+        "U",
+        [
+            Field::new("t", Type::new_generic("T")),
+            Field::new("x", x.clone()),
+        ],
+    );
+    assert_eq!(unknown.offset_of(&["x"]), None);
+}
+
+#[test]
+fn interpolated_local_index() {
+    let t = Type::new_generic("T");
+    let inner = Type::new_struct("Inner", [Field::new("g", t.clone())]);
+    let outer = Type::new_struct(
+        "Outer",
+        [Field::new("a", Type::new_array(Type::new_ref(inner), 2))],
+    );
+    let p = Local::new(shared_ref(&outer), "p");
+    let indices = [
+        Local::new(Type::new_generic("usize"), "i"),
+        Local::new(Type::new_generic("u8"), "j"),
+    ];
+
+    let mut e = place_expr!(p.a[#indices[0]].g);
+    check(&mut e, "(*(*p).a[i]).g", "T");
+    let i = &indices[0];
+    assert_eq!(place_expr!(p.a[#i]), place_expr!(p.a[#indices[0]]));
+    assert!(matches!(
+        *place_expr!(p.a[#i]),
+        PlaceExpr::LocalIndex(_, ref local) if *local == indices[0]
+    ));
+
+    let mut e = place_expr!(p.a[#indices[1]]);
+    let err = e.compute_ty().unwrap_err();
+    assert!(matches!(err, PlaceTyError::IndexNotUsize { .. }));
+    assert_eq!(
+        err.to_string(),
+        "the type of `j: u8` cannot be used as an index, it should be `usize`"
+    );
+}
+
+#[test]
+fn projections_on_never() {
+    let never = Type::new_never();
+    assert_eq!(never.to_string(), "!");
+    assert_eq!(never, Type::new_never());
+    assert_eq!(never.layout(), Some(Layout { size: 0, align: 1 }));
+    let p = Local::new(never.clone(), "p");
+
+    let mut e = place_expr!(p.field);
+    check(&mut e, "p.field", "!");
+    let mut e = place_expr!(p.field[0].0);
+    check(&mut e, "p.field[0].0", "!");
+    assert_eq!(e.ty(), Some(never.clone()));
+    let mut e = place_expr!(*p);
+    check(&mut e, "*p", "!");
+    let mut e = place_expr!((p as Some).0);
+    check(&mut e, "(p as Some).0", "!");
+
+    let s = Type::new_struct("S", [Field::new("diverges", never.clone())]);
+    let q = Local::new(shared_ref(&s), "q");
+    let mut e = place_expr!(q.diverges.field);
+    check(&mut e, "(*q).diverges.field", "!");
+    assert_eq!(never.subst(&[("T", s)]), never);
+}
+
+#[test]
+fn projections_through_dyn() {
+    let dyn_trait = Type::new_dyn("Trait");
+    assert_eq!(dyn_trait.to_string(), "dyn Trait");
+    assert_eq!(dyn_trait.layout(), None);
+    let p = Local::new(Type::new_ref(dyn_trait.clone()), "p");
+    assert_eq!(p.ty().to_string(), "&dyn Trait");
+    assert_eq!(p.ty().layout().unwrap().size, 2 * Layout::POINTER.size);
+
+    let mut e = place_expr!(*p);
+    check(&mut e, "*p", "dyn Trait");
+
+    let mut e = place_expr!((*p).field);
+    let err = e.compute_ty().unwrap_err();
+    assert!(matches!(
+        err,
+        PlaceTyError::FieldOnUnsized { ref projection, .. } if projection == ".field"
+    ));
+    assert_eq!(
+        err.to_string(),
+        "the type of `*p: dyn Trait` is an unsized trait object, so its contents are unknown and \
+         `.field` cannot be projected from it"
+    );
+    let mut e = place_expr!(p.field);
+    assert!(matches!(
+        e.compute_ty(),
+        Err(PlaceTyError::FieldOnUnsized { .. })
+    ));
+    let mut e = place_expr!(p[0]);
+    assert!(matches!(
+        e.compute_ty(),
+        Err(PlaceTyError::FieldOnUnsized { ref projection, .. }) if projection == "[0]"
+    ));
+
+    let q = Local::new(Type::new_generic("T"), "q");
+    let mut e = place_expr!(q.field);
+    assert!(matches!(
+        e.compute_ty(),
+        Err(PlaceTyError::FieldOnNonStruct { .. })
+    ));
+}
+
+#[test]
+fn wrapper_explanation_in_context() {
+    let field = Type::new_generic("Field");
+    let s = Type::new_struct("S", [Field::new("field", field.clone())]);
+    let note = "lifted `Tracked` because every access to the outer value is recorded";
+    let tracked = Type::wrapper("Tracked", &s).with_explanation(note);
+    assert_eq!(tracked.explanation(), Some(note));
+    assert_eq!(tracked, Type::wrapper("Tracked", &s));
+    let p = Local::new(shared_ref(&tracked), "p");
+
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty().unwrap();
+    assert_eq!(e.to_string(), "@%Tracked (**p).field");
+    assert_eq!(ty.explanation(), Some(note));
+    let context = e.context();
+    assert_eq!(
+        context.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        [
+            "p: &Tracked<S>".to_string(),
+            "deref of `&Tracked<S>`".to_string(),
+            "deref of `Tracked<S>` (via `Tracked`)".to_string(),
+            format!("note: {note}"),
+            "S.field: Field".to_string(),
+            "lift of place wrapper `Tracked`".to_string(),
+            format!("note: {note}"),
+        ]
+    );
+    assert!(matches!(
+        &context[3],
+        Context::Explanation(wrapper, explanation) if *wrapper == tracked && explanation == note
+    ));
+
+    let built = Type::builder()
+        .target(s.clone())
+        .display("Logged<S>")
+        .wrapper("Logged", |inner| Type::wrapper("Logged", &inner))
+        .transparent()
+        .explanation("dereferenced `Logged` since it forwards to its contents")
+        .build()
+        .unwrap();
+    let q = Local::new(built, "q");
+    let mut e = place_expr!(q.field);
+    e.compute_ty().unwrap();
+    assert!(
+        e.context().iter().any(|ctx| ctx.to_string()
+            == "note: dereferenced `Logged` since it forwards to its contents")
+    );
+}
+
+#[test]
+fn cached_wrapper_keeps_explanation_of_argument() {
+    let s = Type::new_struct("S", [Field::new("field", Type::new_generic("Field"))]);
+    let noted = Type::transparent_wrapper("Bx", &Type::wrapper("W", &s).with_explanation("note"));
+    let plain = Type::transparent_wrapper("Bx", &Type::wrapper("W", &s));
+    assert!(noted == plain);
+    assert_eq!(noted.deref_target().unwrap().explanation(), Some("note"));
+    assert_eq!(plain.deref_target().unwrap().explanation(), None);
+
+    let p = Local::new(plain, "p");
+    let mut e = place_expr!(p.field);
+    e.compute_ty().unwrap();
+    assert!(
+        e.context()
+            .iter()
+            .all(|ctx| !matches!(ctx, Context::Explanation(..)))
+    );
+}
+
+#[test]
+fn canonical_paths() {
+    let t = Type::new_generic("T");
+    let x = Type::new_struct(
+        "X",
+        [
+            Field::new("x", maybe_uninit(&t)),
+            Field::new("a", Type::new_array(t.clone(), 4)),
+        ],
+    );
+    let p = Local::new(shared_ref(&x), "p");
+    let i = Local::new(Type::new_generic("usize"), "i");
+
+    let path = place_expr!(p.x).canonical_path();
+    assert_eq!(path, "local:p/deref/field:x");
+    assert_eq!(place_expr!((*p).x).canonical_path(), path);
+    let mb = maybe_uninit(&t);
+    assert_eq!(place_expr!(@%mb (*p).x).canonical_path(), path);
+    assert_ne!(place_expr!(p.a).canonical_path(), path);
+
+    assert_eq!(
+        place_expr!(p.a[i]).canonical_path(),
+        "local:p/deref/field:a/index:local:i"
+    );
+    assert_eq!(
+        place_expr!(p.a[^1]).canonical_path(),
+        place_expr!((*p).a[3]).canonical_path()
+    );
+    assert_eq!(
+        place_expr!(p.a[1..]).canonical_path(),
+        "local:p/deref/field:a/range:1.."
+    );
+    assert_eq!(
+        place_expr!(p.missing).canonical_path(),
+        "local:p/deref/field:missing"
+    );
+
+    let mut paths = Map::new();
+    paths.insert(place_expr!(p.x).canonical_path(), 1);
+    assert_eq!(paths.get(&place_expr!((*p).x).canonical_path()), Some(&1));
+}
+
+#[test]
+fn deref_generic_with_bound() {
+    let u = Type::new_struct("U", [Field::new("field", Type::new_generic("F"))]);
+    let p = Local::new(Type::new_generic("T"), "p");
+    let mut generics = GenericEnv::new();
+    generics.add_deref_bound("T", u.clone());
+    assert_eq!(generics.deref_bound("T"), Some(&u));
+    assert_eq!(generics.deref_bound("U"), None);
+    let options = ComputeOptions {
+        generics,
+        ..ComputeOptions::default()
+    };
+
+    let mut e = place_expr!(*p);
+    assert_eq!(e.compute_ty_with_options(options.clone()).unwrap(), u);
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty_with_options(options.clone()).unwrap();
+    assert_eq!(ty.to_string(), "F");
+    assert_eq!(e.to_string(), "(*p).field");
+
+    let mut e = place_expr!(*p);
+    assert!(matches!(
+        e.compute_ty(),
+        Err(PlaceTyError::DerefOnNonPointer { .. })
+    ));
+    let q = Local::new(Type::new_generic("Q"), "q");
+    let mut e = place_expr!(*q);
+    assert!(matches!(
+        e.compute_ty_with_options(options),
+        Err(PlaceTyError::DerefOnNonPointer { .. })
+    ));
+}
+
+#[test]
+fn deref_generic_with_bound_keeps_target() {
+    let f = Type::new_generic("F");
+    let u = Type::new_struct("U", [Field::new("field", f.clone())]);
+    let t = Type::new_generic("T");
+    let p = Local::new(t.clone(), "p");
+    let mut generics = GenericEnv::new();
+    generics.add_deref_bound("T", u.clone());
+    let options = ComputeOptions {
+        generics,
+        ..ComputeOptions::default()
+    };
+
+    let mut e = place_expr!(p.field);
+    let ty = e.compute_ty_with_options(options).unwrap();
+    assert_eq!(format!("{e:#}"), "(*⟨bound⟩p).field");
+    assert!(*e == *place_expr!((*p).field));
+    assert_eq!(e.ty(), Some(ty.clone()));
+    assert_eq!(e.types_along_chain(), [t.clone(), u.clone(), f.clone()]);
+    assert_eq!(e.deref_chain(), [(t, u)]);
+
+    // The desugared place no longer needs the bound.
+    assert!(e.compute_ty().unwrap() == f);
+    assert_eq!(format!("{e:#}"), "(*⟨bound⟩p).field");
+    // It is not mistaken for the same place written without the bound.
+    assert!(matches!(
+        place_expr!((*p).field).compute_ty(),
+        Err(PlaceTyError::DerefOnNonPointer { .. })
+    ));
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&e).unwrap();
+        let de: Box<PlaceExpr> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.ty(), Some(ty));
+    }
+}
+
+#[test]
+fn well_formed_places() {
+    let t = Type::new_generic("T");
+    let p = Local::new(shared_ref(&t), "p");
+    let mb = maybe_uninit(&t);
+    let local = || Box::new(PlaceExpr::LocalVar(p.clone()));
+
+    assert_eq!(
+        place_expr!(@%mb (*p).x.0[1][^1][1..2] as V).well_formed(),
+        Ok(())
+    );
+    let i = Local::new(Type::new_generic("usize"), "i");
+    assert_eq!(place_expr!(p[i]).well_formed(), Ok(()));
+    // A place that does not type-check can still be well-formed.
+    assert_eq!(place_expr!(p.missing).well_formed(), Ok(()));
+
+    let e = PlaceExpr::FieldAccess(local(), "a b".into());
+    let err = e.well_formed().unwrap_err();
+    assert!(matches!(err, MalformedPlace::InvalidField { ref field, .. } if field == "a b"));
+    assert_eq!(
+        err.to_string(),
+        "the place expression `p.a b` accesses the field `a b`, which is neither an identifier nor \
+         a tuple index"
+    );
+    let e = PlaceExpr::FieldAccess(local(), String::new());
+    assert!(matches!(
+        e.well_formed(),
+        Err(MalformedPlace::InvalidField { .. })
+    ));
+
+    let e = PlaceExpr::Index(local(), Expr("^0".into()));
+    let err = e.well_formed().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "the place expression `p[^0]` has the index `^0`, which is never in bounds"
+    );
+    let e = PlaceExpr::Index(local(), Expr(" ".into()));
+    assert_eq!(
+        e.well_formed().unwrap_err().to_string(),
+        "the place expression `p[ ]` has an empty index"
+    );
+
+    let e = PlaceExpr::LocalIndex(local(), Local::new_temp(Type::new_generic("usize")));
+    assert!(matches!(
+        e.well_formed(),
+        Err(MalformedPlace::TempIndex { .. })
+    ));
+    let e = place_expr!(p[3..1]);
+    assert!(matches!(
+        e.well_formed(),
+        Err(MalformedPlace::ReversedRange {
+            start: 3,
+            end: 1,
+            ..
+        })
+    ));
+    let e = PlaceExpr::Downcast(local(), "1V".into());
+    assert!(matches!(
+        e.well_formed(),
+        Err(MalformedPlace::InvalidVariant { .. })
+    ));
+
+    let b = boxed(&t);
+    let inner = PlaceExpr::Wrap(local(), b.clone());
+    let e = PlaceExpr::FieldAccess(Box::new(inner.clone()), "a b".into());
+    let err = e.well_formed().unwrap_err();
+    assert_eq!(
+        err,
+        MalformedPlace::NotAPlaceWrapper {
+            place: inner,
+            wrapper: b
+        }
+    );
+    assert_eq!(err.place().to_string(), "@%Box p");
+}
+
+#[test]
+fn sort_blog_places() {
+    let field = Type::new_generic("Field");
+    let struct_ = Type::new_struct("Struct", vec![Field::new("field", field.clone())]);
+    let mb_struct = maybe_uninit(&struct_);
+    let blog1 = Local::new(mb_struct.clone(), "p");
+    let blog2 = Local::new(maybe_uninit(&mb_struct), "p");
+    let blog3 = Local::new(shared_ref(&shared_ref(&shared_ref(&mb_struct))), "p");
+    let blog4 = Local::new(maybe_uninit(&shared_ref(&struct_)), "p");
+    let blog5 = Local::new(maybe_uninit(&slice(&Type::new_generic("u8"))), "p");
+    let q = Local::new(shared_ref(&struct_), "q");
+
+    let desugared = |mut place: PlaceExpr| {
+        place.compute_ty().unwrap();
+        place
+    };
+    let (p1, p2, p3, p4, p5) = (&blog1, &blog2, &blog3, &blog4, &blog5);
+    let mut places = vec![
+        *place_expr!(q.field),
+        desugared(*place_expr!(q.field)),
+        *place_expr!(p1.field),
+        desugared(*place_expr!(p1.field)),
+        desugared(*place_expr!(p2.field)),
+        desugared(*place_expr!(p3.field)),
+        desugared(*place_expr!(p4.field)),
+        desugared(*place_expr!(p5[42])),
+        *place_expr!(p1),
+        desugared(*place_expr!((*q).field)),
+    ];
+    places.sort();
+    let sorted = places.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        sorted,
+        [
+            "p",
+            "@%MaybeUninit (****p).field",
+            "(**p).field",
+            "@%MaybeUninit @%MaybeUninit (**p).field",
+            "@%MaybeUninit (*p).field",
+            "p.field",
+            "@%MaybeUninit (*p)[42]",
+            "(*q).field",
+            "(*q).field",
+            "q.field",
+        ]
+    );
+    assert_eq!(places[7], places[8]);
+    assert_ne!(places[8], places[9]);
+    let (written, explicit) = (*place_expr!(q.field), *place_expr!((*q).field));
+    for place in &places {
+        if place.canonical_path() != written.canonical_path() {
+            assert_eq!(written.cmp(place), explicit.cmp(place), "{place}");
+        }
+    }
+    places.reverse();
+    places.sort();
+    assert_eq!(
+        places.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        sorted
+    );
+}